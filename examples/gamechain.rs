@@ -4,7 +4,7 @@
 use onft::prelude::*;
 use std::io::{self, Read};
 use std::net::{TcpListener, TcpStream};
-use std::{convert::TryInto, fmt, ops::Range};
+use std::{convert::TryInto, error::Error, fmt, ops::Range};
 
 const BIND_ADDR: &str = "0.0.0.0:8080";
 
@@ -17,21 +17,21 @@ fn main() -> io::Result<()> {
     for stream in listener.incoming() {
         match handle_stream(&mut chain, stream?) {
             Ok(game_report) => println!("{}", game_report),
-            Err(_) => continue,
+            Err(err) => eprintln!("Couldn't handle stream: {}", err),
         }
     }
     Ok(())
 }
 
 /// Handles an incoming tcp stream and returns a game report if successful
-fn handle_stream(chain: &mut Chain, mut stream: TcpStream) -> Result<GameReport, ()> {
+fn handle_stream(chain: &mut Chain, mut stream: TcpStream) -> Result<GameReport, Box<dyn Error>> {
     // make packet then read
     let mut packet = [0; GameReport::PACKET_LEN];
-    stream.read(&mut packet).map_err(|_| ())?;
+    stream.read_exact(&mut packet)?;
 
     // deserialize packet then add block
-    let game_report = GameReport::from_packet(packet)?;
-    chain.push_data(&packet[..]).map_err(|_| ())?;
+    let game_report = GameReport::from_packet(packet);
+    chain.push_data(&packet[..])?;
 
     Ok(game_report)
 }
@@ -48,18 +48,18 @@ impl GameReport {
     const PACKET_LEN: usize = 2 + 2 + 1;
 
     /// Creates new game report from inputted `packet` array
-    fn from_packet(packet: [u8; Self::PACKET_LEN]) -> Result<Self, ()> {
+    fn from_packet(packet: [u8; Self::PACKET_LEN]) -> Self {
         let get_u16 = |range: Range<usize>| u16::from_be_bytes(packet[range].try_into().unwrap());
 
         let game_id = get_u16(0..2);
         let user_id = get_u16(2..4);
         let won = packet[4] == 255;
 
-        Ok(Self {
+        Self {
             game_id,
             user_id,
             won,
-        })
+        }
     }
 }
 
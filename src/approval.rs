@@ -0,0 +1,287 @@
+//! Contains [Approval] and implementations
+
+use crate::{error::Error, BlockKind, Chain, Ledger, Result, TokenId};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+use std::convert::TryInto;
+
+/// Permission for a delegate key to transfer tokens on behalf of their owner
+///
+/// # Using
+///
+/// Owners can approve a delegate, such as a marketplace, to transfer either a
+/// single token or every token they own, without handing over their keypair.
+/// Approvals of a single token are cleared once it changes hands, whilst
+/// approvals of every token, making the delegate an operator, last until the
+/// owner revokes them. Delegates can only transfer tokens and can't burn,
+/// update, lock or approve them. You can, in high level terms, do the
+/// following:
+///
+/// - Approve a delegate for one token: [Chain::approve]
+/// - Approve a delegate for every token: [Chain::approve_all]
+/// - Revoke an approval: [Chain::revoke] and [Chain::revoke_all]
+/// - Transfer as a delegate: [Chain::transfer]
+/// - Find who's approved: [Ledger::approved] and [Ledger::is_operator]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let owner = PKey::generate_ed25519().unwrap();
+///     let market = PKey::generate_ed25519().unwrap();
+///     let buyer = PKey::generate_ed25519().unwrap();
+///
+///     let mut chain = Chain::default();
+///     let token = chain.mint("Painting", &owner)?;
+///     chain.approve(&token, &owner, &market)?;
+///
+///     // the marketplace sells it on the owner's behalf
+///     chain.transfer(&token, &market, &buyer)?;
+///     assert!(chain.ledger().owner(&token)?.unwrap().public_eq(&buyer));
+///     assert!(chain.ledger().approved(&token)?.is_none());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Approval {
+    /// Public key of the approved delegate.
+    pub delegate: PKey<Public>,
+    /// Token the delegate is approved for, or [None] for every token.
+    pub token: Option<TokenId>,
+}
+
+impl Approval {
+    /// Creates a new approval of the `delegate` for a `token`, or for every token
+    /// if [None].
+    pub fn new(delegate: &PKeyRef<impl HasPublic>, token: Option<TokenId>) -> Result<Self> {
+        let raw = delegate.raw_public_key().map_err(Error::KeyPublic)?;
+        Ok(Self {
+            delegate: PKey::public_key_from_raw_bytes(&raw, Id::ED25519)
+                .map_err(Error::KeyRawPublic)?,
+            token,
+        })
+    }
+
+    /// Encodes this approval into the payload of an approve or revoke block.
+    pub(crate) fn to_payload(&self) -> Result<Vec<u8>> {
+        let mut bytes = self.delegate.raw_public_key().map_err(Error::KeyPublic)?;
+        if let Some(token) = &self.token {
+            bytes.extend_from_slice(&token.0[..]);
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes an approval from the payload of an approve or revoke block.
+    pub(crate) fn from_payload(payload: &[u8]) -> Result<Self> {
+        let token = match payload.len() {
+            32 => None,
+            64 => Some(TokenId(payload[32..].try_into().unwrap())),
+            _ => return Err(Error::InvalidPayload),
+        };
+        let delegate = PKey::public_key_from_raw_bytes(&payload[..32], Id::ED25519)
+            .map_err(|_| Error::InvalidPayload)?;
+        Ok(Self { delegate, token })
+    }
+}
+
+impl Ledger {
+    /// Gets the public key of the delegate approved to transfer a token, or
+    /// [None] if there isn't one or the token isn't known to this ledger.
+    pub fn approved(&self, token: &TokenId) -> Result<Option<PKey<Public>>> {
+        self.tokens
+            .get(token)
+            .and_then(|record| record.approved.as_ref())
+            .map(|raw| {
+                PKey::public_key_from_raw_bytes(raw, Id::ED25519).map_err(Error::KeyRawPublic)
+            })
+            .transpose()
+    }
+
+    /// Checks if the `delegate` is approved to transfer every token the `owner`
+    /// holds.
+    pub fn is_operator(
+        &self,
+        owner: &PKeyRef<impl HasPublic>,
+        delegate: &PKeyRef<impl HasPublic>,
+    ) -> Result<bool> {
+        let owner = owner.raw_public_key().map_err(Error::KeyPublic)?;
+        let delegate = delegate.raw_public_key().map_err(Error::KeyPublic)?;
+        Ok(self.operators.contains(&(owner, delegate)))
+    }
+}
+
+impl Chain {
+    /// Approves the `delegate` to transfer the `token`, adding a new block signed
+    /// by its current `owner`; chainable method.
+    pub fn approve(
+        &mut self,
+        token: &TokenId,
+        owner: &PKey<Private>,
+        delegate: &PKeyRef<impl HasPublic>,
+    ) -> Result<&mut Self> {
+        self.check_owner(token, owner)?;
+        let approval = Approval::new(delegate, Some(token.clone()))?;
+        self.push_kind(&BlockKind::Approve(approval), owner)
+    }
+
+    /// Approves the `delegate` to transfer every token the `owner` holds, now or
+    /// later, adding a new block signed by the `owner`; chainable method.
+    pub fn approve_all(
+        &mut self,
+        owner: &PKey<Private>,
+        delegate: &PKeyRef<impl HasPublic>,
+    ) -> Result<&mut Self> {
+        self.push_kind(&BlockKind::Approve(Approval::new(delegate, None)?), owner)
+    }
+
+    /// Revokes the `delegate`'s approval to transfer the `token`, adding a new
+    /// block signed by its current `owner`; chainable method.
+    pub fn revoke(
+        &mut self,
+        token: &TokenId,
+        owner: &PKey<Private>,
+        delegate: &PKeyRef<impl HasPublic>,
+    ) -> Result<&mut Self> {
+        self.check_owner(token, owner)?;
+        if self.ledger.tokens[token].approved
+            != Some(delegate.raw_public_key().map_err(Error::KeyPublic)?)
+        {
+            return Err(Error::NotApproved);
+        }
+        let approval = Approval::new(delegate, Some(token.clone()))?;
+        self.push_kind(&BlockKind::Revoke(approval), owner)
+    }
+
+    /// Revokes the `delegate`'s approval to transfer every token the `owner`
+    /// holds, adding a new block signed by the `owner`; chainable method.
+    pub fn revoke_all(
+        &mut self,
+        owner: &PKey<Private>,
+        delegate: &PKeyRef<impl HasPublic>,
+    ) -> Result<&mut Self> {
+        if !self.ledger.is_operator(owner, delegate)? {
+            return Err(Error::NotApproved);
+        }
+        self.push_kind(&BlockKind::Revoke(Approval::new(delegate, None)?), owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_token_approvals() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let market = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("token", &alice).unwrap();
+        let other = chain.mint("other", &alice).unwrap();
+
+        assert!(matches!(
+            chain.transfer(&token, &market, &bob),
+            Err(Error::NotOwner)
+        ));
+        chain.approve(&token, &alice, &market).unwrap();
+        assert!(matches!(
+            chain.transfer(&other, &market, &bob),
+            Err(Error::NotOwner)
+        ));
+        assert!(matches!(chain.burn(&token, &market), Err(Error::NotOwner)));
+
+        chain.revoke(&token, &alice, &market).unwrap();
+        assert!(matches!(
+            chain.revoke(&token, &alice, &market),
+            Err(Error::NotApproved)
+        ));
+        chain.approve(&token, &alice, &market).unwrap();
+        chain.transfer(&token, &market, &bob).unwrap();
+
+        // approvals don't follow the token to its new owner
+        assert!(matches!(
+            chain.transfer(&token, &market, &alice),
+            Err(Error::NotOwner)
+        ));
+        assert!(chain.ledger().approved(&token).unwrap().is_none());
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn operators() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let market = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let first = chain.mint("first", &alice).unwrap();
+        chain.approve_all(&alice, &market).unwrap();
+        let second = chain.mint("second", &alice).unwrap();
+
+        assert!(chain.ledger().is_operator(&alice, &market).unwrap());
+        chain.transfer(&first, &market, &bob).unwrap();
+        chain.transfer(&second, &market, &bob).unwrap();
+        assert_eq!(chain.ledger().tokens_of(&bob).unwrap().len(), 2);
+
+        let third = chain.mint("third", &alice).unwrap();
+        chain.revoke_all(&alice, &market).unwrap();
+        assert!(matches!(
+            chain.revoke_all(&alice, &market),
+            Err(Error::NotApproved)
+        ));
+        assert!(matches!(
+            chain.transfer(&third, &market, &bob),
+            Err(Error::NotOwner)
+        ));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn approvals_after_rotation() {
+        let old = PKey::generate_ed25519().unwrap();
+        let new = PKey::generate_ed25519().unwrap();
+        let market = PKey::generate_ed25519().unwrap();
+        let relocated = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("token", &old).unwrap();
+        chain.approve(&token, &old, &market).unwrap();
+
+        // approvals granted by the old key are dropped along with it, and only
+        // the new key can grant them again
+        chain.rotate(&old, &new).unwrap();
+        assert!(chain.ledger().approved(&token).unwrap().is_none());
+        assert!(matches!(
+            chain.transfer(&token, &market, &bob),
+            Err(Error::NotOwner)
+        ));
+        assert!(matches!(
+            chain.revoke(&token, &new, &market),
+            Err(Error::NotApproved)
+        ));
+        assert!(matches!(
+            chain.approve(&token, &old, &bob),
+            Err(Error::NotOwner)
+        ));
+        chain.approve(&token, &new, &market).unwrap();
+
+        // approvals don't follow a delegate's rotation
+        chain.rotate(&market, &relocated).unwrap();
+        assert!(matches!(
+            chain.transfer(&token, &relocated, &bob),
+            Err(Error::NotOwner)
+        ));
+        chain.approve(&token, &new, &relocated).unwrap();
+        chain.transfer(&token, &relocated, &bob).unwrap();
+        assert!(chain
+            .ledger()
+            .owner(&token)
+            .unwrap()
+            .unwrap()
+            .public_eq(&bob));
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+}
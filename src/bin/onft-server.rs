@@ -0,0 +1,72 @@
+//! REST API server for a chain stored on disk
+//!
+//! Usage: `onft-server [chain path] [key path] [address]`, defaulting to
+//! `chain.jsonl`, `server.pem` and `127.0.0.1:8080` respectively. The server's
+//! keypair is generated and saved to the key path if it doesn't exist yet.
+//!
+//! A checkpoint is saved after verifying the chain on startup, so following
+//! startups only need to verify and replay blocks added since then.
+
+use onft::server::Server;
+use onft::Store;
+use openssl::pkey::{PKey, Private};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::{env, path::Path};
+use tokio::net::TcpListener;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let chain_path = args.next().unwrap_or_else(|| "chain.jsonl".to_string());
+    let key_path = args.next().unwrap_or_else(|| "server.pem".to_string());
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+
+    let keypair = load_keypair(Path::new(&key_path))?;
+    let (store, chain) = Store::open(&chain_path)?;
+    let verified = match store.load_checkpoint()? {
+        Some(checkpoint) if chain.validate_checkpoint(&checkpoint) => {
+            chain.verify_from(&checkpoint)?
+        }
+        _ => chain.verify()?,
+    };
+    if !verified {
+        return Err(format!("Chain stored at {} couldn't be verified", chain_path).into());
+    }
+    store.save_checkpoint(&chain.checkpoint(chain.blocks().len() - 1)?)?;
+
+    let listener = TcpListener::bind(&addr).await?;
+    println!(
+        "Serving {} on http://{}",
+        chain_path,
+        listener.local_addr()?
+    );
+    Server::new(chain, store, keypair).serve(listener).await?;
+    Ok(())
+}
+
+/// Loads the PEM-encoded keypair at `path`, generating and saving a new one if
+/// it doesn't exist yet. New keys are only readable by their owner where
+/// supported.
+fn load_keypair(path: &Path) -> Result<PKey<Private>, Box<dyn Error>> {
+    if path.exists() {
+        return Ok(PKey::private_key_from_pem(&fs::read(path)?)?);
+    }
+    let keypair = PKey::generate_ed25519()?;
+    create_private(path)?.write_all(&keypair.private_key_to_pem_pkcs8()?)?;
+    Ok(keypair)
+}
+
+/// Creates a new file at `path`, failing if it already exists, which is only
+/// readable by its owner on platforms supporting it.
+fn create_private(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
@@ -0,0 +1,225 @@
+//! Command-line tool for inspecting and manipulating chains stored on disk
+//!
+//! Every subcommand operates on the chain file given by `--chain`, which
+//! defaults to `chain.jsonl` and uses the same format as [onft::Store].
+
+use clap::{Parser, Subcommand};
+use onft::{from_hex, to_hex, Block, Chain, Hash, Ownership, Store, TokenId};
+use openssl::pkey::{Id, PKey, Private, Public};
+use serde_json::json;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+/// Inspect and manipulate onft chains stored on disk
+#[derive(Parser)]
+#[command(name = "onft", version)]
+struct Cli {
+    /// Path of the stored chain
+    #[arg(long, global = true, default_value = "chain.jsonl")]
+    chain: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Creates a new chain containing just the genesis block
+    Init,
+    /// Appends the contents of a file as a new block
+    Push {
+        /// File containing the block's data
+        file: PathBuf,
+        /// PEM private key to sign the block with, otherwise a throwaway key
+        #[arg(long)]
+        key: Option<PathBuf>,
+    },
+    /// Verifies the chain, reporting the first invalid block
+    Verify {
+        /// Truncates the chain to just before the first invalid block
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Shows a single block by its height or hash
+    Show {
+        /// Height or hexadecimal hash of the block
+        block: String,
+    },
+    /// Exports the entire chain
+    Export {
+        /// Exports as a JSON array instead of a summary of each block
+        #[arg(long)]
+        json: bool,
+    },
+    /// Generates a new keypair, printing its raw public key
+    Keygen {
+        /// Path to save the PEM private key to, readable only by its owner
+        out: PathBuf,
+    },
+    /// Lists every unburned token with the hash of its mint block and its owner
+    Tokens,
+    /// Permanently burns a token
+    Burn {
+        /// Hexadecimal identifier of the token, as listed by tokens
+        token: TokenId,
+        /// PEM private key of the token's current owner
+        #[arg(long)]
+        key: PathBuf,
+    },
+    /// Transfers ownership of a token to a new owner
+    Transfer {
+        /// Hexadecimal identifier of the token, as listed by tokens
+        token: TokenId,
+        /// PEM private key of the token's current owner
+        #[arg(long)]
+        key: PathBuf,
+        /// Hexadecimal raw public key of the new owner
+        #[arg(long)]
+        to: String,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(err) = run(cli) {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> CliResult<()> {
+    let path = &cli.chain;
+    match cli.command {
+        Command::Init => {
+            if path.exists() {
+                return Err(format!("{} already exists", path.display()).into());
+            }
+            Store::open(path)?;
+            println!("Created {}", path.display());
+        }
+        Command::Push { file, key } => {
+            let (mut store, mut chain) = open(path)?;
+            let data = fs::read(file)?;
+            match key {
+                Some(key) => chain.push_signed(data, &load_key(&key)?)?,
+                None => chain.push_data(data)?,
+            };
+            store.append(chain.tip())?;
+            println!("{}", chain.tip().hash);
+        }
+        Command::Verify { repair } => {
+            let (mut store, mut chain) = open(path)?;
+            match chain.first_invalid()? {
+                None => println!("Verified {} blocks", chain.blocks().len()),
+                Some(height) if repair => {
+                    store.save(chain.truncate(height)?)?;
+                    println!("Truncated chain to {} blocks", height);
+                }
+                Some(height) => return Err(format!("Block at height {} is invalid", height).into()),
+            }
+        }
+        Command::Show { block } => {
+            let (_, chain) = open(path)?;
+            let found = match block.parse::<usize>() {
+                Ok(height) => chain.blocks().get(height),
+                Err(_) => {
+                    let hash: Hash = block.parse()?;
+                    chain.blocks().iter().find(|block| block.hash == hash)
+                }
+            };
+            let found = found.ok_or(onft::error::Error::UnknownBlock)?;
+            println!("{}", serde_json::to_string_pretty(found)?);
+        }
+        Command::Export { json } => {
+            let (_, chain) = open(path)?;
+            if json {
+                // pruned chains need their snapshot to be loaded again
+                let exported = match chain.pruned() {
+                    Some(snapshot) => serde_json::to_string_pretty(
+                        &json!({ "snapshot": snapshot, "blocks": chain.blocks() }),
+                    )?,
+                    None => serde_json::to_string_pretty(&chain)?,
+                };
+                println!("{}", exported);
+            } else {
+                for (height, block) in chain.blocks().iter().enumerate() {
+                    println!("{}\t{}\t{}", height, block.hash, summary(block)?);
+                }
+            }
+        }
+        Command::Keygen { out } => {
+            if out.exists() {
+                return Err(format!("{} already exists", out.display()).into());
+            }
+            let keypair = PKey::generate_ed25519()?;
+            create_private(&out)?.write_all(&keypair.private_key_to_pem_pkcs8()?)?;
+            println!("{}", to_hex(&keypair.raw_public_key()?));
+        }
+        Command::Tokens => {
+            let (_, chain) = open(path)?;
+            let ledger = chain.ledger();
+            for token in ledger.tokens() {
+                if let (Some(mint), Some(owner)) = (ledger.mint(token), ledger.ownership(token)?) {
+                    println!("{}\t{}\t{}", token, mint, to_hex(&owner.to_raw_public()?));
+                }
+            }
+        }
+        Command::Burn { token, key } => {
+            let (mut store, mut chain) = open(path)?;
+            chain.burn(&token, &load_key(&key)?)?;
+            store.append(chain.tip())?;
+            println!("{}", chain.tip().hash);
+        }
+        Command::Transfer { token, key, to } => {
+            let (mut store, mut chain) = open(path)?;
+            let recipient = parse_public(&to)?;
+            chain.transfer(&token, &load_key(&key)?, &recipient)?;
+            store.append(chain.tip())?;
+            println!("{}", chain.tip().hash);
+        }
+    }
+    Ok(())
+}
+
+/// Opens the stored chain at `path`, which has to have been created already.
+fn open(path: &Path) -> CliResult<(Store, Chain)> {
+    if !path.exists() {
+        return Err(format!("{} doesn't exist, create it with init", path.display()).into());
+    }
+    Ok(Store::open(path)?)
+}
+
+/// Describes the owner and data length of a block for summaries.
+fn summary(block: &Block) -> CliResult<String> {
+    let owner = match block.ownership {
+        Ownership::Genesis => "genesis".to_string(),
+        _ => to_hex(&block.ownership.to_raw_public()?),
+    };
+    Ok(format!("{}\t{} bytes", owner, block.data.inner.len()))
+}
+
+fn load_key(path: &Path) -> CliResult<PKey<Private>> {
+    Ok(PKey::private_key_from_pem(&fs::read(path)?)?)
+}
+
+fn parse_public(hex: &str) -> CliResult<PKey<Public>> {
+    let raw = from_hex(hex).ok_or_else(|| format!("{} isn't a hexadecimal raw public key", hex))?;
+    Ok(PKey::public_key_from_raw_bytes(&raw, Id::ED25519)?)
+}
+
+/// Creates a new file at `path`, failing if it already exists, which is only
+/// readable by its owner on platforms supporting it.
+fn create_private(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
@@ -1,10 +1,22 @@
 //! Contains [Block], [Ownership] and implementations
 
-use crate::{error::Error, Hash, Result, DEFAULT_GENESIS};
-use openssl::pkey::{PKey, Private, Public};
+use crate::hash::commitment;
+#[cfg(feature = "serde")]
+use crate::PROTO_VERSION;
+use crate::{error::Error, Cosignature, Hash, MultiSig, Result, DEFAULT_GENESIS};
+use openssl::pkey::{Id, PKey, Private, Public};
 use openssl::sha::Sha256;
 #[cfg(feature = "serde")]
-use serde::{ser::SerializeStruct, Serialize};
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Furthest a block's timestamp may be ahead of the local clock, in seconds
+///
+/// This is kept short as every block has to be dated no earlier than the block
+/// before it, so a block dated ahead drags every block after it forward too.
+pub const MAX_CLOCK_DRIFT: u64 = 15 * 60;
 
 /// Single block within a larger blockchain, providing access to a block of data
 ///
@@ -14,6 +26,9 @@ use serde::{ser::SerializeStruct, Serialize};
 ///
 /// - Create a genesis block: [Block::default]
 /// - Create a block containing data: [Block::new]
+/// - Create a block signed by a known keypair: [Block::new_signed]
+/// - Create a block signed by several keys of a [MultiSig]: [Block::new_multisig]
+/// - Create a genesis block containing data: [Block::new_genesis]
 /// - Verify a block: [Block::verify]
 ///
 /// # Example
@@ -42,10 +57,20 @@ pub struct Block {
     pub hash: Hash,
     /// Ownership identifier, represents if we own it or not.
     pub ownership: Ownership,
-    /// Signature which wraps data into a key to verify ownership.
+    /// Signature which wraps data into a key to verify ownership, or the digest
+    /// of every cosignature if owned by a [MultiSig].
     pub signature: [u8; Hash::SIG_LEN],
+    /// Signatures by the keys of a [MultiSig] owning this block, which is empty
+    /// for any other ownership.
+    pub cosignatures: Vec<Cosignature>,
     /// Underlying data contained for this block.
     pub data: BlockData,
+    /// Seconds since the Unix epoch when this block was created, which is
+    /// signed alongside its data; zero for genesis blocks.
+    pub timestamp: u64,
+    /// Nonce used by [ProofOfWork](crate::ProofOfWork) consensus, which isn't
+    /// signed so it can be mined after the block has been created.
+    pub nonce: u64,
 }
 
 impl<'a> Block {
@@ -68,13 +93,107 @@ impl<'a> Block {
     /// }
     /// ```
     pub fn new(previous_hash: impl Into<&'a Hash>, data: impl Into<Vec<u8>>) -> Result<Self> {
+        Self::new_at(previous_hash, data, now())
+    }
+
+    /// Creates a new block like [Block::new], with the given `timestamp` instead
+    /// of the current time.
+    pub(crate) fn new_at(
+        previous_hash: impl Into<&'a Hash>,
+        data: impl Into<Vec<u8>>,
+        timestamp: u64,
+    ) -> Result<Self> {
         let data = BlockData::new(data.into())?;
-        let (hash, signature, pkey) = Hash::new(previous_hash, data.hash)?;
+        let (hash, signature, pkey) = Hash::new(previous_hash, commitment(data.hash, timestamp))?;
         Ok(Self {
             hash,
             ownership: pkey.into(),
             signature,
+            cosignatures: vec![],
             data,
+            timestamp,
+            nonce: 0,
+        })
+    }
+
+    /// Creates a new block from the previous block in a chain alongside the data
+    /// contained within this block, signed by a known keypair which will own it
+    /// instead of a newly generated one.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    /// use openssl::pkey::PKey;
+    ///
+    /// fn main() -> onft::Result<()> {
+    ///     let genesis_block = Block::default();
+    ///     let keypair = PKey::generate_ed25519().unwrap();
+    ///
+    ///     let data = "Hello, world!";
+    ///     let block = Block::new_signed(&genesis_block, data, &keypair)?;
+    ///
+    ///     println!("Block:\n{:?}", block);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new_signed(
+        previous_hash: impl Into<&'a Hash>,
+        data: impl Into<Vec<u8>>,
+        keypair: &PKey<Private>,
+    ) -> Result<Self> {
+        Self::new_signed_at(previous_hash, data, keypair, now())
+    }
+
+    /// Creates a new block signed by a known keypair like [Block::new_signed],
+    /// with the given `timestamp` instead of the current time.
+    pub(crate) fn new_signed_at(
+        previous_hash: impl Into<&'a Hash>,
+        data: impl Into<Vec<u8>>,
+        keypair: &PKey<Private>,
+        timestamp: u64,
+    ) -> Result<Self> {
+        let data = BlockData::new(data.into())?;
+        let (hash, signature, pkey) = Hash::new_existing_keypair(
+            previous_hash,
+            commitment(data.hash, timestamp),
+            keypair.clone(),
+        )?;
+        Ok(Self {
+            hash,
+            ownership: pkey.into(),
+            signature,
+            cosignatures: vec![],
+            data,
+            timestamp,
+            nonce: 0,
+        })
+    }
+
+    /// Creates a new genesis block containing data, such as a chain policy,
+    /// instead of the default empty genesis block.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    ///
+    /// fn main() -> onft::Result<()> {
+    ///     let genesis_block = Block::new_genesis("Hello, world!")?;
+    ///     assert_ne!(genesis_block.hash, Block::default().hash);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new_genesis(data: impl Into<Vec<u8>>) -> Result<Self> {
+        let data = BlockData::new(data.into())?;
+        Ok(Self {
+            hash: Hash::new_genesis(data.hash),
+            ownership: Ownership::Genesis,
+            signature: [0; Hash::SIG_LEN],
+            cosignatures: vec![],
+            data,
+            timestamp: 0,
+            nonce: 0,
         })
     }
 
@@ -102,7 +221,7 @@ impl<'a> Block {
     /// ```
     pub fn verify(&self, previous_hash: impl Into<&'a Hash>) -> Result<bool> {
         let previous_hash = previous_hash.into();
-        let data_hash = self.data.hash;
+        let data_hash = commitment(self.data.hash, self.timestamp);
 
         match &self.ownership {
             Ownership::Them(pkey) => {
@@ -112,9 +231,32 @@ impl<'a> Block {
             Ownership::Us(pkey) => self
                 .hash
                 .verify(previous_hash, self.signature, data_hash, pkey),
+            Ownership::MultiSig(multisig) => multisig.verify(
+                &self.hash,
+                previous_hash,
+                self.signature,
+                &self.cosignatures,
+                data_hash,
+            ),
             Ownership::Genesis => Err(Error::GenesisIsNotKey),
         }
     }
+
+    /// Checks if this block's timestamp can follow on from the `previous`
+    /// block's, being no earlier than it and no further ahead of the local clock
+    /// than [MAX_CLOCK_DRIFT].
+    pub fn follows_in_time(&self, previous: &Block) -> bool {
+        follows_in_time(previous.timestamp, self.timestamp)
+    }
+
+    /// Computes the hash proving the work done for this block, which is the
+    /// block's hash combined with its [nonce](Block::nonce).
+    pub fn work_hash(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.hash.0[..]);
+        hasher.update(&self.nonce.to_le_bytes());
+        Hash(hasher.finish())
+    }
 }
 
 impl Default for Block {
@@ -124,7 +266,10 @@ impl Default for Block {
             hash: Hash::default(),
             ownership: Ownership::Genesis,
             signature: [0; Hash::SIG_LEN],
+            cosignatures: vec![],
             data: BlockData::default(),
+            timestamp: 0,
+            nonce: 0,
         }
     }
 }
@@ -135,17 +280,110 @@ impl Serialize for Block {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("Block", 4 + 1)?;
+        let cosignatures: Vec<_> = self
+            .cosignatures
+            .iter()
+            .map(|(index, signature)| (index, &signature[..]))
+            .collect();
+        let mut state = serializer.serialize_struct("Block", 8 + 1)?;
         state.serialize_field("pver", &PROTO_VERSION)?; // custom protocol version
         state.serialize_field("hash", &self.hash)?;
         state.serialize_field("ownership", &self.ownership)?;
+        state.serialize_field("signature", &self.signature[..])?;
         state.serialize_field("data", &self.data.inner)?;
         state.serialize_field("data_hash", &self.data.hash)?;
+        state.serialize_field("timestamp", &self.timestamp)?;
+        state.serialize_field("nonce", &self.nonce)?;
+        state.serialize_field("cosignatures", &cosignatures)?;
         state.end()
     }
 }
 
-// TODO: deserialize
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Block {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        /// Mirror of the serialized layout of a block
+        #[derive(Deserialize)]
+        struct RawBlock {
+            pver: u8,
+            hash: Hash,
+            ownership: Ownership,
+            signature: Vec<u8>,
+            data: Vec<u8>,
+            data_hash: [u8; 32],
+            #[serde(default)]
+            timestamp: u64,
+            #[serde(default)]
+            nonce: u64,
+            #[serde(default)]
+            cosignatures: Vec<(u8, Vec<u8>)>,
+        }
+
+        let raw = RawBlock::deserialize(deserializer)?;
+        if raw.pver != PROTO_VERSION {
+            return Err(de::Error::custom(format!(
+                "Block uses protocol version {} but {} is supported",
+                raw.pver, PROTO_VERSION
+            )));
+        }
+        let signature = raw.signature[..]
+            .try_into()
+            .map_err(|_| de::Error::invalid_length(raw.signature.len(), &"a 64 byte signature"))?;
+        let cosignatures = raw_cosignatures(raw.cosignatures)?;
+
+        Ok(Self {
+            hash: raw.hash,
+            ownership: raw.ownership,
+            signature,
+            cosignatures,
+            data: BlockData {
+                inner: raw.data,
+                hash: raw.data_hash,
+            },
+            timestamp: raw.timestamp,
+            nonce: raw.nonce,
+        })
+    }
+}
+
+/// Gets the current time in seconds since the Unix epoch, used to timestamp new
+/// blocks.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Gets the timestamp for a new block following on from the `previous` block's,
+/// being the current time unless the previous block is dated later.
+pub(crate) fn next_timestamp(previous: u64) -> u64 {
+    now().max(previous)
+}
+
+/// Checks if a block's `timestamp` can follow on from the `previous` block's,
+/// see [Block::follows_in_time].
+pub(crate) fn follows_in_time(previous: u64, timestamp: u64) -> bool {
+    timestamp >= previous && timestamp <= now().saturating_add(MAX_CLOCK_DRIFT)
+}
+
+/// Converts deserialized cosignatures into their fixed-length form.
+#[cfg(feature = "serde")]
+pub(crate) fn raw_cosignatures<E: de::Error>(
+    cosignatures: Vec<(u8, Vec<u8>)>,
+) -> std::result::Result<Vec<Cosignature>, E> {
+    cosignatures
+        .into_iter()
+        .map(|(index, signature)| {
+            let signature = signature[..]
+                .try_into()
+                .map_err(|_| E::invalid_length(signature.len(), &"a 64 byte signature"))?;
+            Ok((index, signature))
+        })
+        .collect()
+}
 
 /// Data contained within a block along with it's hash to be used downstream
 ///
@@ -164,14 +402,25 @@ pub struct BlockData {
 impl BlockData {
     /// Creates new instance from data, hashing automatically.
     pub fn new(data: impl Into<Vec<u8>>) -> Result<Self> {
-        let mut hasher = Sha256::new();
         let data = data.into();
-        hasher.update(data.as_slice());
         Ok(Self {
+            hash: Self::hash_of(&data),
             inner: data,
-            hash: hasher.finish(),
         })
     }
+
+    /// Hashes raw data in the same way as [BlockData::new].
+    pub(crate) fn hash_of(data: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// Checks if the payload still matches the data hash which was signed, as
+    /// only the hash is covered by a block's signature.
+    pub(crate) fn is_intact(&self) -> bool {
+        Self::hash_of(&self.inner) == self.hash
+    }
 }
 
 impl Default for BlockData {
@@ -218,15 +467,43 @@ pub enum Ownership {
     Them(PKey<Public>),
     /// Owned by us as we have a private key.
     Us(PKey<Private>),
+    /// Owned jointly by several keys, a threshold of which have to sign.
+    MultiSig(MultiSig),
 }
 
 impl Ownership {
-    /// Converts ownership to a public key, used primarily for serialization if enabled.
+    /// Creates ownership owned by an external source from a raw ED25519 public
+    /// key, used primarily for deserialization if enabled.
+    pub fn from_raw_public(raw: &[u8]) -> Result<Self> {
+        PKey::public_key_from_raw_bytes(raw, Id::ED25519)
+            .map(Self::Them)
+            .map_err(Error::KeyRawPublic)
+    }
+
+    /// Creates ownership shared between several keys from a [MultiSig] encoded
+    /// by [MultiSig::to_bytes], used primarily for deserialization if enabled.
+    pub fn from_multisig_bytes(bytes: &[u8]) -> Result<Self> {
+        MultiSig::from_bytes(bytes).map(Self::MultiSig)
+    }
+
+    /// Creates ownership from the raw identity of an owner, being either a raw
+    /// public key or an encoded [MultiSig] which is never 32 bytes long.
+    pub(crate) fn from_raw_owner(raw: &[u8]) -> Result<Self> {
+        if raw.len() == 32 {
+            Self::from_raw_public(raw)
+        } else {
+            Self::from_multisig_bytes(raw)
+        }
+    }
+
+    /// Converts ownership to a public key, or an encoded [MultiSig], used
+    /// primarily for serialization if enabled.
     pub fn to_raw_public(&self) -> Result<Vec<u8>> {
         match self {
             Self::Genesis => Err(Error::GenesisIsNotKey),
             Self::Them(pkey) => pkey.raw_public_key().map_err(Error::KeyPublic),
             Self::Us(pkey) => pkey.raw_public_key().map_err(Error::KeyPublic),
+            Self::MultiSig(multisig) => multisig.to_bytes(),
         }
     }
 }
@@ -243,6 +520,12 @@ impl From<PKey<Private>> for Ownership {
     }
 }
 
+impl From<MultiSig> for Ownership {
+    fn from(multisig: MultiSig) -> Self {
+        Self::MultiSig(multisig)
+    }
+}
+
 #[cfg(feature = "serde")]
 impl Serialize for Ownership {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
@@ -250,18 +533,43 @@ impl Serialize for Ownership {
         S: serde::Serializer,
     {
         const NAME: &str = "Ownership";
+        let raw = |ownership: &Self| {
+            ownership
+                .to_raw_public()
+                .map_err(|err| serde::ser::Error::custom(format!("{}", err)))
+        };
         match self {
             Ownership::Genesis => serializer.serialize_unit_variant(NAME, 0, "Genesis"),
-            _ => serializer.serialize_newtype_variant(
-                NAME,
-                1,
-                "Them",
-                &self
-                    .to_raw_public()
-                    .map_err(|err| serde::ser::Error::custom(&format!("{}", err)))?[..],
-            ),
+            Ownership::MultiSig(_) => {
+                serializer.serialize_newtype_variant(NAME, 2, "MultiSig", &raw(self)?[..])
+            }
+            _ => serializer.serialize_newtype_variant(NAME, 1, "Them", &raw(self)?[..]),
         }
     }
 }
 
-// TODO: deserialize
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Ownership {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        /// Mirror of the serialized layout of ownership, where private keys are
+        /// always serialized as their public counterparts
+        #[derive(Deserialize)]
+        enum RawOwnership {
+            Genesis,
+            Them(Vec<u8>),
+            MultiSig(Vec<u8>),
+        }
+
+        match RawOwnership::deserialize(deserializer)? {
+            RawOwnership::Genesis => Ok(Self::Genesis),
+            RawOwnership::Them(raw) => {
+                Self::from_raw_public(&raw[..]).map_err(|err| de::Error::custom(format!("{}", err)))
+            }
+            RawOwnership::MultiSig(bytes) => Self::from_multisig_bytes(&bytes[..])
+                .map_err(|err| de::Error::custom(format!("{}", err))),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Contains [Burn] and implementations
+
+use crate::kind::{tagged, BURN_TAG};
+use crate::{error::Error, BlockData, BlockKind, Chain, Result, TokenId};
+use openssl::pkey::{PKey, Private};
+use std::convert::TryInto;
+
+/// Permanent retirement of a token by its current owner
+///
+/// # Using
+///
+/// A token can be burned by its current owner, which signs a new block
+/// containing this burn as its data. Burned tokens can't be transferred or
+/// burned again, which [Chain::verify] enforces, and are reported as such by
+/// [Ledger::is_burned](crate::Ledger::is_burned). You'll typically want to use
+/// [Chain::burn] instead of creating burns directly.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let owner = PKey::generate_ed25519().unwrap();
+///     let recipient = PKey::generate_ed25519().unwrap();
+///
+///     let mut chain = Chain::default();
+///     chain.push_signed("Potion", &owner)?;
+///     let token = chain.token_id(&chain.tip().hash)?;
+///
+///     chain.burn(&token, &owner)?;
+///     assert!(chain.ledger().is_burned(&token));
+///
+///     // it's gone for good
+///     assert!(chain.transfer(&token, &owner, &recipient).is_err());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Burn {
+    /// Identifier of the burned token.
+    pub token: TokenId,
+}
+
+impl Burn {
+    /// Creates a new burn of the `token`.
+    pub fn new(token: TokenId) -> Self {
+        Self { token }
+    }
+
+    /// Encodes this burn into bytes to be used as block data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        tagged(BURN_TAG, &self.token.0[..])
+    }
+
+    /// Decodes a burn from block data, returning [None] if the data doesn't
+    /// contain a burn at all, see [BlockKind::from_data].
+    pub fn from_data(data: &BlockData) -> Result<Option<Self>> {
+        match BlockKind::from_data(data)? {
+            BlockKind::Burn(burn) => Ok(Some(burn)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decodes the payload of a burn, after its tag.
+    pub(crate) fn from_payload(payload: &[u8]) -> Result<Self> {
+        let token = TokenId(payload.try_into().map_err(|_| Error::InvalidPayload)?);
+        Ok(Self { token })
+    }
+}
+
+impl Chain {
+    /// Burns the `token`, adding a new block signed by its current `owner`;
+    /// chainable method.
+    pub fn burn(&mut self, token: &TokenId, owner: &PKey<Private>) -> Result<&mut Self> {
+        self.check_owner(token, owner)?;
+        self.push_signed(Burn::new(token.clone()).to_bytes(), owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Collection, Condition, Ledger};
+
+    #[test]
+    fn burned_tokens() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.push_signed("token", &alice).unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+
+        assert!(matches!(chain.burn(&token, &bob), Err(Error::NotOwner)));
+        chain.burn(&token, &alice).unwrap();
+        assert!(chain.ledger().is_burned(&token));
+        assert!(chain.ledger().owner(&token).unwrap().is_none());
+        assert!(chain.ledger().tokens_of(&alice).unwrap().is_empty());
+        assert!(matches!(chain.burn(&token, &alice), Err(Error::Burned)));
+        assert!(matches!(
+            chain.transfer(&token, &alice, &bob),
+            Err(Error::Burned)
+        ));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn burned_collection_tokens() {
+        let studio = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let collection = chain
+            .create_collection(&Collection::new(Some(2), "Series"), &studio)
+            .unwrap();
+        let token = chain.mint_into(&collection, "first", &studio).unwrap();
+        let locked = chain.mint_into(&collection, "second", &studio).unwrap();
+        chain
+            .lock(&locked, &studio, &bob, Condition::Height(10))
+            .unwrap();
+
+        // locked tokens can't be burned from under their recipient
+        assert!(matches!(chain.burn(&locked, &studio), Err(Error::Locked)));
+        chain.burn(&token, &studio).unwrap();
+
+        // burned tokens still count towards the supply
+        assert!(matches!(
+            chain.mint_into(&collection, "third", &studio),
+            Err(Error::SupplyExhausted)
+        ));
+        assert!(matches!(
+            chain.approve(&token, &studio, &bob),
+            Err(Error::Burned)
+        ));
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn payloads() {
+        let burn = Burn::new(TokenId([7; 32]));
+        let data = BlockData::new(burn.to_bytes()).unwrap();
+        assert_eq!(Burn::from_data(&data).unwrap(), Some(burn));
+        assert!(matches!(
+            Burn::from_data(&BlockData::new(&b"\0nft\x03short"[..]).unwrap()),
+            Err(Error::InvalidPayload)
+        ));
+        assert!(Burn::from_data(&BlockData::new("token").unwrap())
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,8 +1,16 @@
 //! Contains [Chain] and implementations
 
-use crate::{error::Result, Block};
+use crate::block::next_timestamp;
+use crate::ledger::verify_ledger;
+use crate::{
+    error::Error, error::Result, Block, Consensus, Ledger, Open, Ownership, Policy, RoyaltyRule,
+    Snapshot,
+};
+use openssl::pkey::{PKey, Private};
 #[cfg(feature = "serde")]
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::sync::Arc;
 
 /// Representation of an Onft blockchain
 ///
@@ -11,9 +19,33 @@ use serde::{Deserialize, Serialize};
 /// You can, in high level terms, do the following directly to a blockchain:
 ///
 /// - Create an initial blockchain: [Chain::default]
+/// - Create a blockchain from existing blocks: [Chain::try_from]
+/// - Create an initial blockchain using a [Consensus]: [Chain::with_consensus]
+/// - Create an initial blockchain only authorities may mint on: [Chain::with_policy]
 /// - Add some data inside a new block: [Chain::push_data]
+/// - Add some data inside a new block signed by a known keypair: [Chain::push_signed]
+/// - Add some data inside a new block signed by several keys: [Chain::push_multisig]
+/// - Mint a new token with some metadata: [Chain::mint]
+/// - Mint a limited run of editions of a token: [Chain::mint_editions]
+/// - Add a block performing a typed [BlockKind](crate::BlockKind) of operation: [Chain::push_kind]
+/// - Transfer ownership of a token to someone else: [Chain::transfer]
+/// - Replace the metadata of a token: [Chain::update_metadata]
+/// - Permanently retire a token: [Chain::burn]
+/// - Enforce royalties on resale: [Chain::set_royalty_rule] and [Chain::transfer_settled]
+/// - Transfer a token once a condition is met: [Chain::lock] and [Chain::release]
+/// - Let a delegate transfer tokens on your behalf: [Chain::approve] and [Chain::approve_all]
+/// - Replace a leaked owner key with a new one: [Chain::rotate]
+/// - Group tokens minted by a single creator: [Chain::create_collection] and [Chain::mint_into]
+/// - Identify tokens independently of their blocks: [Chain::token_id] and [Chain::token]
 /// - Extend multiple new pieces of data inside new blocks: [Chain::extend_data]
 /// - Verify entire blockchain one-by-one: [Chain::verify]
+/// - Verify the blockchain from a known-good [Checkpoint](crate::Checkpoint): [Chain::verify_from]
+/// - Get the blocks inside of the blockchain: [Chain::blocks] and [Chain::tip]
+/// - Find who owns which tokens: [Chain::ledger]
+/// - Find where two blockchains have forked: [Chain::common_ancestor]
+/// - Prove a block is inside of the blockchain: [Chain::merkle_root] and [Chain::inclusion_proof]
+/// - Synchronize with a peer incrementally: [Chain::delta] and [Chain::apply_delta]
+/// - Drop old payloads whilst keeping ownership: [Chain::prune] and [Chain::snapshot]
 ///
 /// # Example
 ///
@@ -35,11 +67,87 @@ use serde::{Deserialize, Serialize};
 ///     eprintln!("Not verified")
 /// }
 /// ```
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+///
+/// # Invariant
+///
+/// Every chain is non-empty and starts with a single genesis block, which is
+/// checked whenever a chain is created from existing blocks or deserialized.
+///
+/// The chain's [Consensus] isn't stored alongside its blocks, so chains created
+/// from existing blocks or deserialized use [Open] consensus until
+/// [Chain::set_consensus] is used.
+#[cfg_attr(feature = "serde", derive(Deserialize), serde(try_from = "Vec<Block>"))]
 #[derive(Debug, Clone)]
-pub struct Chain(Vec<Block>);
+pub struct Chain {
+    /// Every block in order, starting with the genesis block.
+    pub(crate) blocks: Vec<Block>,
+    /// Consensus consulted for new blocks and verification.
+    pub(crate) consensus: Arc<dyn Consensus>,
+    /// Snapshot which the payloads of older blocks were pruned up to.
+    pub(crate) base: Option<Snapshot>,
+    /// Ownership index kept up to date as new blocks are added.
+    pub(crate) ledger: Ledger,
+    /// Rule consulted for every transfer, if the host application set one.
+    pub(crate) royalty_rule: Option<Arc<dyn RoyaltyRule>>,
+}
 
 impl Chain {
+    /// Creates an initial blockchain containing just the genesis block, which
+    /// will use the given `consensus` for new blocks and verification.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    /// use onft::ProofOfWork;
+    ///
+    /// fn main() -> onft::Result<()> {
+    ///     let mut chain = Chain::with_consensus(ProofOfWork::new(4)?);
+    ///     chain.push_data("Hello, world!")?;
+    ///
+    ///     assert!(chain.verify()?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn with_consensus(consensus: impl Consensus + 'static) -> Self {
+        Self::from_parts(vec![Block::default()], Arc::new(consensus), None)
+    }
+
+    /// Creates a chain directly from its parts, which should already uphold the
+    /// chain invariant, replaying its ledger from the `base` snapshot if given.
+    pub(crate) fn from_parts(
+        blocks: Vec<Block>,
+        consensus: Arc<dyn Consensus>,
+        base: Option<Snapshot>,
+    ) -> Self {
+        let mut chain = Self {
+            ledger: Ledger::new(&blocks[0]),
+            blocks,
+            consensus,
+            base,
+            royalty_rule: None,
+        };
+        chain.ledger = Ledger::replay(&chain);
+        chain
+    }
+
+    /// Replaces the consensus used by this chain, which is typically needed
+    /// after loading a chain from existing blocks.
+    pub fn set_consensus(&mut self, consensus: impl Consensus + 'static) {
+        self.consensus = Arc::new(consensus)
+    }
+
+    /// Gets the ledger of who currently owns each token on this chain, see
+    /// [Ledger] for more information.
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Gets the consensus used by this chain.
+    pub fn consensus(&self) -> &dyn Consensus {
+        &*self.consensus
+    }
+
     /// Verifies entire chain block-by-block from the first index.
     ///
     /// # Example
@@ -70,14 +178,85 @@ impl Chain {
     /// using the [Block::verify] method if at all possible as the method simply
     /// links to this one.
     pub fn verify(&self) -> Result<bool> {
-        let mut previous_hash = &self.0[0].hash;
-        for block in self.0[1..].iter() {
-            if !block.verify(previous_hash)? {
-                return Ok(false);
+        Ok(self.first_invalid()?.is_none())
+    }
+
+    /// Finds the height of the first block which doesn't verify, or [None] if
+    /// the entire chain does; see [Chain::verify] for what's checked.
+    ///
+    /// Each block is checked against the block before it and the ledger up to
+    /// it, so truncating the chain to just before the returned height leaves a
+    /// chain which verifies.
+    pub fn first_invalid(&self) -> Result<Option<usize>> {
+        let genesis = self.blocks.first().ok_or(Error::ChainEmpty)?;
+        // pruned genesis blocks no longer have their payload to check
+        if !verify_genesis(genesis, self.base.is_none()) {
+            return Ok(Some(0));
+        }
+        let (mut ledger, start) = match &self.base {
+            Some(base) => match self.blocks.get(base.height) {
+                Some(block) if block.hash == base.tip => (base.ledger.clone(), base.height + 1),
+                _ => return Ok(Some(base.height.min(self.blocks.len() - 1))),
+            },
+            None if Policy::from_data(&genesis.data).is_err() => return Ok(Some(0)),
+            None => (Ledger::new(genesis), 1),
+        };
+
+        for (height, pair) in self.blocks.windows(2).enumerate() {
+            let (previous, block) = (&pair[0], &pair[1]);
+            let height = height + 1;
+            if !self.verify_link(previous, block)? {
+                return Ok(Some(height));
+            } else if height < start {
+                // blocks up to the snapshot are trusted besides their links
+                continue;
             }
-            previous_hash = &block.hash
+            let settled = match &self.royalty_rule {
+                Some(rule) => ledger.settles(&**rule, block)?,
+                None => true,
+            };
+            if !settled || !ledger.apply(block) {
+                return Ok(Some(height));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Drops every block from `height` onwards, such as the first invalid block
+    /// found by [Chain::first_invalid]; chainable method.
+    ///
+    /// The genesis block and blocks up to the snapshot the chain was pruned to
+    /// can't be dropped, as the chain couldn't be verified without them.
+    pub fn truncate(&mut self, height: usize) -> Result<&mut Self> {
+        if height == 0 {
+            return Err(Error::ChainEmpty);
+        } else if self.base.as_ref().is_some_and(|base| height <= base.height) {
+            return Err(Error::SnapshotMismatch);
+        } else if height < self.blocks.len() {
+            self.blocks.truncate(height);
+            self.ledger = Ledger::replay(self);
         }
-        Ok(true)
+        Ok(self)
+    }
+
+    /// Verifies the genesis block, [Policy](crate::Policy) rules and royalty
+    /// rule of the entire chain, being everything [Chain::verify] checks besides
+    /// the links between blocks.
+    pub(crate) fn verify_contents(&self) -> Result<bool> {
+        let genesis = self.blocks.first().ok_or(Error::ChainEmpty)?;
+        // pruned genesis blocks no longer have their payload to check
+        Ok(verify_genesis(genesis, self.base.is_none())
+            && verify_ledger(
+                self.base.as_ref(),
+                self.blocks.iter(),
+                self.royalty_rule.as_deref(),
+            )?)
+    }
+
+    /// Verifies that a `block` correctly follows on from the `previous` block,
+    /// by its signature, its timestamp and according to the chain's consensus.
+    pub(crate) fn verify_link(&self, previous: &Block, block: &Block) -> Result<bool> {
+        verify_link(&*self.consensus, previous, block)
     }
 
     /// Adds a new single block to the chain via new data; chainable method.
@@ -93,9 +272,48 @@ impl Chain {
     /// println!("Chain: {:?}", chain);
     /// ```
     pub fn push_data(&mut self, data: impl Into<Vec<u8>>) -> Result<&mut Self> {
-        let previous_block = self.0.last().unwrap();
-        let new_block = Block::new(&previous_block.hash, data)?;
-        self.0.push(new_block);
+        let timestamp = next_timestamp(self.tip().timestamp);
+        let new_block = Block::new_at(&self.tip().hash, data, timestamp)?;
+        self.push_sealed(new_block)
+    }
+
+    /// Adds a new single block to the chain via new data, signed by a known
+    /// keypair which will own it; chainable method.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    /// use openssl::pkey::PKey;
+    ///
+    /// let keypair = PKey::generate_ed25519().unwrap();
+    /// let mut chain = Chain::default();
+    /// chain.push_signed("Hello, world!", &keypair).unwrap();
+    ///
+    /// println!("Chain: {:?}", chain);
+    /// ```
+    pub fn push_signed(
+        &mut self,
+        data: impl Into<Vec<u8>>,
+        keypair: &PKey<Private>,
+    ) -> Result<&mut Self> {
+        let timestamp = next_timestamp(self.tip().timestamp);
+        let new_block = Block::new_signed_at(&self.tip().hash, data, keypair, timestamp)?;
+        self.push_sealed(new_block)
+    }
+
+    /// Seals a newly created block using the chain's consensus before adding it.
+    pub(crate) fn push_sealed(&mut self, mut block: Block) -> Result<&mut Self> {
+        if !block.follows_in_time(self.tip()) {
+            return Err(Error::InvalidTimestamp);
+        } else if !self.ledger.allows(&block) {
+            return Err(Error::Unauthorized);
+        } else if !self.royalties_settled(&block)? {
+            return Err(Error::Unsettled);
+        }
+        self.consensus.seal(self.tip(), &mut block)?;
+        self.ledger.apply(&block);
+        self.blocks.push(block);
         Ok(self)
     }
 
@@ -127,11 +345,266 @@ impl Chain {
         Ok(self)
     }
 
+    /// Gets the latest block of the chain, which is the genesis block if no
+    /// other blocks have been added yet.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    ///
+    /// let mut chain = Chain::default();
+    /// chain.push_data("Hello, world!").unwrap();
+    ///
+    /// println!("Tip: {:?}", chain.tip());
+    /// ```
+    pub fn tip(&self) -> &Block {
+        // never empty due to the chain invariant
+        &self.blocks[self.blocks.len() - 1]
+    }
+
+    /// Gets every block in the chain in order, starting with the genesis block.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    ///
+    /// let mut chain = Chain::default();
+    /// chain.push_data("Hello, world!").unwrap();
+    ///
+    /// assert_eq!(chain.blocks().len(), 2);
+    /// ```
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks[..]
+    }
+
+    /// Finds the height of the latest block shared with the `other` chain, with
+    /// the genesis block being at `0`, or [None] if they don't share a genesis.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    ///
+    /// fn main() -> onft::Result<()> {
+    ///     let mut ours = Chain::default();
+    ///     ours.push_data("Hello")?;
+    ///
+    ///     let mut theirs = ours.clone();
+    ///     ours.push_data("world")?;
+    ///     theirs.push_data("there")?;
+    ///
+    ///     assert_eq!(ours.common_ancestor(&theirs), Some(1));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn common_ancestor(&self, other: &Chain) -> Option<usize> {
+        self.blocks
+            .iter()
+            .zip(other.blocks.iter())
+            .take_while(|(ours, theirs)| ours.hash == theirs.hash)
+            .count()
+            .checked_sub(1)
+    }
+
     // TODO: more vec-like interface
 }
 
+/// Verifies that a `block` correctly follows on from the `previous` block
+/// according to the given `consensus`, see [Chain::verify_link].
+///
+/// Genesis blocks can't follow on from anything, so they're invalid rather
+/// than an error as they may well have come from an untrusted peer.
+pub(crate) fn verify_link(
+    consensus: &dyn Consensus,
+    previous: &Block,
+    block: &Block,
+) -> Result<bool> {
+    let verified = match block.verify(previous) {
+        Err(Error::GenesisIsNotKey) => false,
+        verified => verified?,
+    };
+    Ok(verified && block.follows_in_time(previous) && consensus.validate(previous, block)?)
+}
+
+/// Verifies that a genesis block is either the default one or that its hash
+/// matches the data it contains, if its `payload` should be checked.
+pub(crate) fn verify_genesis(genesis: &Block, payload: bool) -> bool {
+    let header = genesis.header();
+    header.verify_genesis() && (!payload || header.check_payload(&genesis.data.inner))
+}
+
+impl TryFrom<Vec<Block>> for Chain {
+    type Error = Error;
+
+    /// Creates a chain from existing blocks, ensuring that the blocks start with
+    /// a genesis block and that no other genesis blocks are contained.
+    fn try_from(blocks: Vec<Block>) -> Result<Self> {
+        match blocks.first() {
+            Some(block) if matches!(block.ownership, Ownership::Genesis) => (),
+            Some(_) => return Err(Error::ChainNoGenesis),
+            None => return Err(Error::ChainEmpty),
+        }
+        match blocks[1..]
+            .iter()
+            .position(|block| matches!(block.ownership, Ownership::Genesis))
+        {
+            Some(index) => Err(Error::ChainGenesisMisplaced(index + 1)),
+            None => Ok(Self::from_parts(blocks, Arc::new(Open), None)),
+        }
+    }
+}
+
+impl From<Chain> for Vec<Block> {
+    fn from(chain: Chain) -> Self {
+        chain.blocks
+    }
+}
+
 impl Default for Chain {
     fn default() -> Self {
-        Self(vec![Block::default()])
+        Self::with_consensus(Open)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Chain {
+    /// Serializes the chain as its list of blocks, which fails for pruned chains
+    /// as their [Snapshot] can't be recovered from the blocks alone.
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.base.is_some() {
+            return Err(serde::ser::Error::custom(
+                "pruned chains can't be serialized without their snapshot",
+            ));
+        }
+        self.blocks.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Hash, MAX_CLOCK_DRIFT};
+
+    #[test]
+    fn malformed_chains() {
+        let block = Block::new(&Hash::default(), "Hello, world!").unwrap();
+
+        assert!(matches!(Chain::try_from(vec![]), Err(Error::ChainEmpty)));
+        assert!(matches!(
+            Chain::try_from(vec![block.clone()]),
+            Err(Error::ChainNoGenesis)
+        ));
+        assert!(matches!(
+            Chain::try_from(vec![Block::default(), block, Block::default()]),
+            Err(Error::ChainGenesisMisplaced(2))
+        ));
+    }
+
+    #[test]
+    fn reordered_chain() {
+        let mut chain = Chain::default();
+        chain.extend_data(vec!["first", "second"]).unwrap();
+
+        let mut blocks: Vec<Block> = chain.into();
+        blocks.swap(1, 2);
+        let chain = Chain::try_from(blocks).unwrap();
+
+        assert!(!chain.verify().unwrap());
+    }
+
+    #[test]
+    fn timestamped_blocks() {
+        let keypair = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.push_data("first").unwrap();
+        assert!(chain.tip().timestamp > 0);
+        let timestamp = chain.tip().timestamp;
+
+        // even zero timestamps are signed, so they can't be swapped out
+        let genesis = Block::default();
+        let mut untimed = Block::new_signed_at(&genesis, "old", &keypair, 0).unwrap();
+        assert!(untimed.verify(&genesis).unwrap());
+        let (hash, signature, _) =
+            Hash::new_existing_keypair(&genesis, untimed.data.hash, keypair.clone()).unwrap();
+        untimed.hash = hash;
+        untimed.signature = signature;
+        assert!(!untimed.verify(&genesis).unwrap());
+
+        // blocks can't go back in time or run too far ahead of the clock
+        let backdated = Block::new_signed_at(chain.tip(), "old", &keypair, timestamp - 1).unwrap();
+        let ahead = crate::block::now() + MAX_CLOCK_DRIFT + 60;
+        let future = Block::new_signed_at(chain.tip(), "new", &keypair, ahead).unwrap();
+        for block in [backdated, future] {
+            assert!(block.verify(chain.tip()).unwrap());
+            let mut blocks = chain.blocks.clone();
+            blocks.push(block.clone());
+            assert_eq!(
+                Chain::try_from(blocks).unwrap().first_invalid().unwrap(),
+                Some(2)
+            );
+            assert!(matches!(
+                chain.clone().push_sealed(block),
+                Err(Error::InvalidTimestamp)
+            ));
+        }
+    }
+
+    #[test]
+    fn future_dated_tip() {
+        let keypair = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.push_data("first").unwrap();
+
+        // a block dated as far ahead as allowed doesn't hold up later blocks
+        let ahead = crate::block::now() + MAX_CLOCK_DRIFT - 60;
+        let future = Block::new_signed_at(chain.tip(), "future", &keypair, ahead).unwrap();
+        chain.push_sealed(future).unwrap();
+        chain.push_data("second").unwrap();
+        chain.push_signed("third", &keypair).unwrap();
+        assert!(chain.tip().timestamp >= ahead);
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn repair_tampered() {
+        let mut chain = Chain::default();
+        chain.extend_data(vec!["first", "second", "third"]).unwrap();
+        assert_eq!(chain.first_invalid().unwrap(), None);
+
+        // payloads swapped out after signing still link up
+        let mut blocks: Vec<Block> = chain.into();
+        blocks[2].data.inner = b"forged".to_vec();
+        let mut tampered = Chain::try_from(blocks).unwrap();
+        assert_eq!(tampered.first_invalid().unwrap(), Some(2));
+        assert!(!tampered.verify().unwrap());
+
+        assert!(matches!(tampered.truncate(0), Err(Error::ChainEmpty)));
+        tampered.truncate(2).unwrap();
+        assert_eq!(tampered.blocks.len(), 2);
+        assert!(tampered.verify().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn deserialize_chains() {
+        let mut chain = Chain::default();
+        chain.push_data("Hello, world!").unwrap();
+
+        let json = serde_json::to_string(&chain).unwrap();
+        let decoded: Chain = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.tip().hash, chain.tip().hash);
+        assert!(decoded.verify().unwrap());
+
+        let blocks = serde_json::to_string(&vec![chain.tip()]).unwrap();
+        assert!(serde_json::from_str::<Chain>("[]").is_err());
+        assert!(serde_json::from_str::<Chain>(&blocks).is_err());
+
+        chain.prune(1).unwrap();
+        assert!(serde_json::to_string(&chain).is_err());
     }
 }
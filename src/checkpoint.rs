@@ -0,0 +1,280 @@
+//! Contains [Checkpoint] and implementations
+
+use crate::chain::verify_genesis;
+use crate::error::{Error, SignerError, VerifierError};
+use crate::{Chain, Hash, Ledger, Result};
+use openssl::pkey::{HasPublic, PKey, PKeyRef, Private};
+use openssl::sign::{Signer, Verifier};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Prefix of signed checkpoint messages, keeping them distinct from blocks
+const CHECKPOINT_DOMAIN: &[u8] = b"onft checkpoint";
+
+/// Known-good point of a chain which verification can resume from
+///
+/// # Using
+///
+/// Verifying a long chain from its genesis block on every startup is costly,
+/// so a checkpoint records the height and hash of a block which has already
+/// been verified. Checkpoints are either trusted locally, such as ones made by
+/// this node after verifying the chain itself, or signed by a trusted key so
+/// they can be shared. Checkpoints made from a chain also keep its ledger at
+/// the checkpointed block, which is signed alongside the height and hash, so
+/// verifying from them only replays the blocks which follow. You can, in high
+/// level terms, do the following:
+///
+/// - Create a checkpoint at a height of a chain: [Chain::checkpoint]
+/// - Sign a checkpoint to share it: [Checkpoint::sign]
+/// - Check a checkpoint was signed by a trusted key: [Checkpoint::verify_signature]
+/// - Check a checkpoint matches a chain: [Chain::validate_checkpoint]
+/// - Verify a chain from a checkpoint onwards: [Chain::verify_from]
+///
+/// With the `store` feature enabled, checkpoints can be kept next to a stored
+/// chain using `Store::save_checkpoint`.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+///
+/// fn main() -> onft::Result<()> {
+///     let mut chain = Chain::default();
+///     chain.extend_data(vec!["Hello", "world"])?;
+///     assert!(chain.verify()?);
+///     let checkpoint = chain.checkpoint(2)?;
+///
+///     // later on, only new blocks need verifying
+///     chain.push_data("again")?;
+///     assert!(chain.verify_from(&checkpoint)?);
+///     Ok(())
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Height of the checkpointed block.
+    pub height: usize,
+    /// Hash of the checkpointed block.
+    pub hash: Hash,
+    /// Signature over the height, hash and [digest](Ledger::digest) of the
+    /// ledger, if the checkpoint has been signed.
+    pub signature: Option<Vec<u8>>,
+    /// Ledger at the checkpointed block, if known; replacing or removing it
+    /// invalidates the signature.
+    pub ledger: Option<Ledger>,
+}
+
+impl Checkpoint {
+    /// Creates a new unsigned checkpoint, which should only be used if it's
+    /// trusted locally.
+    pub fn new(height: usize, hash: Hash) -> Self {
+        Self {
+            height,
+            hash,
+            signature: None,
+            ledger: None,
+        }
+    }
+
+    /// Signs this checkpoint using the given `keypair`, replacing any existing
+    /// signature.
+    pub fn sign(mut self, keypair: &PKey<Private>) -> Result<Self> {
+        let mut signer = Signer::new_without_digest(keypair).map_err(SignerError::Create)?;
+        let signature = signer
+            .sign_oneshot_to_vec(&self.message())
+            .map_err(SignerError::Update)?;
+        self.signature = Some(signature);
+        Ok(self)
+    }
+
+    /// Checks if this checkpoint was signed by the given trusted public key,
+    /// being `false` for unsigned checkpoints.
+    pub fn verify_signature(&self, pkey: &PKeyRef<impl HasPublic>) -> Result<bool> {
+        let signature = match &self.signature {
+            Some(signature) => signature,
+            None => return Ok(false),
+        };
+        let mut verifier = Verifier::new_without_digest(pkey).map_err(VerifierError::Create)?;
+        Ok(verifier
+            .verify_oneshot(signature, &self.message())
+            .map_err(VerifierError::Execute)?)
+    }
+
+    /// Creates the message which is signed for this checkpoint, committing to
+    /// its ledger if it has one.
+    fn message(&self) -> Vec<u8> {
+        let mut message = CHECKPOINT_DOMAIN.to_vec();
+        message.extend_from_slice(&(self.height as u64).to_le_bytes());
+        message.extend_from_slice(&self.hash.0[..]);
+        match &self.ledger {
+            Some(ledger) => {
+                message.push(1);
+                message.extend_from_slice(&ledger.digest().0[..]);
+            }
+            None => message.push(0),
+        }
+        message
+    }
+}
+
+impl Chain {
+    /// Creates an unsigned checkpoint of the block at `height` along with the
+    /// ledger up to it, which should only be done once the chain has been
+    /// verified up to it.
+    ///
+    /// Checkpoints below the snapshot the chain was pruned to can't have their
+    /// ledger rebuilt, so they're created without one.
+    pub fn checkpoint(&self, height: usize) -> Result<Checkpoint> {
+        let block = self.blocks.get(height).ok_or(Error::UnknownBlock)?;
+        let mut checkpoint = Checkpoint::new(height, block.hash.clone());
+        checkpoint.ledger = if height == self.blocks.len() - 1 {
+            Some(self.ledger.clone())
+        } else {
+            Ledger::replay_to(self, height)
+        };
+        Ok(checkpoint)
+    }
+
+    /// Checks if the `checkpoint` matches the block at its height in this chain.
+    pub fn validate_checkpoint(&self, checkpoint: &Checkpoint) -> bool {
+        self.blocks
+            .get(checkpoint.height)
+            .is_some_and(|block| block.hash == checkpoint.hash)
+    }
+
+    /// Verifies this chain from a trusted `checkpoint` onwards, being `false` if
+    /// the checkpoint doesn't match this chain.
+    ///
+    /// Signatures and consensus rules are only checked for blocks after the
+    /// checkpoint. If it has a ledger, only the blocks after it are replayed on
+    /// top of it too, otherwise the [Policy](crate::Policy) and royalty rules
+    /// are still checked for the entire chain.
+    pub fn verify_from(&self, checkpoint: &Checkpoint) -> Result<bool> {
+        if !self.validate_checkpoint(checkpoint) {
+            return Ok(false);
+        }
+        for pair in self.blocks[checkpoint.height..].windows(2) {
+            if !self.verify_link(&pair[0], &pair[1])? {
+                return Ok(false);
+            }
+        }
+        // blocks pruned after the checkpoint no longer have payloads to replay
+        let pruned = self.base.as_ref().map_or(0, |base| base.height);
+        match &checkpoint.ledger {
+            Some(ledger) if pruned <= checkpoint.height => {
+                let genesis = &self.blocks[0];
+                let blocks = &self.blocks[checkpoint.height + 1..];
+                let rule = self.royalty_rule.as_deref();
+                Ok(verify_genesis(genesis, self.base.is_none())
+                    && ledger.clone().folded(blocks, rule)?.is_some())
+            }
+            _ => self.verify_contents(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Block, RequireSettlement, Royalty, TokenMetadata};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn resume_verification() {
+        let mut chain = Chain::default();
+        chain.extend_data(vec!["first", "second"]).unwrap();
+        let checkpoint = chain.checkpoint(1).unwrap();
+        assert!(matches!(chain.checkpoint(3), Err(Error::UnknownBlock)));
+
+        let mut blocks: Vec<Block> = chain.clone().into();
+        let previous = blocks[2].clone();
+        blocks.push(Block::new(&previous, "third").unwrap());
+        blocks[2].data.hash = [0; 32];
+        let tampered = Chain::try_from(blocks).unwrap();
+
+        assert!(chain.verify_from(&checkpoint).unwrap());
+        assert!(!tampered.verify_from(&checkpoint).unwrap());
+        assert!(!chain
+            .verify_from(&Checkpoint::new(1, Hash::default()))
+            .unwrap());
+    }
+
+    #[test]
+    fn checks_whole_chain_rules() {
+        let artist = PKey::generate_ed25519().unwrap();
+        let collector = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let metadata = TokenMetadata::new("token", vec![Royalty::new(&artist, 500).unwrap()]);
+        let token = chain.mint(metadata.to_bytes().unwrap(), &artist).unwrap();
+        chain.transfer(&token, &artist, &collector).unwrap();
+        let tip = chain.tip().hash.clone();
+        let checkpoint = Checkpoint::new(chain.blocks().len() - 1, tip);
+        assert!(chain.verify_from(&checkpoint).unwrap());
+
+        // without a ledger, unsettled transfers before the checkpoint still
+        // break the rule
+        chain.set_royalty_rule(RequireSettlement);
+        assert!(!chain.verify_from(&checkpoint).unwrap());
+
+        // as does a genesis payload which doesn't match its hash
+        let mut blocks: Vec<Block> = Chain::default().into();
+        blocks[0].data.inner = b"forged".to_vec();
+        let forged = Chain::try_from(blocks).unwrap();
+        assert!(!forged.verify_from(&forged.checkpoint(0).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn replays_from_ledger() {
+        let artist = PKey::generate_ed25519().unwrap();
+        let collector = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let metadata = TokenMetadata::new("token", vec![Royalty::new(&artist, 500).unwrap()]);
+        let token = chain.mint(metadata.to_bytes().unwrap(), &artist).unwrap();
+        chain.transfer(&token, &artist, &collector).unwrap();
+        let checkpoint = chain.checkpoint(chain.blocks().len() - 1).unwrap();
+        assert_eq!(checkpoint.ledger.as_ref(), Some(chain.ledger()));
+        assert_eq!(
+            chain.checkpoint(1).unwrap().ledger,
+            Ledger::replay_to(&chain, 1)
+        );
+
+        // blocks up to the checkpoint aren't replayed again
+        chain.set_royalty_rule(RequireSettlement);
+        assert!(chain.verify_from(&checkpoint).unwrap());
+
+        // whilst ones after it are applied on top of its ledger
+        let mut blocks: Vec<Block> = chain.clone().into();
+        let stolen = crate::Transfer::new(token, &artist).unwrap();
+        let block = Block::new_signed(&blocks[2], stolen.to_bytes().unwrap(), &artist).unwrap();
+        blocks.push(block);
+        let tampered = Chain::try_from(blocks).unwrap();
+        assert!(!tampered.verify_from(&checkpoint).unwrap());
+    }
+
+    #[test]
+    fn signed_checkpoints() {
+        let trusted = PKey::generate_ed25519().unwrap();
+        let other = PKey::generate_ed25519().unwrap();
+        let chain = Chain::default();
+
+        let checkpoint = chain.checkpoint(0).unwrap();
+        assert!(!checkpoint.verify_signature(&trusted).unwrap());
+
+        let signed = checkpoint.sign(&trusted).unwrap();
+        assert!(signed.verify_signature(&trusted).unwrap());
+        assert!(!signed.verify_signature(&other).unwrap());
+
+        let mut moved = signed.clone();
+        moved.height = 1;
+        assert!(!moved.verify_signature(&trusted).unwrap());
+
+        // the ledger is signed too, so it can't be swapped or dropped
+        let mut stripped = signed.clone();
+        stripped.ledger = None;
+        assert!(!stripped.verify_signature(&trusted).unwrap());
+        let mut forged = signed;
+        forged.ledger.as_mut().unwrap().height += 1;
+        assert!(!forged.verify_signature(&trusted).unwrap());
+    }
+}
@@ -0,0 +1,382 @@
+//! Contains [Collection], [CollectionId] and implementations
+
+use crate::error::Error;
+use crate::hash::{from_hex, to_hex};
+use crate::kind::{tagged, COLLECTION_TAG};
+use crate::{BlockData, BlockKind, Chain, Hash, Ledger, Result, TokenId};
+use openssl::pkey::{Id, PKey, Private, Public};
+use openssl::sha::Sha256;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{convert::TryInto, fmt, str::FromStr};
+
+/// Prefix of hashed collection identifiers, keeping them distinct from tokens
+const COLLECTION_DOMAIN: &[u8] = b"onft collection";
+
+/// Group of tokens minted by a single creator, optionally with a supply cap
+///
+/// # Using
+///
+/// A collection is created by signing a block containing it, after which only
+/// its creator may mint tokens into it and [Chain::verify] rejects any mints
+/// past its supply cap. Burned tokens still count towards the supply, so a
+/// collection can never have more tokens minted than its cap. You can, in high
+/// level terms, do the following:
+///
+/// - Create a new collection: [Chain::create_collection]
+/// - Mint a new token into a collection: [Chain::mint_into]
+/// - Look up a collection and its creator: [Ledger::collection] and [Ledger::creator]
+/// - Find how many tokens a collection has minted: [Ledger::minted]
+/// - Find which collection a token belongs to: [Ledger::collection_of]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let studio = PKey::generate_ed25519().unwrap();
+///     let mut chain = Chain::default();
+///
+///     let collection = chain.create_collection(&Collection::new(Some(2), "Cards"), &studio)?;
+///     let token = chain.mint_into(&collection, "Ace", &studio)?;
+///     chain.mint_into(&collection, "King", &studio)?;
+///
+///     // the supply is used up
+///     assert!(chain.mint_into(&collection, "Queen", &studio).is_err());
+///     assert_eq!(chain.ledger().collection_of(&token), Some(&collection));
+///     Ok(())
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collection {
+    /// Maximum amount of tokens which can ever be minted, or [None] if there's
+    /// no limit.
+    pub supply: Option<u64>,
+    /// Metadata describing the collection.
+    pub metadata: Vec<u8>,
+}
+
+impl Collection {
+    /// Creates a new collection with an optional `supply` cap.
+    pub fn new(supply: Option<u64>, metadata: impl Into<Vec<u8>>) -> Self {
+        Self {
+            supply,
+            metadata: metadata.into(),
+        }
+    }
+
+    /// Encodes this collection into bytes to be used as block data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = vec![];
+        match self.supply {
+            Some(supply) => {
+                payload.push(1);
+                payload.extend_from_slice(&supply.to_le_bytes());
+            }
+            None => payload.push(0),
+        }
+        payload.extend_from_slice(&self.metadata);
+        tagged(COLLECTION_TAG, &payload)
+    }
+
+    /// Decodes a collection from block data, returning [None] if the data
+    /// doesn't contain a collection at all, see [BlockKind::from_data].
+    pub fn from_data(data: &BlockData) -> Result<Option<Self>> {
+        match BlockKind::from_data(data)? {
+            BlockKind::CreateCollection(collection) => Ok(Some(collection)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decodes the payload of a collection, after its tag.
+    pub(crate) fn from_payload(payload: &[u8]) -> Result<Self> {
+        let (supply, metadata) = match payload.split_first() {
+            Some((0, metadata)) => (None, metadata),
+            Some((1, rest)) if rest.len() >= 8 => {
+                let supply = u64::from_le_bytes(rest[..8].try_into().unwrap());
+                (Some(supply), &rest[8..])
+            }
+            _ => return Err(Error::InvalidPayload),
+        };
+        Ok(Self::new(supply, metadata))
+    }
+}
+
+/// Stable identifier of a [Collection], assigned when it's created
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CollectionId(pub(crate) [u8; 32]);
+
+impl CollectionId {
+    /// Creates the identifier of a collection created by the block with the
+    /// `creation` hash on the chain with the `chain` genesis hash.
+    pub fn new(chain: &Hash, creation: &Hash) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(COLLECTION_DOMAIN);
+        hasher.update(&chain.0[..]);
+        hasher.update(&creation.0[..]);
+        Self(hasher.finish())
+    }
+}
+
+/// Current state of a single collection inside of a [Ledger]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CollectionRecord {
+    /// Raw public key of the collection's creator.
+    pub(crate) creator: Vec<u8>,
+    /// The collection as it was created.
+    pub(crate) collection: Collection,
+    /// Amount of tokens minted into the collection so far.
+    pub(crate) minted: u64,
+}
+
+impl CollectionRecord {
+    /// Checks if the `signer` may mint `count` more tokens into this collection.
+    pub(crate) fn allows_mint(&self, signer: &[u8], count: u64) -> bool {
+        self.creator == signer
+            && self
+                .collection
+                .supply
+                .map_or(true, |supply| self.minted.saturating_add(count) <= supply)
+    }
+}
+
+impl Ledger {
+    /// Gets a collection, or [None] if it isn't known to this ledger.
+    pub fn collection(&self, collection: &CollectionId) -> Option<&Collection> {
+        self.collections
+            .get(collection)
+            .map(|record| &record.collection)
+    }
+
+    /// Gets the public key of the creator of a collection, or [None] if it
+    /// isn't known to this ledger.
+    pub fn creator(&self, collection: &CollectionId) -> Result<Option<PKey<Public>>> {
+        self.collections
+            .get(collection)
+            .map(|record| {
+                PKey::public_key_from_raw_bytes(&record.creator, Id::ED25519)
+                    .map_err(Error::KeyRawPublic)
+            })
+            .transpose()
+    }
+
+    /// Gets the amount of tokens minted into a collection so far, including
+    /// ones which have since been burned.
+    pub fn minted(&self, collection: &CollectionId) -> Option<u64> {
+        self.collections.get(collection).map(|record| record.minted)
+    }
+
+    /// Gets the collection a token was minted into, or [None] if it wasn't
+    /// minted into one.
+    pub fn collection_of(&self, token: &TokenId) -> Option<&CollectionId> {
+        self.tokens.get(token)?.collection.as_ref()
+    }
+}
+
+impl Chain {
+    /// Creates a new `collection` which only the `creator` can mint into,
+    /// returning the new collection's identifier.
+    pub fn create_collection(
+        &mut self,
+        collection: &Collection,
+        creator: &PKey<Private>,
+    ) -> Result<CollectionId> {
+        self.push_kind(&BlockKind::CreateCollection(collection.clone()), creator)?;
+        Ok(CollectionId::new(self.id(), &self.tip().hash))
+    }
+
+    /// Mints a new token with the given `metadata` into a `collection`, signed
+    /// by the collection's `creator`, returning the new token's identifier.
+    pub fn mint_into(
+        &mut self,
+        collection: &CollectionId,
+        metadata: impl Into<Vec<u8>>,
+        creator: &PKey<Private>,
+    ) -> Result<TokenId> {
+        self.check_collection(collection, creator, 1)?;
+        let kind = BlockKind::Mint {
+            collection: Some(collection.clone()),
+            metadata: metadata.into(),
+        };
+        self.push_kind(&kind, creator)?;
+        self.token_id(&self.tip().hash)
+    }
+
+    /// Checks that the `creator` may mint `count` more tokens into a
+    /// `collection`, before any blocks are signed.
+    pub(crate) fn check_collection(
+        &self,
+        collection: &CollectionId,
+        creator: &PKey<Private>,
+        count: u64,
+    ) -> Result<()> {
+        let record = self
+            .ledger
+            .collections
+            .get(collection)
+            .ok_or(Error::UnknownCollection)?;
+        let signer = creator.raw_public_key().map_err(Error::KeyPublic)?;
+        let authorized = self
+            .ledger
+            .authorities
+            .as_ref()
+            .map_or(true, |authorities| authorities.contains(&signer));
+        if record.creator != signer || !authorized {
+            Err(Error::Unauthorized)
+        } else if !record.allows_mint(&signer, count) {
+            Err(Error::SupplyExhausted)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for CollectionId {
+    /// Formats this identifier as lowercase hexadecimal.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", to_hex(&self.0[..]))
+    }
+}
+
+impl FromStr for CollectionId {
+    type Err = Error;
+
+    /// Parses an identifier from hexadecimal, as formatted by its display.
+    fn from_str(s: &str) -> Result<Self> {
+        from_hex(s)
+            .and_then(|bytes| bytes[..].try_into().ok())
+            .map(Self)
+            .ok_or(Error::InvalidHash)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for CollectionId {
+    /// Serializes as hexadecimal for human-readable formats and as raw bytes
+    /// otherwise.
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for CollectionId {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(|err| de::Error::custom(format!("{}", err)))
+        } else {
+            <[u8; 32]>::deserialize(deserializer).map(Self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Block, Policy};
+
+    #[test]
+    fn creator_only_minting() {
+        let studio = PKey::generate_ed25519().unwrap();
+        let outsider = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let collection = chain
+            .create_collection(&Collection::new(None, "Art"), &studio)
+            .unwrap();
+
+        let token = chain.mint_into(&collection, "first", &studio).unwrap();
+        assert!(matches!(
+            chain.mint_into(&collection, "fake", &outsider),
+            Err(Error::Unauthorized)
+        ));
+        assert!(matches!(
+            chain.mint_into(&CollectionId([0; 32]), "lost", &studio),
+            Err(Error::UnknownCollection)
+        ));
+
+        let ledger = chain.ledger();
+        assert_eq!(ledger.collection_of(&token), Some(&collection));
+        assert_eq!(ledger.collection(&collection).unwrap().metadata, b"Art");
+        assert!(ledger
+            .creator(&collection)
+            .unwrap()
+            .unwrap()
+            .public_eq(&studio));
+        assert!(chain.verify().unwrap());
+
+        // operators can transfer the creator's tokens but not mint into it
+        chain.approve_all(&studio, &outsider).unwrap();
+        assert!(matches!(
+            chain.mint_into(&collection, "fake", &outsider),
+            Err(Error::Unauthorized)
+        ));
+        chain.transfer(&token, &outsider, &outsider).unwrap();
+        assert_eq!(chain.ledger().collection_of(&token), Some(&collection));
+    }
+
+    #[test]
+    fn requires_authority() {
+        let studio = PKey::generate_ed25519().unwrap();
+        let admin = PKey::generate_ed25519().unwrap();
+        let policy = Policy::new(vec![&studio, &admin]).unwrap();
+        let mut chain = Chain::with_policy(&policy).unwrap();
+        let collection = chain
+            .create_collection(&Collection::new(None, "Art"), &studio)
+            .unwrap();
+        chain
+            .update_policy(&Policy::new(vec![&admin]).unwrap(), &admin)
+            .unwrap();
+
+        // creators removed from the policy can't mint into their collection
+        assert!(matches!(
+            chain.mint_into(&collection, "more", &studio),
+            Err(Error::Unauthorized)
+        ));
+        let kind = BlockKind::Mint {
+            collection: Some(collection.clone()),
+            metadata: b"more".to_vec(),
+        };
+        let block = Block::new_signed(chain.tip(), kind.to_bytes().unwrap(), &studio).unwrap();
+        assert!(!chain.ledger().allows(&block));
+        assert_eq!(chain.ledger().minted(&collection), Some(0));
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn supply_cap() {
+        let studio = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let collection = chain
+            .create_collection(&Collection::new(Some(1), "Limited"), &studio)
+            .unwrap();
+
+        let token = chain.mint_into(&collection, "only", &studio).unwrap();
+        chain.burn(&token, &studio).unwrap();
+        assert!(matches!(
+            chain.mint_into(&collection, "another", &studio),
+            Err(Error::SupplyExhausted)
+        ));
+        assert_eq!(chain.ledger().minted(&collection), Some(1));
+
+        let collection = Collection::new(Some(7), "payload");
+        let data = BlockData::new(collection.to_bytes()).unwrap();
+        assert_eq!(Collection::from_data(&data).unwrap(), Some(collection));
+    }
+}
@@ -0,0 +1,209 @@
+//! Contains [Consensus] and its reference implementations
+
+use crate::{error::Error, Block, Ownership, Result};
+use openssl::pkey::{HasPublic, PKey};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Rules deciding which blocks may extend a [Chain](crate::Chain)
+///
+/// # Using
+///
+/// Onft doesn't force any consensus on you, so every chain starts out with
+/// [Open] consensus which accepts any validly signed block. A chain can instead
+/// opt into one of the reference implementations or your own:
+///
+/// - Creating a chain using a consensus: [Chain::with_consensus](crate::Chain::with_consensus)
+/// - Proof-of-work, mining a nonce for every block: [ProofOfWork]
+/// - Proof-of-authority, only allowing known signers: [ProofOfAuthority]
+///
+/// The consensus is consulted whenever a block is created on the chain using
+/// [Consensus::seal] and whenever a block is verified using [Consensus::validate],
+/// which happens on top of the usual signature and link checks.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use onft::ProofOfWork;
+///
+/// fn main() -> onft::Result<()> {
+///     let mut chain = Chain::with_consensus(ProofOfWork::new(8)?);
+///     chain.push_data("Hello, world!")?;
+///
+///     assert!(chain.tip().work_hash().meets_difficulty(8));
+///     assert!(chain.verify()?);
+///     Ok(())
+/// }
+/// ```
+pub trait Consensus: fmt::Debug + Send + Sync {
+    /// Prepares a newly created `block` which will follow the `previous` block
+    /// before it's added to the chain, returning an error if it can't be.
+    fn seal(&self, previous: &Block, block: &mut Block) -> Result<()> {
+        if self.validate(previous, block)? {
+            Ok(())
+        } else {
+            Err(Error::ConsensusRejected)
+        }
+    }
+
+    /// Checks if an already signed `block` following the `previous` block is
+    /// allowed by this consensus.
+    fn validate(&self, previous: &Block, block: &Block) -> Result<bool>;
+}
+
+/// Consensus accepting every validly signed block, used by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Open;
+
+impl Consensus for Open {
+    fn validate(&self, _previous: &Block, _block: &Block) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Proof-of-work consensus requiring a mined [nonce](Block::nonce) for every block
+///
+/// Blocks are valid once their [work hash](Block::work_hash) has at least the
+/// `difficulty` amount of leading zero bits, with each extra bit doubling the
+/// expected amount of work needed to create a block. Difficulties above
+/// [ProofOfWork::MAX_DIFFICULTY] can't realistically be met using a 64-bit
+/// nonce and are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofOfWork {
+    /// Amount of leading zero bits needed in a block's work hash.
+    pub difficulty: u32,
+}
+
+impl ProofOfWork {
+    /// Highest difficulty which can be met, being the amount of bits in a
+    /// nonce, as trying every nonce is only expected to meet this many bits
+    pub const MAX_DIFFICULTY: u32 = u64::BITS;
+
+    /// Creates a new proof-of-work consensus with the given `difficulty`,
+    /// returning an error if it's above [ProofOfWork::MAX_DIFFICULTY].
+    pub fn new(difficulty: u32) -> Result<Self> {
+        if difficulty > Self::MAX_DIFFICULTY {
+            return Err(Error::InvalidDifficulty);
+        }
+        Ok(Self { difficulty })
+    }
+}
+
+impl Consensus for ProofOfWork {
+    /// Mines the block by searching for a nonce meeting the difficulty, failing
+    /// if every nonce has been tried without meeting it.
+    fn seal(&self, _previous: &Block, block: &mut Block) -> Result<()> {
+        if self.difficulty > Self::MAX_DIFFICULTY {
+            return Err(Error::InvalidDifficulty);
+        }
+        block.nonce = 0;
+        while !block.work_hash().meets_difficulty(self.difficulty) {
+            block.nonce = block.nonce.checked_add(1).ok_or(Error::InvalidDifficulty)?;
+        }
+        Ok(())
+    }
+
+    fn validate(&self, _previous: &Block, block: &Block) -> Result<bool> {
+        Ok(block.work_hash().meets_difficulty(self.difficulty))
+    }
+}
+
+/// Proof-of-authority consensus only accepting blocks signed by a known set of
+/// authorities
+#[derive(Debug, Clone, Default)]
+pub struct ProofOfAuthority {
+    authorities: HashSet<Vec<u8>>,
+}
+
+impl ProofOfAuthority {
+    /// Creates a new proof-of-authority consensus allowing the given public keys
+    /// to sign blocks.
+    pub fn new<'a, T: HasPublic + 'a>(
+        authorities: impl IntoIterator<Item = &'a PKey<T>>,
+    ) -> Result<Self> {
+        let authorities = authorities
+            .into_iter()
+            .map(|pkey| pkey.raw_public_key().map_err(Error::KeyPublic))
+            .collect::<Result<_>>()?;
+        Ok(Self { authorities })
+    }
+
+    /// Checks if the given `ownership` belongs to one of the authorities.
+    pub fn is_authority(&self, ownership: &Ownership) -> Result<bool> {
+        match ownership {
+            Ownership::Genesis => Ok(false),
+            _ => Ok(self.authorities.contains(&ownership.to_raw_public()?)),
+        }
+    }
+}
+
+impl Consensus for ProofOfAuthority {
+    fn validate(&self, _previous: &Block, block: &Block) -> Result<bool> {
+        self.is_authority(&block.ownership)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chain;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn proof_of_work() {
+        let mut chain = Chain::with_consensus(ProofOfWork::new(8).unwrap());
+        chain.extend_data(vec!["first", "second"]).unwrap();
+        assert!(chain.verify().unwrap());
+
+        // unmined blocks don't verify
+        let mut unmined = Chain::default();
+        unmined.push_data("first").unwrap();
+        let mut blocks: Vec<Block> = unmined.into();
+        while blocks[1].work_hash().meets_difficulty(8) {
+            blocks[1].nonce += 1;
+        }
+
+        let mut unmined = Chain::try_from(blocks).unwrap();
+        unmined.set_consensus(ProofOfWork::new(8).unwrap());
+        assert!(!unmined.verify().unwrap());
+
+        // unreachable difficulties would mine forever
+        assert!(ProofOfWork::new(ProofOfWork::MAX_DIFFICULTY).is_ok());
+        for difficulty in [ProofOfWork::MAX_DIFFICULTY + 1, 128, 257] {
+            assert!(matches!(
+                ProofOfWork::new(difficulty),
+                Err(Error::InvalidDifficulty)
+            ));
+            let mut impossible = Chain::with_consensus(ProofOfWork { difficulty });
+            assert!(matches!(
+                impossible.push_data("never"),
+                Err(Error::InvalidDifficulty)
+            ));
+        }
+    }
+
+    #[test]
+    fn proof_of_authority() {
+        let authority = PKey::generate_ed25519().unwrap();
+        let outsider = PKey::generate_ed25519().unwrap();
+        let consensus = ProofOfAuthority::new(vec![&authority]).unwrap();
+
+        let mut chain = Chain::with_consensus(consensus.clone());
+        chain.push_signed("allowed", &authority).unwrap();
+        assert!(matches!(
+            chain.push_signed("denied", &outsider),
+            Err(Error::ConsensusRejected)
+        ));
+        assert!(matches!(
+            chain.push_data("denied"),
+            Err(Error::ConsensusRejected)
+        ));
+        assert!(chain.verify().unwrap());
+
+        let mut open = Chain::default();
+        open.push_signed("denied", &outsider).unwrap();
+        open.set_consensus(consensus);
+        assert!(!open.verify().unwrap());
+    }
+}
@@ -0,0 +1,218 @@
+//! Contains [Edition] and implementations
+
+use crate::error::Error;
+use crate::{BlockKind, Chain, CollectionId, Hash, Ledger, Result, TokenId};
+use openssl::pkey::{PKey, Private};
+use openssl::sha::Sha256;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Prefix of hashed edition identifiers, keeping them distinct from single tokens
+const EDITION_DOMAIN: &[u8] = b"onft edition";
+
+/// Position of a token within a limited run of editions minted together
+///
+/// # Using
+///
+/// A single block can mint a run of editions sharing the same initial metadata,
+/// such as 100 copies of one artwork. Each edition is a token of its own with
+/// its own [TokenId] and owner, so it can be transferred, burned or have its
+/// metadata updated independently of the others. You can, in high level terms,
+/// do the following:
+///
+/// - Mint a run of editions: [Chain::mint_editions]
+/// - Find which edition a token is: [Ledger::edition]
+/// - Find every edition minted alongside a token: [Ledger::editions]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let artist = PKey::generate_ed25519().unwrap();
+///     let collector = PKey::generate_ed25519().unwrap();
+///     let mut chain = Chain::default();
+///
+///     let prints = chain.mint_editions("Sunflowers", 100, None, &artist)?;
+///     chain.transfer(&prints[41], &artist, &collector)?;
+///
+///     let edition = chain.ledger().edition(&prints[41]).unwrap();
+///     assert_eq!((edition.number, edition.total), (42, 100));
+///     assert_eq!(chain.ledger().tokens_of(&collector)?, vec![&prints[41]]);
+///     Ok(())
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edition {
+    /// Number of this edition, starting from 1.
+    pub number: u32,
+    /// Amount of editions minted alongside each other.
+    pub total: u32,
+}
+
+impl Edition {
+    /// Maximum amount of editions a single block can mint.
+    pub const MAX: u32 = 10_000;
+}
+
+impl TokenId {
+    /// Creates the identifier of the edition with the given `number` minted by
+    /// the block with the `mint` hash on the chain with the `chain` genesis hash.
+    pub fn edition(chain: &Hash, mint: &Hash, number: u32) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(EDITION_DOMAIN);
+        hasher.update(&chain.0[..]);
+        hasher.update(&mint.0[..]);
+        hasher.update(&number.to_le_bytes());
+        Self(hasher.finish())
+    }
+}
+
+impl Ledger {
+    /// Gets the edition a token is, or [None] if it wasn't minted as part of a
+    /// run of editions.
+    pub fn edition(&self, token: &TokenId) -> Option<&Edition> {
+        self.tokens.get(token)?.edition.as_ref()
+    }
+
+    /// Gets every edition minted alongside the given token, including itself,
+    /// in order of their edition numbers.
+    pub fn editions(&self, token: &TokenId) -> Vec<&TokenId> {
+        let mint = match self.tokens.get(token) {
+            Some(record) if record.edition.is_some() => &record.mint,
+            _ => return vec![],
+        };
+        let mut editions: Vec<_> = self
+            .tokens
+            .iter()
+            .filter(|(_, record)| &record.mint == mint)
+            .filter_map(|(token, record)| Some((record.edition?.number, token)))
+            .collect();
+        editions.sort_unstable_by_key(|(number, _)| *number);
+        editions.into_iter().map(|(_, token)| token).collect()
+    }
+}
+
+impl Chain {
+    /// Mints a run of `editions` tokens with the same `metadata`, all owned by
+    /// the `keypair` which signs them and optionally into a `collection`,
+    /// returning each edition's identifier in order.
+    pub fn mint_editions(
+        &mut self,
+        metadata: impl Into<Vec<u8>>,
+        editions: u32,
+        collection: Option<&CollectionId>,
+        keypair: &PKey<Private>,
+    ) -> Result<Vec<TokenId>> {
+        if editions == 0 || editions > Edition::MAX {
+            return Err(Error::InvalidEditions);
+        } else if let Some(collection) = collection {
+            self.check_collection(collection, keypair, editions.into())?;
+        }
+
+        let kind = BlockKind::MintEditions {
+            collection: collection.cloned(),
+            editions,
+            metadata: metadata.into(),
+        };
+        self.push_kind(&kind, keypair)?;
+        let mint = &self.tip().hash;
+        Ok((1..=editions)
+            .map(|number| TokenId::edition(self.id(), mint, number))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Collection, RequireSettlement, Royalty, Settlement, TokenMetadata};
+
+    #[test]
+    fn independent_editions() {
+        let artist = PKey::generate_ed25519().unwrap();
+        let collector = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let prints = chain.mint_editions("print", 3, None, &artist).unwrap();
+
+        chain.transfer(&prints[1], &artist, &collector).unwrap();
+        chain.burn(&prints[2], &artist).unwrap();
+        chain
+            .update_metadata(&prints[1], "signed print", &collector)
+            .unwrap();
+
+        let ledger = chain.ledger();
+        assert_eq!(ledger.tokens_of(&artist).unwrap(), vec![&prints[0]]);
+        assert_eq!(ledger.tokens_of(&collector).unwrap(), vec![&prints[1]]);
+        assert_eq!(ledger.metadata(&prints[0]), Some(&b"print"[..]));
+        assert_eq!(
+            ledger.editions(&prints[2]),
+            prints.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ledger.edition(&prints[2]),
+            Some(&Edition {
+                number: 3,
+                total: 3
+            })
+        );
+        assert_eq!(
+            chain.token(&prints[0]).unwrap().hash,
+            ledger.tokens[&prints[0]].mint
+        );
+        assert_eq!(ledger, &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+        assert!(matches!(
+            chain.mint_editions("none", 0, None, &artist),
+            Err(Error::InvalidEditions)
+        ));
+    }
+
+    #[test]
+    fn editions_count_towards_supply() {
+        let studio = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let collection = chain
+            .create_collection(&Collection::new(Some(5), "Limited"), &studio)
+            .unwrap();
+
+        chain
+            .mint_editions("print", 4, Some(&collection), &studio)
+            .unwrap();
+        assert!(matches!(
+            chain.mint_editions("print", 2, Some(&collection), &studio),
+            Err(Error::SupplyExhausted)
+        ));
+        assert_eq!(chain.ledger().minted(&collection), Some(4));
+    }
+
+    #[test]
+    fn editions_carry_royalties() {
+        let artist = PKey::generate_ed25519().unwrap();
+        let collector = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.set_royalty_rule(RequireSettlement);
+        let metadata = TokenMetadata::new("print", vec![Royalty::new(&artist, 1_000).unwrap()]);
+        let prints = chain
+            .mint_editions(metadata.to_bytes().unwrap(), 2, None, &artist)
+            .unwrap();
+        for print in prints.iter() {
+            assert_eq!(chain.ledger().royalties(print).unwrap().unwrap().len(), 1);
+        }
+
+        // settling one edition doesn't settle the others
+        let settlement = Settlement::new(prints[0].clone(), 1_000, 100);
+        let settlement = chain.settle(&settlement, &artist).unwrap();
+        assert!(matches!(
+            chain.transfer_settled(&prints[1], &artist, &collector, &settlement),
+            Err(Error::Unsettled)
+        ));
+        chain
+            .transfer_settled(&prints[0], &artist, &collector, &settlement)
+            .unwrap();
+        assert!(chain.verify().unwrap());
+    }
+}
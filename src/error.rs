@@ -9,10 +9,20 @@
 //! - Abstract library error: [Error]
 //!     - Whilst signing a block: [SignerError]
 //!     - Whilst verifying a block: [VerifierError]
+//!     - Whilst talking to a peer, if the `net` feature is enabled: `NetError`
+//!     - Whilst storing a chain, if the `store` feature is enabled: `StoreError`
+//!     - Whilst serving the REST API, if the `server` feature is enabled:
+//!       `Error::Serve`
 //! - Module result wrapper type: [Result]
+//!
+//! Each error implements [std::error::Error], with the underlying [ErrorStack]
+//! from openssl being reachable through [source](std::error::Error::source)
+//! where one exists.
 
 use openssl::error::ErrorStack;
-use std::fmt;
+#[cfg(any(feature = "net", feature = "store", feature = "server"))]
+use std::io;
+use std::{error, fmt};
 
 /// Error variants, describing possible errors which may occur within this crate
 #[allow(missing_docs)]
@@ -20,9 +30,42 @@ use std::fmt;
 pub enum Error {
     Signer(SignerError),
     Verifier(VerifierError),
+    #[cfg(feature = "net")]
+    Net(NetError),
+    #[cfg(feature = "store")]
+    Store(StoreError),
+    #[cfg(feature = "server")]
+    Serve(io::Error),
+    #[cfg(feature = "server")]
+    TypedPayload,
     KeyGen(ErrorStack),
     KeyPublic(ErrorStack),
+    KeyRawPublic(ErrorStack),
     GenesisIsNotKey,
+    ChainEmpty,
+    ChainNoGenesis,
+    ChainGenesisMisplaced(usize),
+    UnknownBlock,
+    InvalidHash,
+    InvalidPayload,
+    NotToken,
+    NotOwner,
+    ConsensusRejected,
+    InvalidDifficulty,
+    Unauthorized,
+    SnapshotMismatch,
+    Burned,
+    UnknownCollection,
+    SupplyExhausted,
+    InvalidEditions,
+    Unsettled,
+    InvalidMultiSig,
+    ThresholdNotMet,
+    Locked,
+    NotReleasable,
+    NotApproved,
+    Retired,
+    InvalidTimestamp,
 }
 
 impl fmt::Display for Error {
@@ -30,18 +73,102 @@ impl fmt::Display for Error {
         match self {
             Error::Signer(err) => write!(f, "{}", err),
             Error::Verifier(err) => write!(f, "{}", err),
+            #[cfg(feature = "net")]
+            Error::Net(err) => write!(f, "{}", err),
+            #[cfg(feature = "store")]
+            Error::Store(err) => write!(f, "{}", err),
+            #[cfg(feature = "server")]
+            Error::Serve(err) => write!(f, "Couldn't serve the REST API ({})", err),
+            #[cfg(feature = "server")]
+            Error::TypedPayload => write!(f, "Raw data can't start with onft's payload prefix"),
             Error::KeyGen(err) => write!(f, "Couldn't generate new ED25519 keypair ({})", err),
             Error::KeyPublic(err) => write!(f, "Couldn't convert pkey to raw public key ({})", err),
+            Error::KeyRawPublic(err) => {
+                write!(f, "Couldn't convert raw public key to pkey ({})", err)
+            }
             Error::GenesisIsNotKey => write!(
                 f,
                 "Genesis block's don't contain pkeys but it was queried for"
             ),
+            Error::ChainEmpty => write!(f, "Chain contains no blocks, not even a genesis block"),
+            Error::ChainNoGenesis => write!(f, "Chain doesn't start with a genesis block"),
+            Error::ChainGenesisMisplaced(index) => {
+                write!(f, "Chain contains another genesis block at index {}", index)
+            }
+            Error::UnknownBlock => write!(f, "Block with the given hash isn't known"),
+            Error::InvalidHash => write!(f, "Hash isn't 64 characters of hexadecimal"),
+            Error::InvalidPayload => write!(f, "Block data contains a malformed payload"),
+            Error::NotToken => write!(f, "Block isn't a token which can be owned"),
+            Error::NotOwner => write!(f, "Key doesn't currently own the token"),
+            Error::ConsensusRejected => write!(f, "Block was rejected by the chain's consensus"),
+            Error::InvalidDifficulty => {
+                write!(f, "Proof-of-work difficulty can't be met by any nonce")
+            }
+            Error::Unauthorized => write!(f, "Key isn't authorized to add this block"),
+            Error::SnapshotMismatch => write!(f, "Snapshot doesn't match the chain's blocks"),
+            Error::Burned => write!(f, "Token has been burned"),
+            Error::UnknownCollection => write!(f, "Collection with the given id isn't known"),
+            Error::SupplyExhausted => write!(f, "Collection has already minted its full supply"),
+            Error::InvalidEditions => write!(f, "Amount of editions is zero or too large"),
+            Error::Unsettled => write!(f, "Transfer doesn't settle the token's royalties"),
+            Error::InvalidMultiSig => write!(
+                f,
+                "Multi-signature has an unreachable threshold or repeated keys"
+            ),
+            Error::ThresholdNotMet => write!(f, "Not enough of the multi-signature's keys signed"),
+            Error::Locked => write!(f, "Token is locked in a pending transfer"),
+            Error::NotReleasable => write!(f, "Key can't release or refund the token's lock yet"),
+            Error::NotApproved => write!(f, "Key hasn't been approved by the token's owner"),
+            Error::Retired => write!(f, "Key has been rotated out and can't be used anymore"),
+            Error::InvalidTimestamp => write!(
+                f,
+                "Block's timestamp is before the latest block's or too far ahead"
+            ),
         }
     }
 }
 
-impl From<Error> for () {
-    fn from(_: Error) -> Self {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Signer(err) => err.source(),
+            Error::Verifier(err) => err.source(),
+            #[cfg(feature = "net")]
+            Error::Net(err) => err.source(),
+            #[cfg(feature = "store")]
+            Error::Store(err) => err.source(),
+            #[cfg(feature = "server")]
+            Error::Serve(err) => Some(err),
+            #[cfg(feature = "server")]
+            Error::TypedPayload => None,
+            Error::KeyGen(err) | Error::KeyPublic(err) | Error::KeyRawPublic(err) => Some(err),
+            Error::GenesisIsNotKey
+            | Error::ChainEmpty
+            | Error::ChainNoGenesis
+            | Error::ChainGenesisMisplaced(_)
+            | Error::UnknownBlock
+            | Error::InvalidHash
+            | Error::InvalidPayload
+            | Error::NotToken
+            | Error::NotOwner
+            | Error::ConsensusRejected
+            | Error::InvalidDifficulty
+            | Error::Unauthorized
+            | Error::SnapshotMismatch
+            | Error::Burned
+            | Error::UnknownCollection
+            | Error::SupplyExhausted
+            | Error::InvalidEditions
+            | Error::Unsettled
+            | Error::InvalidMultiSig
+            | Error::ThresholdNotMet
+            | Error::Locked
+            | Error::NotReleasable
+            | Error::NotApproved
+            | Error::Retired
+            | Error::InvalidTimestamp => None,
+        }
+    }
 }
 
 /// Errors related to the creation of block signatures contained within hashes
@@ -77,8 +204,14 @@ impl fmt::Display for SignerError {
     }
 }
 
-impl From<SignerError> for () {
-    fn from(_: SignerError) -> Self {}
+impl error::Error for SignerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SignerError::Create(err) | SignerError::Update(err) | SignerError::Execute(err) => {
+                Some(err)
+            }
+        }
+    }
 }
 
 /// Errors related to verification of hashes and block signatures
@@ -108,8 +241,124 @@ impl fmt::Display for VerifierError {
     }
 }
 
-impl From<VerifierError> for () {
-    fn from(_: VerifierError) -> Self {}
+impl error::Error for VerifierError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            VerifierError::Create(err)
+            | VerifierError::Update(err)
+            | VerifierError::Execute(err) => Some(err),
+        }
+    }
+}
+
+/// Errors related to talking with peers over the network
+#[cfg(feature = "net")]
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    Encode(serde_json::Error),
+    Decode(serde_json::Error),
+    FrameTooLarge(usize),
+    Version(u8),
+    Unexpected,
+}
+
+#[cfg(feature = "net")]
+impl From<NetError> for Error {
+    fn from(err: NetError) -> Self {
+        Self::Net(err)
+    }
+}
+
+#[cfg(feature = "net")]
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Net(NetError::Io(err))
+    }
+}
+
+#[cfg(feature = "net")]
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Io(err) => write!(f, "Couldn't talk to peer ({})", err),
+            NetError::Encode(err) => write!(f, "Couldn't encode message for peer ({})", err),
+            NetError::Decode(err) => write!(f, "Couldn't decode message from peer ({})", err),
+            NetError::FrameTooLarge(len) => {
+                write!(f, "Peer sent a frame of {} bytes which is too large", len)
+            }
+            NetError::Version(version) => write!(
+                f,
+                "Peer uses protocol version {} which isn't supported",
+                version
+            ),
+            NetError::Unexpected => write!(f, "Peer sent an unexpected message"),
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+impl error::Error for NetError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            NetError::Io(err) => Some(err),
+            NetError::Encode(err) | NetError::Decode(err) => Some(err),
+            NetError::FrameTooLarge(_) | NetError::Version(_) | NetError::Unexpected => None,
+        }
+    }
+}
+
+/// Errors related to persistently storing a chain
+#[cfg(feature = "store")]
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub enum StoreError {
+    Io(io::Error),
+    Encode(serde_json::Error),
+    Decode(usize, serde_json::Error),
+    Checkpoint(serde_json::Error),
+    Snapshot(serde_json::Error),
+}
+
+#[cfg(feature = "store")]
+impl From<StoreError> for Error {
+    fn from(err: StoreError) -> Self {
+        Self::Store(err)
+    }
+}
+
+#[cfg(feature = "store")]
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(err) => write!(f, "Couldn't access stored chain ({})", err),
+            StoreError::Encode(err) => write!(f, "Couldn't encode data for storage ({})", err),
+            StoreError::Decode(line, err) => write!(
+                f,
+                "Couldn't decode stored block on line {} ({})",
+                line + 1,
+                err
+            ),
+            StoreError::Checkpoint(err) => {
+                write!(f, "Couldn't decode stored checkpoint ({})", err)
+            }
+            StoreError::Snapshot(err) => write!(f, "Couldn't decode stored snapshot ({})", err),
+        }
+    }
+}
+
+#[cfg(feature = "store")]
+impl error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            StoreError::Io(err) => Some(err),
+            StoreError::Encode(err)
+            | StoreError::Decode(_, err)
+            | StoreError::Checkpoint(err)
+            | StoreError::Snapshot(err) => Some(err),
+        }
+    }
 }
 
 /// Type alias for results containing crate-based errors
@@ -126,3 +375,24 @@ impl<T> From<VerifierError> for Result<T> {
         Result::Err(err.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn source_chaining() {
+        let err: Error = SignerError::Create(ErrorStack::get()).into();
+        assert!(err.source().unwrap().is::<ErrorStack>());
+        assert!(Error::GenesisIsNotKey.source().is_none());
+    }
+
+    #[test]
+    fn boxed_error() {
+        fn fallible() -> std::result::Result<(), Box<dyn error::Error>> {
+            Err(Error::GenesisIsNotKey)?
+        }
+        assert!(fallible().unwrap_err().is::<Error>());
+    }
+}
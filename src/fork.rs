@@ -0,0 +1,627 @@
+//! Contains [ChainTree], [ForkChoice], [Reorg] and implementations
+
+use crate::chain::verify_link;
+use crate::{
+    error::Error, Block, Chain, Consensus, Hash, Ledger, Policy, Result, RoyaltyRule, Snapshot,
+};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Interval of heights at which a [ChainTree] keeps the ledger of a block once
+/// it's been extended, so ledgers of other blocks can be folded forward from
+/// one of them without keeping a ledger for every block
+const LEDGER_INTERVAL: usize = 32;
+
+/// Tree of competing branches which all extend the same genesis block
+///
+/// # Using
+///
+/// Onft doesn't decide which branch is the "real" one for you, but this tree
+/// gives you the tools to do so once two nodes have extended the same tip
+/// differently. You can, in high level terms, do the following:
+///
+/// - Create a tree from a known chain: [ChainTree::new]
+/// - Add a single block onto any known block: [ChainTree::insert]
+/// - Add every block from another chain: [ChainTree::extend]
+/// - Find the common ancestor of two blocks: [ChainTree::common_ancestor]
+/// - Pick the preferred tip using a [ForkChoice] rule: [ChainTree::best_tip]
+/// - Get the blocks needed to switch branches: [ChainTree::reorg]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+///
+/// fn main() -> onft::Result<()> {
+///     let mut ours = Chain::default();
+///     ours.push_data("Hello")?;
+///     let mut theirs = ours.clone();
+///
+///     ours.push_data("world")?;
+///     theirs.extend_data(vec!["other", "world"])?;
+///
+///     let mut tree = ChainTree::new(&ours);
+///     tree.extend(&theirs)?;
+///
+///     let reorg = tree.fork_choice(&ours.tip().hash, &ForkChoice::Longest)?;
+///     if let Some(reorg) = reorg {
+///         reorg.apply(&mut ours)?;
+///     }
+///
+///     assert_eq!(ours.tip().hash, theirs.tip().hash);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChainTree {
+    /// Hash of the genesis block every branch starts from.
+    root: Hash,
+    /// All known blocks keyed by their hash.
+    nodes: HashMap<Hash, Node>,
+    /// Consensus of the chain the tree was created from.
+    consensus: Arc<dyn Consensus>,
+    /// Snapshot the chain the tree was created from was pruned up to.
+    base: Option<Snapshot>,
+    /// Royalty rule of the chain the tree was created from, if it has one.
+    royalty_rule: Option<Arc<dyn RoyaltyRule>>,
+}
+
+/// Single block inside of a [ChainTree] alongside its position
+#[derive(Debug, Clone)]
+struct Node {
+    block: Block,
+    parent: Option<Hash>,
+    height: usize,
+    seen: SystemTime,
+    /// Ledger after applying this block, which is only kept for tips, the
+    /// snapshot and every [LEDGER_INTERVAL] heights.
+    ledger: Option<Ledger>,
+}
+
+impl ChainTree {
+    /// Creates a new tree from an existing chain, which should have already been
+    /// verified as its blocks are trusted as-is, using the chain's consensus for
+    /// new blocks.
+    pub fn new(chain: &Chain) -> Self {
+        let now = SystemTime::now();
+        let mut nodes = HashMap::new();
+        let mut parent: Option<Hash> = None;
+        let tip = chain.blocks.len() - 1;
+
+        // forks are folded forward from the closest known ledger
+        let start = chain.base.as_ref().map_or(0, |base| base.height);
+        let mut folded = match &chain.base {
+            Some(base) => Some(base.ledger.clone()),
+            None if Policy::from_data(&chain.blocks[0].data).is_ok() => {
+                Some(Ledger::new(&chain.blocks[0]))
+            }
+            None => None,
+        };
+        for (height, block) in chain.blocks.iter().enumerate() {
+            if height > start {
+                if let Some(folded) = folded.as_mut() {
+                    folded.apply(block);
+                }
+            }
+            let ledger = match &folded {
+                _ if height == tip => Some(chain.ledger.clone()),
+                Some(folded) if height == start || height % LEDGER_INTERVAL == 0 => {
+                    (height >= start).then(|| folded.clone())
+                }
+                _ => None,
+            };
+            nodes.insert(
+                block.hash.clone(),
+                Node {
+                    block: block.clone(),
+                    parent: parent.replace(block.hash.clone()),
+                    height,
+                    seen: now,
+                    ledger,
+                },
+            );
+        }
+
+        Self {
+            root: chain.blocks[0].hash.clone(),
+            nodes,
+            consensus: chain.consensus.clone(),
+            base: chain.base.clone(),
+            royalty_rule: chain.royalty_rule.clone(),
+        }
+    }
+
+    /// Inserts a new block which extends the known `parent` block, verifying it
+    /// beforehand and returning if it was valid; see [ChainTree::insert_at] for
+    /// more information.
+    pub fn insert(&mut self, parent: &Hash, block: Block) -> Result<bool> {
+        self.insert_at(parent, block, SystemTime::now())
+    }
+
+    /// Inserts a new block which extends the known `parent` block with the time
+    /// it was first seen at, used by the [ForkChoice::Longest] rule.
+    ///
+    /// Blocks which are already known are ignored, keeping their original time
+    /// whilst blocks which don't verify aren't inserted and `false` is returned.
+    /// Blocks are verified in the same way as [Reorg::apply] does, so any
+    /// branch of the tree can be switched over to.
+    pub fn insert_at(&mut self, parent: &Hash, block: Block, seen: SystemTime) -> Result<bool> {
+        let parent_node = self.nodes.get(parent).ok_or(Error::UnknownBlock)?;
+        let height = parent_node.height + 1;
+
+        if self.nodes.contains_key(&block.hash) {
+            return Ok(true);
+        } else if !verify_link(&*self.consensus, &parent_node.block, &block)? {
+            return Ok(false);
+        }
+        let ledger = match self.authorized(parent, &block)? {
+            Some(ledger) => ledger,
+            None => return Ok(false),
+        };
+
+        // the parent's ledger can be folded forward again if it's needed
+        if !self.keeps_ledger(parent_node.height) {
+            if let Some(parent_node) = self.nodes.get_mut(parent) {
+                parent_node.ledger = None;
+            }
+        }
+
+        self.nodes.insert(
+            block.hash.clone(),
+            Node {
+                block,
+                parent: Some(parent.clone()),
+                height,
+                seen,
+                ledger: Some(ledger),
+            },
+        );
+        Ok(true)
+    }
+
+    /// Inserts every block of another chain sharing the same genesis block,
+    /// stopping and returning `false` at the first block which doesn't verify.
+    pub fn extend(&mut self, chain: &Chain) -> Result<bool> {
+        if chain.blocks[0].hash != self.root {
+            return Err(Error::UnknownBlock);
+        }
+
+        for pair in chain.blocks.windows(2) {
+            if !self.insert(&pair[0].hash, pair[1].clone())? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Gets a known block by its hash.
+    pub fn get(&self, hash: &Hash) -> Option<&Block> {
+        self.nodes.get(hash).map(|node| &node.block)
+    }
+
+    /// Gets the height of a known block, with the genesis block being at `0`.
+    pub fn height(&self, hash: &Hash) -> Option<usize> {
+        self.nodes.get(hash).map(|node| node.height)
+    }
+
+    /// Gets the hashes of all blocks which haven't been extended by any others,
+    /// in no particular order.
+    pub fn tips(&self) -> Vec<&Hash> {
+        let parents: HashSet<&Hash> = self
+            .nodes
+            .values()
+            .filter_map(|node| node.parent.as_ref())
+            .collect();
+
+        self.nodes
+            .keys()
+            .filter(|hash| !parents.contains(hash))
+            .collect()
+    }
+
+    /// Finds the latest block which both `a` and `b` descend from, returning
+    /// [None] if either isn't known.
+    pub fn common_ancestor(&self, a: &Hash, b: &Hash) -> Option<&Hash> {
+        let mut a = self.nodes.get_key_value(a)?;
+        let mut b = self.nodes.get_key_value(b)?;
+
+        while a.0 != b.0 {
+            if a.1.height >= b.1.height {
+                a = self.parent_of(a.1)?;
+            } else {
+                b = self.parent_of(b.1)?;
+            }
+        }
+        Some(a.0)
+    }
+
+    /// Creates a chain containing every block from the genesis block up to the
+    /// known `tip` block.
+    pub fn branch(&self, tip: &Hash) -> Option<Chain> {
+        let mut blocks = self.path(&self.root, tip)?;
+        blocks.insert(0, self.get(&self.root)?.clone());
+        let mut chain = Chain::from_parts(blocks, self.consensus.clone(), self.base.clone());
+        chain.royalty_rule = self.royalty_rule.clone();
+        Some(chain)
+    }
+
+    /// Picks the preferred tip out of every known tip using the given `rule`.
+    pub fn best_tip(&self, rule: &ForkChoice) -> &Hash {
+        self.tips()
+            .into_iter()
+            .max_by(|a, b| rule.compare(&self.info(a), &self.info(b)))
+            .unwrap_or(&self.root)
+    }
+
+    /// Gets the blocks needed to switch from the `from` tip over to the `to`
+    /// tip, returning [None] if either isn't known.
+    pub fn reorg(&self, from: &Hash, to: &Hash) -> Option<Reorg> {
+        let ancestor = self.common_ancestor(from, to)?;
+        let mut rollback = self.path(ancestor, from)?;
+        rollback.reverse();
+
+        Some(Reorg {
+            ancestor: ancestor.clone(),
+            rollback,
+            apply: self.path(ancestor, to)?,
+        })
+    }
+
+    /// Applies the fork-choice `rule` whilst currently being on the `current`
+    /// tip, returning the needed [Reorg] if the preferred tip is a different one.
+    pub fn fork_choice(&self, current: &Hash, rule: &ForkChoice) -> Result<Option<Reorg>> {
+        if !self.nodes.contains_key(current) {
+            return Err(Error::UnknownBlock);
+        }
+
+        let best = self.best_tip(rule);
+        if best == current || rule.compare(&self.info(best), &self.info(current)).is_le() {
+            Ok(None)
+        } else {
+            Ok(self.reorg(current, best))
+        }
+    }
+
+    /// Gets the parent entry of a node, returning [None] for the genesis block.
+    fn parent_of(&self, node: &Node) -> Option<(&Hash, &Node)> {
+        self.nodes.get_key_value(node.parent.as_ref()?)
+    }
+
+    /// Collects the blocks after `ancestor` up to and including `tip` in order.
+    fn path(&self, ancestor: &Hash, tip: &Hash) -> Option<Vec<Block>> {
+        let mut blocks = vec![];
+        let mut current = self.nodes.get_key_value(tip)?;
+
+        while current.0 != ancestor {
+            blocks.push(current.1.block.clone());
+            current = self.parent_of(current.1)?;
+        }
+
+        blocks.reverse();
+        Some(blocks)
+    }
+
+    /// Checks if the ledger of a block at `height` is kept once it's extended,
+    /// which is always the case for the snapshot as its ledger can't be rebuilt.
+    fn keeps_ledger(&self, height: usize) -> bool {
+        height % LEDGER_INTERVAL == 0
+            || self.base.as_ref().is_some_and(|base| base.height == height)
+    }
+
+    /// Gets the ledger after a new block extending the known `parent` block, or
+    /// [None] if it doesn't follow the chain's rules, such as its policy, who
+    /// owns each token and its royalty rule.
+    fn authorized(&self, parent: &Hash, block: &Block) -> Result<Option<Ledger>> {
+        match self.ledger_of(parent) {
+            Some(ledger) => ledger.folded(Some(block), self.royalty_rule.as_deref()),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the ledger after a known block by folding forward from its closest
+    /// ancestor with a known ledger, or [None] if there isn't one, such as for
+    /// blocks below the snapshot the chain was pruned to.
+    fn ledger_of(&self, hash: &Hash) -> Option<Ledger> {
+        let mut node = self.nodes.get(hash)?;
+        let mut path = vec![];
+        let mut ledger = loop {
+            match &node.ledger {
+                Some(ledger) => break ledger.clone(),
+                None => {
+                    path.push(&node.block);
+                    node = self.nodes.get(node.parent.as_ref()?)?;
+                }
+            }
+        };
+        path.into_iter()
+            .rev()
+            .all(|block| ledger.apply(block))
+            .then_some(ledger)
+    }
+
+    /// Gets the branch information of a known tip for fork-choice rules.
+    fn info(&self, tip: &Hash) -> Branch<'_> {
+        let node = &self.nodes[tip];
+        Branch {
+            tip: &node.block,
+            height: node.height,
+            seen: node.seen,
+        }
+    }
+}
+
+/// Information about a single branch of a [ChainTree], given to [ForkChoice] rules
+#[derive(Debug, Clone, Copy)]
+pub struct Branch<'a> {
+    /// Latest block of this branch.
+    pub tip: &'a Block,
+    /// Height of the latest block, with the genesis block being at `0`.
+    pub height: usize,
+    /// Time the latest block was first seen by the tree.
+    pub seen: SystemTime,
+}
+
+/// Custom fork-choice rule used by [ForkChoice::Custom]
+pub type CustomRule = Box<dyn Fn(&Branch, &Branch) -> Ordering + Send + Sync>;
+
+/// Rule used to decide which branch of a [ChainTree] is preferred
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+///
+/// // prefer the shortest branch, for whatever reason
+/// let rule = ForkChoice::Custom(Box::new(|a, b| b.height.cmp(&a.height)));
+/// ```
+#[derive(Default)]
+pub enum ForkChoice {
+    /// Prefers the highest branch, then the earliest seen if heights are equal.
+    #[default]
+    Longest,
+    /// Prefers the branch whose latest block has the earliest timestamp, then the
+    /// lowest hash if timestamps are equal, so every node picks the same one.
+    Earliest,
+    /// Custom rule, returning [Ordering::Greater] if the first branch is preferred.
+    Custom(CustomRule),
+}
+
+impl ForkChoice {
+    /// Compares two branches, returning [Ordering::Greater] if `a` is preferred.
+    pub fn compare(&self, a: &Branch, b: &Branch) -> Ordering {
+        match self {
+            Self::Longest => a.height.cmp(&b.height).then(b.seen.cmp(&a.seen)),
+            Self::Earliest => {
+                (b.tip.timestamp, &b.tip.hash.0).cmp(&(a.tip.timestamp, &a.tip.hash.0))
+            }
+            Self::Custom(rule) => rule(a, b),
+        }
+    }
+}
+
+/// Blocks needed to switch a chain from one branch over to another
+#[derive(Debug, Clone)]
+pub struct Reorg {
+    /// Hash of the latest block both branches share.
+    pub ancestor: Hash,
+    /// Blocks to roll back, starting from the current tip.
+    pub rollback: Vec<Block>,
+    /// Blocks to apply in order after rolling back.
+    pub apply: Vec<Block>,
+}
+
+impl Reorg {
+    /// Applies this reorganization to a chain which is currently on the rolled
+    /// back branch, verifying each new block beforehand and leaving the chain
+    /// untouched if any of them don't verify.
+    pub fn apply(&self, chain: &mut Chain) -> Result<bool> {
+        let ancestor = chain
+            .blocks
+            .iter()
+            .position(|block| block.hash == self.ancestor)
+            .ok_or(Error::UnknownBlock)?;
+
+        let mut previous = &chain.blocks[ancestor];
+        for block in self.apply.iter() {
+            if !chain.verify_link(previous, block)? {
+                return Ok(false);
+            }
+            previous = block
+        }
+        // rolled back blocks can't be undone, so the ancestor needs replaying
+        let ledger = if ancestor == chain.blocks.len() - 1 {
+            chain.ledger.clone()
+        } else {
+            match Ledger::replay_to(chain, ancestor) {
+                Some(ledger) => ledger,
+                None => return Ok(false),
+            }
+        };
+        chain.ledger = match ledger.folded(&self.apply, chain.royalty_rule.as_deref())? {
+            Some(ledger) => ledger,
+            None => return Ok(false),
+        };
+        chain.blocks.truncate(ancestor + 1);
+        chain.blocks.extend(self.apply.iter().cloned());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transfer;
+    use openssl::pkey::PKey;
+    use std::time::Duration;
+
+    /// Creates two chains which fork after a shared first block, with the
+    /// second chain being one block longer
+    fn forked() -> (Chain, Chain) {
+        let mut ours = Chain::default();
+        ours.push_data("shared").unwrap();
+        let mut theirs = ours.clone();
+        ours.push_data("ours").unwrap();
+        theirs.extend_data(vec!["theirs", "more"]).unwrap();
+        (ours, theirs)
+    }
+
+    #[test]
+    fn common_ancestor() {
+        let (ours, theirs) = forked();
+        let mut tree = ChainTree::new(&ours);
+        tree.extend(&theirs).unwrap();
+
+        assert_eq!(tree.tips().len(), 2);
+        assert_eq!(
+            tree.common_ancestor(&ours.tip().hash, &theirs.tip().hash),
+            Some(&ours.blocks[1].hash)
+        );
+    }
+
+    #[test]
+    fn longest_reorg() {
+        let (mut ours, theirs) = forked();
+        let mut tree = ChainTree::new(&ours);
+        tree.extend(&theirs).unwrap();
+
+        let reorg = tree
+            .fork_choice(&ours.tip().hash, &ForkChoice::Longest)
+            .unwrap()
+            .unwrap();
+        assert_eq!(reorg.rollback.len(), 1);
+        assert_eq!(reorg.apply.len(), 2);
+
+        assert!(reorg.apply(&mut ours).unwrap());
+        assert_eq!(ours.tip().hash, theirs.tip().hash);
+        assert!(ours.verify().unwrap());
+    }
+
+    #[test]
+    fn earliest_and_custom() {
+        let mut ours = Chain::default();
+        ours.push_data("shared").unwrap();
+        let mut theirs = ours.clone();
+        theirs.extend_data(vec!["theirs", "more"]).unwrap();
+        let dated = Block::new_at(&ours.tip().hash, "ours", theirs.tip().timestamp + 60).unwrap();
+        ours.push_sealed(dated).unwrap();
+
+        // judged by signed timestamps rather than when blocks arrived
+        let mut tree = ChainTree::new(&ours);
+        let later = SystemTime::now() + Duration::from_secs(60);
+        tree.insert_at(&theirs.blocks[1].hash, theirs.blocks[2].clone(), later)
+            .unwrap();
+        tree.insert_at(&theirs.blocks[2].hash, theirs.blocks[3].clone(), later)
+            .unwrap();
+
+        let current = &ours.tip().hash;
+        let reorg = tree
+            .fork_choice(current, &ForkChoice::Earliest)
+            .unwrap()
+            .unwrap();
+        assert_eq!(reorg.apply.last().unwrap().hash, theirs.tip().hash);
+
+        let shortest = ForkChoice::Custom(Box::new(|a, b| b.height.cmp(&a.height)));
+        // rules can be shared with other threads, such as by async nodes
+        fn shareable(_: &(impl Send + Sync)) {}
+        shareable(&shortest);
+        assert!(tree.fork_choice(current, &shortest).unwrap().is_none());
+        assert_eq!(tree.best_tip(&ForkChoice::Longest), &theirs.tip().hash);
+    }
+
+    #[test]
+    fn forks_follow_ownership() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut ours = Chain::default();
+        let token = ours.mint("token", &alice).unwrap();
+        ours.extend_data(vec!["first", "second"]).unwrap();
+        let mut tree = ChainTree::new(&ours);
+
+        // forks off an earlier block are checked against the ledger there
+        let fork = &ours.blocks[2].hash;
+        let stolen = Transfer::new(token.clone(), &bob).unwrap();
+        let stolen = Block::new_signed(fork, stolen.to_bytes().unwrap(), &bob).unwrap();
+        assert!(!tree.insert(fork, stolen).unwrap());
+
+        let transfer = Transfer::new(token.clone(), &bob).unwrap();
+        let transfer = Block::new_signed(fork, transfer.to_bytes().unwrap(), &alice).unwrap();
+        let hash = transfer.hash.clone();
+        assert!(tree.insert(fork, transfer).unwrap());
+        let back = Transfer::new(token.clone(), &alice).unwrap();
+        let back = Block::new_signed(&hash, back.to_bytes().unwrap(), &bob).unwrap();
+        assert!(tree.insert(&hash, back).unwrap());
+
+        let branch = tree.branch(tree.best_tip(&ForkChoice::Longest)).unwrap();
+        assert_eq!(branch.ledger(), &Ledger::replay(&branch));
+        assert!(branch.verify().unwrap());
+    }
+
+    #[test]
+    fn checks_like_reorgs() {
+        let keypair = PKey::generate_ed25519().unwrap();
+        let (mut ours, theirs) = forked();
+        let mut tree = ChainTree::new(&ours);
+
+        // blocks going back in time would fail the reorg onto them
+        let fork = &theirs.blocks[1];
+        let backdated = fork.timestamp.saturating_sub(1);
+        let backdated = Block::new_signed_at(fork, "past", &keypair, backdated).unwrap();
+        assert!(backdated.verify(fork).unwrap());
+        assert!(!tree.insert(&fork.hash, backdated.clone()).unwrap());
+        assert!(tree.get(&backdated.hash).is_none());
+
+        tree.extend(&theirs).unwrap();
+        let reorg = tree
+            .fork_choice(&ours.tip().hash, &ForkChoice::Longest)
+            .unwrap()
+            .unwrap();
+        assert!(reorg.apply(&mut ours).unwrap());
+        assert_eq!(ours.tip().hash, theirs.tip().hash);
+    }
+
+    #[test]
+    fn sparse_ledgers() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut ours = Chain::default();
+        let token = ours.mint("token", &alice).unwrap();
+        ours.extend_data(vec!["filler"; LEDGER_INTERVAL * 2])
+            .unwrap();
+        let mut tree = ChainTree::new(&ours);
+        let mut theirs = ours.clone();
+        theirs.extend_data(vec!["more"; 8]).unwrap();
+        tree.extend(&theirs).unwrap();
+
+        // only the genesis block, every interval and the tips keep ledgers
+        let kept = tree.nodes.values().filter(|node| node.ledger.is_some());
+        assert_eq!(kept.count(), 4);
+
+        // forks in between still fold forward from the closest ledger
+        let fork = &ours.blocks[LEDGER_INTERVAL + 5].hash;
+        let stolen = Transfer::new(token.clone(), &bob).unwrap();
+        let stolen = Block::new_signed(fork, stolen.to_bytes().unwrap(), &bob).unwrap();
+        assert!(!tree.insert(fork, stolen).unwrap());
+        let transfer = Transfer::new(token, &bob).unwrap();
+        let transfer = Block::new_signed(fork, transfer.to_bytes().unwrap(), &alice).unwrap();
+        let hash = transfer.hash.clone();
+        assert!(tree.insert(fork, transfer).unwrap());
+        let branch = tree.branch(&hash).unwrap();
+        assert_eq!(branch.ledger(), tree.nodes[&hash].ledger.as_ref().unwrap());
+    }
+
+    #[test]
+    fn unknown_parent() {
+        let (ours, theirs) = forked();
+        let mut tree = ChainTree::new(&ours);
+
+        assert!(matches!(
+            tree.insert(&theirs.blocks[2].hash, theirs.blocks[3].clone()),
+            Err(Error::UnknownBlock)
+        ));
+        assert!(!tree
+            .insert(&ours.blocks[0].hash, theirs.blocks[2].clone())
+            .unwrap());
+    }
+}
@@ -4,6 +4,9 @@ use crate::error::{Error, SignerError, VerifierError};
 use crate::{Block, Result, DEFAULT_GENESIS};
 use openssl::pkey::{HasPublic, PKey, PKeyRef, Private};
 use openssl::{sha::Sha256, sign::Signer, sign::Verifier};
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{convert::TryInto, fmt, str::FromStr};
 
 /// Hash for a block allowing full blockchain usage
 ///
@@ -17,7 +20,9 @@ use openssl::{sha::Sha256, sign::Signer, sign::Verifier};
 ///
 /// - Create a genesis hash: [Hash::default]
 /// - Create a hash containing hashed data: [Hash::new]
+/// - Create a hash signed by a known keypair: [Hash::new_existing_keypair]
 /// - Verify a hash: [Hash::verify]
+/// - Check a hash against a proof-of-work difficulty: [Hash::meets_difficulty]
 /// - Get the length of a hash signature: [Hash::SIG_LEN]
 ///
 /// # Example
@@ -40,13 +45,28 @@ use openssl::{sha::Sha256, sign::Signer, sign::Verifier};
 ///     Ok(())
 /// }
 /// ```
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Hash([u8; 32]);
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Hash(pub(crate) [u8; 32]);
 
 impl Hash {
     /// Length of ED25518-based signatures in bytes
     pub const SIG_LEN: usize = 64;
+
+    /// Counts the amount of leading zero bits in this hash.
+    pub fn leading_zeros(&self) -> u32 {
+        let zero_bytes = self.0.iter().take_while(|byte| **byte == 0).count();
+        let partial = self
+            .0
+            .get(zero_bytes)
+            .map_or(0, |byte| byte.leading_zeros());
+        zero_bytes as u32 * 8 + partial
+    }
+
+    /// Checks if this hash meets a proof-of-work `difficulty` target, being the
+    /// amount of leading zero bits it needs to have.
+    pub fn meets_difficulty(&self, difficulty: u32) -> bool {
+        self.leading_zeros() >= difficulty
+    }
 }
 
 impl<'a> Hash {
@@ -107,21 +127,41 @@ impl<'a> Hash {
         pkey: &PKeyRef<impl HasPublic>,
     ) -> Result<bool> {
         let mut verifier = Verifier::new_without_digest(pkey).map_err(VerifierError::Create)?;
+        let previous = previous.into();
         let data_hash = data_hash.into();
         let signature_verified = verifier
-            .verify_oneshot(signature.as_ref(), &data_hash[..])
+            .verify_oneshot(signature.as_ref(), &signed_message(previous, data_hash))
             .map_err(VerifierError::Execute)?;
 
         Ok(if signature_verified {
-            self.0 == hash_triplet(previous.into(), signature, data_hash)
+            self.0 == hash_triplet(previous, signature, data_hash)
         } else {
             false
         })
     }
 
-    /// Creates a new hash from the previous one alongside hte core data included
-    /// within the hash, manually inputting the public/private keypair.
-    fn new_existing_keypair(
+    /// Creates a new hash from the previous one alongside the core data included
+    /// within the hash, manually inputting the public/private keypair which is
+    /// used to sign it; returning this hash, the signature and the keypair.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    /// use openssl::pkey::PKey;
+    ///
+    /// fn main() -> onft::Result<()> {
+    ///     let genesis_hash = Hash::default();
+    ///     let keypair = PKey::generate_ed25519().unwrap();
+    ///
+    ///     let data = BlockData::new("Hello, world!")?;
+    ///     let (new_hash, _, _) = Hash::new_existing_keypair(&genesis_hash, data, keypair)?;
+    ///
+    ///     println!("Hash:\n{:?}", new_hash);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new_existing_keypair(
         previous: impl Into<&'a Hash>,
         data_hash: impl Into<[u8; 32]>,
         keypair: PKey<Private>,
@@ -131,20 +171,33 @@ impl<'a> Hash {
         let mut signer =
             Signer::new_without_digest(&keypair_signer).map_err(SignerError::Create)?;
 
+        let previous = previous.into();
         let data_hash = data_hash.into();
         let mut signature = [0; Self::SIG_LEN];
         signer
-            .sign_oneshot(&mut signature, &data_hash[..])
+            .sign_oneshot(&mut signature, &signed_message(previous, data_hash))
             .map_err(SignerError::Update)?;
 
         Ok((
-            Self(hash_triplet(previous.into(), signature, data_hash)),
+            Self(hash_triplet(previous, signature, data_hash)),
             signature,
             keypair,
         ))
     }
 }
 
+impl Hash {
+    /// Creates the hash of a genesis block containing data with the given hash,
+    /// which is signed by nobody.
+    pub(crate) fn new_genesis(data_hash: [u8; 32]) -> Self {
+        Self(hash_triplet(
+            &Hash::default(),
+            [0; Self::SIG_LEN],
+            data_hash,
+        ))
+    }
+}
+
 impl Default for Hash {
     /// Creates default genesis hash.
     fn default() -> Self {
@@ -164,11 +217,100 @@ impl<'a> From<&'a Block> for &'a Hash {
     }
 }
 
+impl fmt::Display for Hash {
+    /// Formats this hash as lowercase hexadecimal.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", to_hex(&self.0[..]))
+    }
+}
+
+impl FromStr for Hash {
+    type Err = Error;
+
+    /// Parses a hash from hexadecimal, as formatted by [struct@Hash]'s display.
+    fn from_str(s: &str) -> Result<Self> {
+        from_hex(s)
+            .and_then(|bytes| bytes[..].try_into().ok())
+            .map(Self)
+            .ok_or(Error::InvalidHash)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Hash {
+    /// Serializes as hexadecimal for human-readable formats and as raw bytes
+    /// otherwise.
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Hash {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(|err| de::Error::custom(format!("{}", err)))
+        } else {
+            <[u8; 32]>::deserialize(deserializer).map(Self)
+        }
+    }
+}
+
+/// Encodes bytes as lowercase hexadecimal, as used for hashes and keys.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes hexadecimal into bytes, returning [None] if it's invalid.
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
 fn gen_keypair() -> Result<PKey<Private>> {
     PKey::generate_ed25519().map_err(Error::KeyGen)
 }
 
-fn hash_triplet(previous: &Hash, signature: impl AsRef<[u8]>, data_hash: [u8; 32]) -> [u8; 32] {
+/// Creates the message which is signed for a block, binding the block's data to
+/// the block it follows so signatures can't be replayed elsewhere.
+pub(crate) fn signed_message(previous: &Hash, data_hash: [u8; 32]) -> [u8; 64] {
+    let mut message = [0; 64];
+    message[..32].copy_from_slice(&previous.0[..]);
+    message[32..].copy_from_slice(&data_hash[..]);
+    message
+}
+
+/// Binds a block's `data_hash` to its `timestamp`, which is what the block's
+/// signature commits to.
+pub(crate) fn commitment(data_hash: [u8; 32], timestamp: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(&data_hash[..]);
+    hasher.update(&timestamp.to_le_bytes());
+    hasher.finish()
+}
+
+pub(crate) fn hash_triplet(
+    previous: &Hash,
+    signature: impl AsRef<[u8]>,
+    data_hash: [u8; 32],
+) -> [u8; 32] {
     let mut hasher = Sha256::new();
     hasher.update(&previous.0[..]);
     hasher.update(signature.as_ref());
@@ -198,4 +340,21 @@ mod tests {
             panic!("Valid hash not verified successfully")
         }
     }
+
+    #[test]
+    fn replayed_signature() {
+        let data = BlockData::new("Hello, world!").unwrap();
+        let (hash, signature, pkey) = Hash::new(&Hash::default(), &data).unwrap();
+        let replayed = Hash(hash_triplet(&hash, signature, data.hash));
+
+        assert!(!replayed.verify(&hash, signature, data, &pkey).unwrap());
+    }
+
+    #[test]
+    fn hex_roundtrip() {
+        let hash = Hash::default();
+        assert_eq!(hash.to_string().parse::<Hash>().unwrap(), hash);
+        assert!("not hex".parse::<Hash>().is_err());
+        assert!("abcd".parse::<Hash>().is_err());
+    }
 }
@@ -0,0 +1,411 @@
+//! Contains [BlockHeader], [HeaderChain] and implementations
+
+use crate::block::follows_in_time;
+use crate::hash::commitment;
+#[cfg(feature = "serde")]
+use crate::PROTO_VERSION;
+use crate::{
+    error::Error, Block, BlockData, Chain, Cosignature, Hash, Ownership, Result, DEFAULT_GENESIS,
+};
+#[cfg(feature = "serde")]
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+#[cfg(feature = "serde")]
+use std::convert::TryInto;
+
+/// Everything from a [Block] apart from its data payload
+///
+/// # Using
+///
+/// Block hashes only commit to the hash of their data, so a block's signature
+/// and link to the previous block can be verified without the data itself. This
+/// makes headers far smaller than blocks, which is useful for light clients that
+/// can't store every payload. You can, in high level terms, do the following:
+///
+/// - Get the header of a block: [Block::header]
+/// - Verify a header against the previous hash: [BlockHeader::verify]
+/// - Check a payload belongs to a header: [BlockHeader::check_payload]
+/// - Rebuild the full block using a payload: [BlockHeader::attach]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+///
+/// fn main() -> onft::Result<()> {
+///     let genesis_block = Block::default();
+///     let block = Block::new(&genesis_block, "Hello, world!")?;
+///
+///     let header = block.header();
+///     assert!(header.verify(&genesis_block.hash)?);
+///     assert!(header.check_payload("Hello, world!"));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlockHeader {
+    /// The hash of the block.
+    pub hash: Hash,
+    /// Ownership identifier of the block.
+    pub ownership: Ownership,
+    /// Signature of the block.
+    pub signature: [u8; Hash::SIG_LEN],
+    /// Cosignatures of the block, see [Block::cosignatures].
+    pub cosignatures: Vec<Cosignature>,
+    /// Hash of the block's data payload.
+    pub data_hash: [u8; 32],
+    /// Timestamp of the block, see [Block::timestamp].
+    pub timestamp: u64,
+    /// Nonce of the block, see [Block::nonce].
+    pub nonce: u64,
+}
+
+impl BlockHeader {
+    /// Verifies this header's signature and link based upon the known hash of
+    /// the last block, in the same way as [Block::verify].
+    pub fn verify(&self, previous_hash: &Hash) -> Result<bool> {
+        let data_hash = commitment(self.data_hash, self.timestamp);
+        match &self.ownership {
+            Ownership::Them(pkey) => {
+                self.hash
+                    .verify(previous_hash, self.signature, data_hash, pkey)
+            }
+            Ownership::Us(pkey) => self
+                .hash
+                .verify(previous_hash, self.signature, data_hash, pkey),
+            Ownership::MultiSig(multisig) => multisig.verify(
+                &self.hash,
+                previous_hash,
+                self.signature,
+                &self.cosignatures,
+                data_hash,
+            ),
+            Ownership::Genesis => Err(Error::GenesisIsNotKey),
+        }
+    }
+
+    /// Checks if the given payload is the data this header commits to.
+    pub fn check_payload(&self, payload: impl AsRef<[u8]>) -> bool {
+        let payload = payload.as_ref();
+        if self.hash == Hash::default() && self.data_hash == DEFAULT_GENESIS {
+            // default genesis block has no real data hash
+            payload.is_empty()
+        } else {
+            BlockData::hash_of(payload) == self.data_hash
+        }
+    }
+
+    /// Rebuilds the full block from this header and its payload, returning
+    /// [None] if the payload isn't the data this header commits to.
+    pub fn attach(&self, payload: impl Into<Vec<u8>>) -> Option<Block> {
+        let payload = payload.into();
+        if !self.check_payload(&payload) {
+            return None;
+        }
+        Some(Block {
+            hash: self.hash.clone(),
+            ownership: self.ownership.clone(),
+            signature: self.signature,
+            cosignatures: self.cosignatures.clone(),
+            data: BlockData {
+                inner: payload,
+                hash: self.data_hash,
+            },
+            timestamp: self.timestamp,
+            nonce: self.nonce,
+        })
+    }
+
+    /// Checks if this is a valid genesis header, either being the default one or
+    /// having a hash matching its data hash.
+    pub(crate) fn verify_genesis(&self) -> bool {
+        matches!(self.ownership, Ownership::Genesis)
+            && if self.hash == Hash::default() {
+                self.data_hash == DEFAULT_GENESIS
+            } else {
+                self.hash == Hash::new_genesis(self.data_hash)
+            }
+    }
+}
+
+impl Block {
+    /// Gets the header of this block, which is everything apart from its data
+    /// payload.
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            hash: self.hash.clone(),
+            ownership: self.ownership.clone(),
+            signature: self.signature,
+            cosignatures: self.cosignatures.clone(),
+            data_hash: self.data.hash,
+            timestamp: self.timestamp,
+            nonce: self.nonce,
+        }
+    }
+}
+
+impl From<&Block> for BlockHeader {
+    fn from(block: &Block) -> Self {
+        block.header()
+    }
+}
+
+/// Chain of block headers for verifying a chain without storing its payloads
+///
+/// # Using
+///
+/// A header chain verifies that every header is correctly signed and linked to
+/// the one before it, just like [Chain::verify] does for blocks. Payloads can
+/// then be attached to individual headers as they're needed, which are checked
+/// against the header's data hash. You can, in high level terms, do the following:
+///
+/// - Create a header chain from a full chain: [HeaderChain::from]
+/// - Create a header chain from existing headers: [HeaderChain::try_from]
+/// - Add a new header which follows on from the latest one: [HeaderChain::push]
+/// - Verify every header one-by-one: [HeaderChain::verify]
+/// - Attach a payload to a header: [HeaderChain::attach]
+/// - Get a full block once its payload is attached: [HeaderChain::block]
+///
+/// Rules which depend on payloads, such as a [Policy](crate::Policy) or
+/// [Consensus](crate::Consensus), can only be checked on full chains.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use onft::HeaderChain;
+///
+/// fn main() -> onft::Result<()> {
+///     let mut chain = Chain::default();
+///     chain.extend_data(vec!["Hello", "world"])?;
+///
+///     let mut headers = HeaderChain::from(&chain);
+///     assert!(headers.verify()?);
+///
+///     assert!(!headers.attach(1, "world")?);
+///     assert!(headers.attach(1, "Hello")?);
+///     assert_eq!(headers.block(1).unwrap().data.inner, b"Hello");
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    headers: Vec<BlockHeader>,
+    payloads: HashMap<usize, Vec<u8>>,
+}
+
+impl HeaderChain {
+    /// Verifies every header from the genesis header onwards.
+    pub fn verify(&self) -> Result<bool> {
+        let (genesis, headers) = self.headers.split_first().ok_or(Error::ChainEmpty)?;
+        if !genesis.verify_genesis() {
+            return Ok(false);
+        }
+
+        let mut previous = genesis;
+        for header in headers.iter() {
+            if !header.verify(&previous.hash)?
+                || !follows_in_time(previous.timestamp, header.timestamp)
+            {
+                return Ok(false);
+            }
+            previous = header
+        }
+        Ok(true)
+    }
+
+    /// Adds a new header following on from the latest one, verifying it
+    /// beforehand and returning if it was valid.
+    pub fn push(&mut self, header: BlockHeader) -> Result<bool> {
+        let tip = self.tip();
+        if !header.verify(&tip.hash)? || !follows_in_time(tip.timestamp, header.timestamp) {
+            return Ok(false);
+        }
+        self.headers.push(header);
+        Ok(true)
+    }
+
+    /// Attaches a payload to the header at `height`, returning `false` if the
+    /// payload isn't the data the header commits to.
+    pub fn attach(&mut self, height: usize, payload: impl Into<Vec<u8>>) -> Result<bool> {
+        let header = self.headers.get(height).ok_or(Error::UnknownBlock)?;
+        let payload = payload.into();
+        if !header.check_payload(&payload) {
+            return Ok(false);
+        }
+        self.payloads.insert(height, payload);
+        Ok(true)
+    }
+
+    /// Removes the payload attached to the header at `height`, if any.
+    pub fn detach(&mut self, height: usize) -> Option<Vec<u8>> {
+        self.payloads.remove(&height)
+    }
+
+    /// Gets the full block at `height` if its payload has been attached.
+    pub fn block(&self, height: usize) -> Option<Block> {
+        let payload = self.payloads.get(&height)?;
+        self.headers.get(height)?.attach(payload.clone())
+    }
+
+    /// Gets every header in order, starting with the genesis header.
+    pub fn headers(&self) -> &[BlockHeader] {
+        &self.headers[..]
+    }
+
+    /// Gets the latest header.
+    pub fn tip(&self) -> &BlockHeader {
+        // never empty due to the chain invariant
+        &self.headers[self.headers.len() - 1]
+    }
+}
+
+impl From<&Chain> for HeaderChain {
+    /// Creates a header chain from the headers of a full chain, without
+    /// attaching any payloads.
+    fn from(chain: &Chain) -> Self {
+        Self {
+            headers: chain.blocks.iter().map(Block::header).collect(),
+            payloads: HashMap::new(),
+        }
+    }
+}
+
+impl TryFrom<Vec<BlockHeader>> for HeaderChain {
+    type Error = Error;
+
+    /// Creates a header chain from existing headers, with the same checks as
+    /// [Chain::try_from].
+    fn try_from(headers: Vec<BlockHeader>) -> Result<Self> {
+        match headers.first() {
+            Some(header) if matches!(header.ownership, Ownership::Genesis) => (),
+            Some(_) => return Err(Error::ChainNoGenesis),
+            None => return Err(Error::ChainEmpty),
+        }
+        match headers[1..]
+            .iter()
+            .position(|header| matches!(header.ownership, Ownership::Genesis))
+        {
+            Some(index) => Err(Error::ChainGenesisMisplaced(index + 1)),
+            None => Ok(Self {
+                headers,
+                payloads: HashMap::new(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for BlockHeader {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let cosignatures: Vec<_> = self
+            .cosignatures
+            .iter()
+            .map(|(index, signature)| (index, &signature[..]))
+            .collect();
+        let mut state = serializer.serialize_struct("BlockHeader", 7 + 1)?;
+        state.serialize_field("pver", &PROTO_VERSION)?; // custom protocol version
+        state.serialize_field("hash", &self.hash)?;
+        state.serialize_field("ownership", &self.ownership)?;
+        state.serialize_field("signature", &self.signature[..])?;
+        state.serialize_field("data_hash", &self.data_hash)?;
+        state.serialize_field("timestamp", &self.timestamp)?;
+        state.serialize_field("nonce", &self.nonce)?;
+        state.serialize_field("cosignatures", &cosignatures)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for BlockHeader {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        /// Mirror of the serialized layout of a header
+        #[derive(Deserialize)]
+        struct RawHeader {
+            pver: u8,
+            hash: Hash,
+            ownership: Ownership,
+            signature: Vec<u8>,
+            data_hash: [u8; 32],
+            #[serde(default)]
+            timestamp: u64,
+            #[serde(default)]
+            nonce: u64,
+            #[serde(default)]
+            cosignatures: Vec<(u8, Vec<u8>)>,
+        }
+
+        let raw = RawHeader::deserialize(deserializer)?;
+        if raw.pver != PROTO_VERSION {
+            return Err(de::Error::custom(format!(
+                "Header uses protocol version {} but {} is supported",
+                raw.pver, PROTO_VERSION
+            )));
+        }
+        let signature = raw.signature[..]
+            .try_into()
+            .map_err(|_| de::Error::invalid_length(raw.signature.len(), &"a 64 byte signature"))?;
+
+        Ok(Self {
+            hash: raw.hash,
+            ownership: raw.ownership,
+            signature,
+            cosignatures: crate::block::raw_cosignatures(raw.cosignatures)?,
+            data_hash: raw.data_hash,
+            timestamp: raw.timestamp,
+            nonce: raw.nonce,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_without_payloads() {
+        let mut chain = Chain::default();
+        chain.extend_data(vec!["first", "second"]).unwrap();
+        let mut headers = HeaderChain::from(&chain);
+        assert!(headers.verify().unwrap());
+
+        let extra = Block::new(chain.tip(), "third").unwrap();
+        assert!(!headers
+            .push(Block::new(&chain.blocks[1], "third").unwrap().header())
+            .unwrap());
+        assert!(headers.push(extra.header()).unwrap());
+        assert!(headers.verify().unwrap());
+
+        let mut tampered = headers.headers().to_vec();
+        tampered[2].data_hash = [0; 32];
+        assert!(!HeaderChain::try_from(tampered).unwrap().verify().unwrap());
+    }
+
+    #[test]
+    fn attach_payloads() {
+        let mut chain = Chain::default();
+        chain.push_data("Hello, world!").unwrap();
+        let mut headers = HeaderChain::from(&chain);
+
+        assert!(headers.block(1).is_none());
+        assert!(!headers.attach(1, "Goodbye").unwrap());
+        assert!(headers.attach(1, "Hello, world!").unwrap());
+        assert!(headers.attach(0, vec![]).unwrap());
+        assert!(matches!(
+            headers.attach(2, "missing"),
+            Err(Error::UnknownBlock)
+        ));
+
+        let block = headers.block(1).unwrap();
+        assert_eq!(block.data, chain.tip().data);
+        assert!(block.verify(&chain.blocks[0]).unwrap());
+        assert_eq!(headers.detach(1).unwrap(), b"Hello, world!");
+    }
+}
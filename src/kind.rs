@@ -0,0 +1,437 @@
+//! Contains [BlockKind] and implementations
+
+use crate::{
+    error::Error, Approval, Block, BlockData, Burn, Chain, Collection, CollectionId, Edition, Lock,
+    Policy, Result, Rotation, Settlement, TokenId, Transfer,
+};
+use openssl::pkey::{PKey, Private};
+use std::convert::TryInto;
+
+/// Prefix of block data containing a payload which onft itself understands
+pub(crate) const PAYLOAD_MAGIC: [u8; 4] = *b"\0nft";
+
+/// Tag identifying a [BlockKind::Transfer] to a single key
+pub(crate) const TRANSFER_TAG: u8 = 1;
+/// Tag identifying a [BlockKind::Policy]
+pub(crate) const POLICY_TAG: u8 = 2;
+/// Tag identifying a [BlockKind::Burn]
+pub(crate) const BURN_TAG: u8 = 3;
+/// Tag identifying a [BlockKind::Mint]
+const MINT_TAG: u8 = 4;
+/// Tag identifying a [BlockKind::MetadataUpdate]
+const METADATA_TAG: u8 = 5;
+/// Tag identifying a [BlockKind::CreateCollection]
+pub(crate) const COLLECTION_TAG: u8 = 6;
+/// Tag identifying a [BlockKind::MintEditions]
+const EDITIONS_TAG: u8 = 7;
+/// Tag identifying a [BlockKind::Settlement]
+pub(crate) const SETTLEMENT_TAG: u8 = 8;
+/// Tag identifying a [BlockKind::Transfer] to a [MultiSig](crate::MultiSig)
+pub(crate) const MULTISIG_TRANSFER_TAG: u8 = 9;
+/// Tag identifying a [BlockKind::Lock]
+pub(crate) const LOCK_TAG: u8 = 10;
+/// Tag identifying a [BlockKind::Release]
+const RELEASE_TAG: u8 = 11;
+/// Tag identifying a [BlockKind::Refund]
+const REFUND_TAG: u8 = 12;
+/// Tag identifying a [BlockKind::Approve]
+const APPROVE_TAG: u8 = 13;
+/// Tag identifying a [BlockKind::Revoke]
+const REVOKE_TAG: u8 = 14;
+/// Tag identifying a [BlockKind::Rotation]
+const ROTATION_TAG: u8 = 15;
+
+/// Encodes a `payload` tagged with its kind into bytes to be used as block
+/// data, see [BlockKind::from_data] for decoding it.
+pub(crate) fn tagged(tag: u8, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = PAYLOAD_MAGIC.to_vec();
+    bytes.push(tag);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Operation a block performs, encoded inside of its signed data
+///
+/// # Using
+///
+/// Data which onft understands starts with a short magic prefix followed by a
+/// tag for its kind, letting [Chain::verify] check that each operation is
+/// allowed. Every kind is decoded here, with the `from_data` functions on
+/// each payload type, such as [Transfer::from_data], picking out their own
+/// kind. Any other data is [BlockKind::Opaque], which is how blocks added
+/// using [Chain::push_data] and [Chain::push_signed] are treated; these still
+/// mint a token owned by their signer, just without any metadata.
+///
+/// Data which starts with the magic prefix but can't be decoded, such as an
+/// unknown tag, is kept on the chain as-is but never creates a token.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let owner = PKey::generate_ed25519().unwrap();
+///     let mut chain = Chain::default();
+///
+///     let token = chain.mint("Sword of a thousand truths", &owner)?;
+///     chain.update_metadata(&token, "Sword of a thousand lies", &owner)?;
+///
+///     assert!(matches!(chain.tip().kind()?, BlockKind::MetadataUpdate { .. }));
+///     assert_eq!(chain.ledger().metadata(&token), Some(&b"Sword of a thousand lies"[..]));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub enum BlockKind {
+    /// Arbitrary data which onft doesn't understand, minting a token without
+    /// any metadata.
+    Opaque(Vec<u8>),
+    /// Mints a new token owned by the signer with some initial metadata.
+    Mint {
+        /// Collection the token is minted into, which must be signed by the
+        /// collection's creator.
+        collection: Option<CollectionId>,
+        /// Metadata describing the new token.
+        metadata: Vec<u8>,
+    },
+    /// Transfers a token over to someone else, see [Transfer].
+    Transfer(Transfer),
+    /// Permanently retires a token, see [Burn].
+    Burn(Burn),
+    /// Mints a limited run of editions sharing the same initial metadata, see
+    /// [Edition](crate::Edition).
+    MintEditions {
+        /// Collection the editions are minted into, which must be signed by the
+        /// collection's creator and counts each edition towards its supply.
+        collection: Option<CollectionId>,
+        /// Amount of editions to mint, up to [Edition::MAX](crate::Edition::MAX).
+        editions: u32,
+        /// Metadata describing every edition.
+        metadata: Vec<u8>,
+    },
+    /// Replaces the metadata of a token, which must be signed by its owner.
+    MetadataUpdate {
+        /// Identifier of the updated token.
+        token: TokenId,
+        /// New metadata describing the token.
+        metadata: Vec<u8>,
+    },
+    /// Replaces the chain's authorities, see [Policy].
+    Policy(Policy),
+    /// Creates a new collection owned by the signer, see [Collection].
+    CreateCollection(Collection),
+    /// Records the royalties paid for a sale, see [Settlement].
+    Settlement(Settlement),
+    /// Locks a token until a condition is met, see [Lock].
+    Lock(Lock),
+    /// Completes the pending transfer of a locked token, which must be signed
+    /// by its recipient for time locks or its arbiter for escrow.
+    Release(TokenId),
+    /// Returns a token held in escrow to its owner, which must be signed by
+    /// its arbiter.
+    Refund(TokenId),
+    /// Approves a delegate to transfer a token or every token on behalf of the
+    /// signer, see [Approval].
+    Approve(Approval),
+    /// Revokes an earlier approval of a delegate, see [Approval].
+    Revoke(Approval),
+    /// Hands everything held by the signer over to a new key, see [Rotation].
+    Rotation(Rotation),
+}
+
+impl BlockKind {
+    /// Decodes the kind of operation some block data performs, returning
+    /// [Error::InvalidPayload] if it starts with the magic prefix but can't be
+    /// decoded.
+    pub fn from_data(data: &BlockData) -> Result<Self> {
+        let bytes = &data.inner[..];
+        if bytes.len() <= PAYLOAD_MAGIC.len() || bytes[..PAYLOAD_MAGIC.len()] != PAYLOAD_MAGIC {
+            return Ok(Self::Opaque(bytes.to_vec()));
+        }
+        let payload = &bytes[PAYLOAD_MAGIC.len() + 1..];
+        match bytes[PAYLOAD_MAGIC.len()] {
+            TRANSFER_TAG => Ok(Self::Transfer(Transfer::from_payload(payload)?)),
+            MULTISIG_TRANSFER_TAG => Ok(Self::Transfer(Transfer::from_multisig_payload(payload)?)),
+            POLICY_TAG => Ok(Self::Policy(Policy::from_payload(payload)?)),
+            BURN_TAG => Ok(Self::Burn(Burn::from_payload(payload)?)),
+            COLLECTION_TAG => Ok(Self::CreateCollection(Collection::from_payload(payload)?)),
+            SETTLEMENT_TAG => Ok(Self::Settlement(Settlement::from_payload(payload)?)),
+            LOCK_TAG => Ok(Self::Lock(Lock::from_payload(payload)?)),
+            MINT_TAG => {
+                let (collection, metadata) = split_collection(payload)?;
+                Ok(Self::Mint {
+                    collection,
+                    metadata: metadata.to_vec(),
+                })
+            }
+            EDITIONS_TAG if payload.len() >= 4 => {
+                let editions = u32::from_le_bytes(payload[..4].try_into().unwrap());
+                if editions == 0 || editions > Edition::MAX {
+                    return Err(Error::InvalidPayload);
+                }
+                let (collection, metadata) = split_collection(&payload[4..])?;
+                Ok(Self::MintEditions {
+                    collection,
+                    editions,
+                    metadata: metadata.to_vec(),
+                })
+            }
+            METADATA_TAG if payload.len() >= 32 => Ok(Self::MetadataUpdate {
+                token: TokenId(payload[..32].try_into().unwrap()),
+                metadata: payload[32..].to_vec(),
+            }),
+            RELEASE_TAG if payload.len() == 32 => {
+                Ok(Self::Release(TokenId(payload.try_into().unwrap())))
+            }
+            REFUND_TAG if payload.len() == 32 => {
+                Ok(Self::Refund(TokenId(payload.try_into().unwrap())))
+            }
+            APPROVE_TAG => Ok(Self::Approve(Approval::from_payload(payload)?)),
+            REVOKE_TAG => Ok(Self::Revoke(Approval::from_payload(payload)?)),
+            ROTATION_TAG => Ok(Self::Rotation(Rotation::from_payload(payload)?)),
+            _ => Err(Error::InvalidPayload),
+        }
+    }
+
+    /// Encodes this kind into bytes to be used as block data.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Opaque(data) => data.clone(),
+            Self::Mint {
+                collection,
+                metadata,
+            } => {
+                let mut bytes = tagged(MINT_TAG, &[]);
+                extend_collection(&mut bytes, collection);
+                bytes.extend_from_slice(metadata);
+                bytes
+            }
+            Self::MintEditions {
+                collection,
+                editions,
+                metadata,
+            } => {
+                let mut bytes = tagged(EDITIONS_TAG, &editions.to_le_bytes());
+                extend_collection(&mut bytes, collection);
+                bytes.extend_from_slice(metadata);
+                bytes
+            }
+            Self::Transfer(transfer) => transfer.to_bytes()?,
+            Self::Burn(burn) => burn.to_bytes(),
+            Self::MetadataUpdate { token, metadata } => {
+                let mut bytes = tagged(METADATA_TAG, &token.0[..]);
+                bytes.extend_from_slice(metadata);
+                bytes
+            }
+            Self::Policy(policy) => policy.to_bytes()?,
+            Self::CreateCollection(collection) => collection.to_bytes(),
+            Self::Settlement(settlement) => settlement.to_bytes(),
+            Self::Lock(lock) => lock.to_bytes()?,
+            Self::Release(token) => tagged(RELEASE_TAG, &token.0[..]),
+            Self::Refund(token) => tagged(REFUND_TAG, &token.0[..]),
+            Self::Approve(approval) => tagged(APPROVE_TAG, &approval.to_payload()?),
+            Self::Revoke(approval) => tagged(REVOKE_TAG, &approval.to_payload()?),
+            Self::Rotation(rotation) => tagged(
+                ROTATION_TAG,
+                &rotation.key.raw_public_key().map_err(Error::KeyPublic)?,
+            ),
+        })
+    }
+}
+
+/// Splits the optional collection a mint refers to off the front of its payload.
+fn split_collection(payload: &[u8]) -> Result<(Option<CollectionId>, &[u8])> {
+    match payload.split_first() {
+        Some((0, rest)) => Ok((None, rest)),
+        Some((1, rest)) if rest.len() >= 32 => Ok((
+            Some(CollectionId(rest[..32].try_into().unwrap())),
+            &rest[32..],
+        )),
+        _ => Err(Error::InvalidPayload),
+    }
+}
+
+/// Encodes the optional collection a mint refers to, see [split_collection].
+fn extend_collection(bytes: &mut Vec<u8>, collection: &Option<CollectionId>) {
+    match collection {
+        Some(collection) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&collection.0[..]);
+        }
+        None => bytes.push(0),
+    }
+}
+
+impl Block {
+    /// Decodes the kind of operation this block performs, see [BlockKind] for
+    /// more information.
+    pub fn kind(&self) -> Result<BlockKind> {
+        BlockKind::from_data(&self.data)
+    }
+}
+
+impl Chain {
+    /// Adds a new single block performing the given `kind` of operation, signed
+    /// by a known keypair; chainable method.
+    pub fn push_kind(&mut self, kind: &BlockKind, keypair: &PKey<Private>) -> Result<&mut Self> {
+        self.push_signed(kind.to_bytes()?, keypair)
+    }
+
+    /// Mints a new token with the given `metadata` owned by the `keypair`
+    /// which signs it, returning the new token's identifier.
+    pub fn mint(
+        &mut self,
+        metadata: impl Into<Vec<u8>>,
+        keypair: &PKey<Private>,
+    ) -> Result<TokenId> {
+        let kind = BlockKind::Mint {
+            collection: None,
+            metadata: metadata.into(),
+        };
+        self.push_kind(&kind, keypair)?;
+        self.token_id(&self.tip().hash)
+    }
+
+    /// Replaces the metadata of the `token`, adding a new block signed by its
+    /// current `owner`; chainable method.
+    pub fn update_metadata(
+        &mut self,
+        token: &TokenId,
+        metadata: impl Into<Vec<u8>>,
+        owner: &PKey<Private>,
+    ) -> Result<&mut Self> {
+        self.check_owner(token, owner)?;
+        let kind = BlockKind::MetadataUpdate {
+            token: token.clone(),
+            metadata: metadata.into(),
+        };
+        self.push_kind(&kind, owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChainTree, Condition, Delta, Reorg};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn roundtrip_kinds() {
+        let keypair = PKey::generate_ed25519().unwrap();
+        let kinds = vec![
+            BlockKind::Opaque(b"Hello, world!".to_vec()),
+            BlockKind::Mint {
+                collection: None,
+                metadata: b"metadata".to_vec(),
+            },
+            BlockKind::Mint {
+                collection: Some(CollectionId([4; 32])),
+                metadata: vec![],
+            },
+            BlockKind::Transfer(Transfer::new(TokenId([1; 32]), &keypair).unwrap()),
+            BlockKind::Burn(Burn::new(TokenId([2; 32]))),
+            BlockKind::MetadataUpdate {
+                token: TokenId([3; 32]),
+                metadata: vec![],
+            },
+            BlockKind::Policy(Policy::new(vec![&keypair]).unwrap()),
+            BlockKind::CreateCollection(Collection::new(Some(10), "collection")),
+            BlockKind::MintEditions {
+                collection: Some(CollectionId([5; 32])),
+                editions: 100,
+                metadata: b"editions".to_vec(),
+            },
+            BlockKind::Settlement(Settlement::new(TokenId([6; 32]), 100, 5)),
+            BlockKind::Lock(Lock::new(TokenId([7; 32]), &keypair, Condition::Height(9)).unwrap()),
+            BlockKind::Release(TokenId([8; 32])),
+            BlockKind::Refund(TokenId([9; 32])),
+            BlockKind::Approve(Approval::new(&keypair, Some(TokenId([10; 32]))).unwrap()),
+            BlockKind::Revoke(Approval::new(&keypair, None).unwrap()),
+            BlockKind::Rotation(Rotation::new(&keypair).unwrap()),
+        ];
+        for kind in kinds {
+            let data = BlockData::new(kind.to_bytes().unwrap()).unwrap();
+            let decoded = BlockKind::from_data(&data).unwrap();
+            assert_eq!(decoded.to_bytes().unwrap(), kind.to_bytes().unwrap());
+        }
+
+        for invalid in [
+            &b"\0nft\x05short"[..],
+            &b"\0nft\x04\x01short"[..],
+            &b"\0nft\xff"[..],
+        ] {
+            assert!(matches!(
+                BlockKind::from_data(&BlockData::new(invalid).unwrap()),
+                Err(Error::InvalidPayload)
+            ));
+        }
+    }
+
+    #[test]
+    fn metadata_updates() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("first", &alice).unwrap();
+        chain.push_signed("opaque", &alice).unwrap();
+        let opaque = chain.token_id(&chain.tip().hash).unwrap();
+
+        assert_eq!(chain.ledger().metadata(&token), Some(&b"first"[..]));
+        assert_eq!(chain.ledger().metadata(&opaque), Some(&b""[..]));
+        assert!(matches!(
+            chain.update_metadata(&token, "stolen", &bob),
+            Err(Error::NotOwner)
+        ));
+        chain.update_metadata(&token, "second", &alice).unwrap();
+        assert_eq!(chain.ledger().metadata(&token), Some(&b"second"[..]));
+    }
+
+    #[test]
+    fn tampered_payloads() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mallory = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("token", &alice).unwrap();
+        let checkpoint = chain.checkpoint(1).unwrap();
+        let mut honest = chain.clone();
+        honest.transfer(&token, &alice, &bob).unwrap();
+
+        // swapping the payload keeps the signature valid, as it only covers the
+        // data hash
+        let mut tampered = honest.tip().clone();
+        let stolen = Transfer::new(token.clone(), &mallory).unwrap();
+        tampered.data.inner = stolen.to_bytes().unwrap();
+        assert!(tampered.verify(&chain.tip().hash).unwrap());
+
+        let mut blocks: Vec<Block> = chain.clone().into();
+        blocks.push(tampered.clone());
+        let forged = Chain::try_from(blocks).unwrap();
+        assert!(!forged.verify().unwrap());
+        assert!(!forged.verify_from(&checkpoint).unwrap());
+
+        let tip = chain.tip().hash.clone();
+        let delta = Delta {
+            from: tip.clone(),
+            blocks: vec![tampered.clone()],
+        };
+        assert!(!chain.apply_delta(&delta).unwrap());
+        assert!(!ChainTree::new(&chain)
+            .insert(&tip, tampered.clone())
+            .unwrap());
+        let reorg = Reorg {
+            ancestor: tip,
+            rollback: vec![],
+            apply: vec![tampered],
+        };
+        assert!(!reorg.apply(&mut chain).unwrap());
+        assert!(chain
+            .ledger()
+            .owner(&token)
+            .unwrap()
+            .unwrap()
+            .public_eq(&alice));
+    }
+}
@@ -0,0 +1,871 @@
+//! Contains [Ledger] and implementations
+
+use crate::collection::CollectionRecord;
+use crate::lock::PendingLock;
+use crate::royalty::{split_royalties, RawRoyalties};
+use crate::{
+    error::Error, Block, BlockKind, Chain, CollectionId, Edition, Hash, Ownership, Policy, Result,
+    RoyaltyRule, Settlement, Snapshot, TokenId,
+};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Public};
+use openssl::sha::Sha256;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Amount of the latest blocks whose median timestamp is used by
+/// [Ledger::median_time]
+pub(crate) const MEDIAN_TIME_SPAN: usize = 11;
+
+/// Prefix of ledger digests, keeping them distinct from other hashes
+const LEDGER_DOMAIN: &[u8] = b"onft ledger";
+
+/// Index of who currently owns each token, folded from a chain's blocks
+///
+/// # Using
+///
+/// Every chain keeps its ledger up to date as new blocks are added, which can
+/// be borrowed using [Chain::ledger] to answer ownership questions without
+/// walking the chain's blocks. You can, in high level terms, do the following:
+///
+/// - Find the current owner of a token: [Ledger::owner] and [Ledger::ownership]
+/// - Find every token owned by a key: [Ledger::tokens_of]
+/// - Get the latest metadata of a token: [Ledger::metadata]
+/// - Check if a token has been burned: [Ledger::is_burned]
+/// - Look up collections and their supply: [Ledger::collection] and [Ledger::minted]
+/// - Find which edition a token is: [Ledger::edition] and [Ledger::editions]
+/// - Iterate over every known token: [Ledger::tokens]
+/// - Rebuild a ledger from scratch by replaying a chain: [Ledger::replay]
+/// - Hash the entire ledger, such as to sign it: [Ledger::digest]
+///
+/// Tokens are identified by their [TokenId], assigned when minted. Blocks
+/// which break the chain's rules, such as transfers by someone who isn't the
+/// token's owner, are left out of the ledger.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use onft::Ledger;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let alice = PKey::generate_ed25519().unwrap();
+///     let bob = PKey::generate_ed25519().unwrap();
+///
+///     let mut chain = Chain::default();
+///     chain.push_signed("Hello, world!", &alice)?;
+///     let token = chain.token_id(&chain.tip().hash)?;
+///     chain.transfer(&token, &alice, &bob)?;
+///
+///     let ledger = chain.ledger();
+///     assert!(ledger.tokens_of(&alice)?.is_empty());
+///     assert_eq!(ledger.tokens_of(&bob)?, vec![&token]);
+///
+///     // the same as folding the chain from scratch
+///     assert_eq!(ledger, &Ledger::replay(&chain));
+///     Ok(())
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ledger {
+    /// Hash of the genesis block of the chain, used to identify tokens.
+    pub(crate) chain: Hash,
+    /// Record of every token minted on the chain.
+    pub(crate) tokens: HashMap<TokenId, Record>,
+    /// Raw public keys of the current authorities, if the chain has a policy.
+    pub(crate) authorities: Option<HashSet<Vec<u8>>>,
+    /// Record of every collection created on the chain.
+    pub(crate) collections: HashMap<CollectionId, CollectionRecord>,
+    /// Height of the latest block applied, being its index in the chain.
+    pub(crate) height: u64,
+    /// Timestamps of the latest blocks applied, oldest first, keeping up to
+    /// [MEDIAN_TIME_SPAN] of them.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) timestamps: Vec<u64>,
+    /// Raw identities of owners alongside delegates approved for all of their
+    /// tokens.
+    pub(crate) operators: HashSet<(Vec<u8>, Vec<u8>)>,
+    /// Raw public keys which have been rotated out for good.
+    pub(crate) retired: HashSet<Vec<u8>>,
+    /// Settlements which no transfer has referenced yet, keyed by the hash of
+    /// the block they were recorded in.
+    pub(crate) settlements: HashMap<Hash, Settlement>,
+}
+
+/// Current state of a single token inside of a [Ledger]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Record {
+    /// Hash of the block which minted the token.
+    pub(crate) mint: Hash,
+    /// Raw public key of the token's current owner, or last owner if burned.
+    pub(crate) owner: Vec<u8>,
+    /// Latest metadata describing the token.
+    pub(crate) metadata: Vec<u8>,
+    /// If the token has been burned by its owner.
+    pub(crate) burned: bool,
+    /// Collection the token was minted into, if any.
+    pub(crate) collection: Option<CollectionId>,
+    /// Edition the token is, if it was minted as part of a run of editions.
+    pub(crate) edition: Option<Edition>,
+    /// Raw public keys and basis points of royalties owed on resale.
+    pub(crate) royalties: RawRoyalties,
+    /// Pending transfer the token is locked in, if any.
+    pub(crate) locked: Option<PendingLock>,
+    /// Raw public key of the delegate approved to transfer the token, if any.
+    pub(crate) approved: Option<Vec<u8>>,
+}
+
+/// Change a single block makes to a [Ledger] once it's been allowed
+enum Change {
+    Policy(HashSet<Vec<u8>>),
+    Collection(CollectionId, CollectionRecord),
+    Mint(Vec<(TokenId, Record)>),
+    Token(TokenId, Record),
+    Transfer(TokenId, Record, Option<Hash>),
+    Settlement(Hash, Settlement),
+    Operator((Vec<u8>, Vec<u8>), bool),
+    Rotation(Vec<u8>, Vec<u8>),
+    Nothing,
+}
+
+impl Ledger {
+    /// Rebuilds the ledger of a chain from scratch by replaying each of its
+    /// blocks, starting from the snapshot it was pruned to if there is one.
+    pub fn replay(chain: &Chain) -> Self {
+        // chains always contain the block they were pruned to
+        Self::replay_to(chain, chain.blocks.len() - 1).unwrap()
+    }
+
+    /// Rebuilds the ledger of a chain up to and including the block at
+    /// `height`, or [None] if that block is below the snapshot it was pruned to.
+    pub(crate) fn replay_to(chain: &Chain, height: usize) -> Option<Self> {
+        let (mut ledger, start) = match &chain.base {
+            Some(base) if height < base.height => return None,
+            Some(base) => (base.ledger.clone(), base.height + 1),
+            None => (Self::new(&chain.blocks[0]), 1),
+        };
+        for block in chain.blocks[start..=height].iter() {
+            ledger.apply(block);
+        }
+        Some(ledger)
+    }
+
+    /// Folds this ledger forward over new `blocks`, returning [None] if any of
+    /// them don't follow the chain's rules or the royalty `rule` if there is
+    /// one; see [Ledger::apply] for the rules.
+    pub(crate) fn folded<'a>(
+        mut self,
+        blocks: impl IntoIterator<Item = &'a Block>,
+        rule: Option<&dyn RoyaltyRule>,
+    ) -> Result<Option<Self>> {
+        for block in blocks {
+            if let Some(rule) = rule {
+                if !self.settles(rule, block)? {
+                    return Ok(None);
+                }
+            }
+            if !self.apply(block) {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self))
+    }
+
+    /// Gets the public key of the current owner of a token, or [None] if the
+    /// token isn't known to this ledger or has been burned.
+    ///
+    /// Tokens owned by a [MultiSig](crate::MultiSig) don't have a single public
+    /// key, so this returns an error for them; see [Ledger::ownership] instead.
+    pub fn owner(&self, token: &TokenId) -> Result<Option<PKey<Public>>> {
+        self.tokens
+            .get(token)
+            .filter(|record| !record.burned)
+            .map(|record| {
+                PKey::public_key_from_raw_bytes(&record.owner, Id::ED25519)
+                    .map_err(Error::KeyRawPublic)
+            })
+            .transpose()
+    }
+
+    /// Gets the current owner of a token, which may be a single public key or a
+    /// [MultiSig](crate::MultiSig), or [None] if the token isn't known to this
+    /// ledger or has been burned.
+    pub fn ownership(&self, token: &TokenId) -> Result<Option<Ownership>> {
+        self.tokens
+            .get(token)
+            .filter(|record| !record.burned)
+            .map(|record| Ownership::from_raw_owner(&record.owner))
+            .transpose()
+    }
+
+    /// Gets the hash of the block which minted a token, or [None] if the token
+    /// isn't known to this ledger.
+    pub fn mint(&self, token: &TokenId) -> Option<&Hash> {
+        self.tokens.get(token).map(|record| &record.mint)
+    }
+
+    /// Gets every token currently owned by the given public key, in no
+    /// particular order.
+    pub fn tokens_of(&self, owner: &PKeyRef<impl HasPublic>) -> Result<Vec<&TokenId>> {
+        let raw = owner.raw_public_key().map_err(Error::KeyPublic)?;
+        Ok(self
+            .tokens
+            .iter()
+            .filter(|(_, record)| !record.burned && record.owner == raw)
+            .map(|(token, _)| token)
+            .collect())
+    }
+
+    /// Gets the latest metadata of a token, or [None] if the token isn't known
+    /// to this ledger.
+    pub fn metadata(&self, token: &TokenId) -> Option<&[u8]> {
+        self.tokens.get(token).map(|record| &record.metadata[..])
+    }
+
+    /// Checks if a token has been burned by its owner.
+    pub fn is_burned(&self, token: &TokenId) -> bool {
+        self.tokens.get(token).is_some_and(|record| record.burned)
+    }
+
+    /// Iterates over every token ever minted, including burned tokens, in no
+    /// particular order.
+    pub fn tokens(&self) -> impl Iterator<Item = &TokenId> {
+        self.tokens.keys()
+    }
+
+    /// Checks if a token is known to this ledger.
+    pub fn contains(&self, token: &TokenId) -> bool {
+        self.tokens.contains_key(token)
+    }
+
+    /// Gets the amount of tokens ever minted, including burned tokens.
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Checks if this ledger doesn't know of any tokens.
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Gets the height of the latest block this ledger has applied.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Gets the median timestamp of the latest blocks this ledger has applied,
+    /// which time locks are judged by as a single block dated ahead of the
+    /// clock can't drag it forward.
+    pub fn median_time(&self) -> u64 {
+        let mut timestamps = self.timestamps.clone();
+        timestamps.sort_unstable();
+        timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+    }
+
+    /// Hashes this entire ledger in a canonical order, so equal ledgers always
+    /// have the same digest; used to sign the ledger kept by a
+    /// [Checkpoint](crate::Checkpoint).
+    pub fn digest(&self) -> Hash {
+        let mut encoder = Encoder::default();
+        encoder
+            .bytes(&self.chain.0)
+            .u64(self.height)
+            .u64(self.timestamps.len() as u64);
+        for timestamp in self.timestamps.iter() {
+            encoder.u64(*timestamp);
+        }
+        encoder
+            .option(self.authorities.as_ref(), |encoder, authorities| {
+                encoder.set(authorities.iter().cloned());
+            })
+            .set(self.operators.iter().map(|(owner, operator)| {
+                let mut entry = Encoder::default();
+                entry.bytes(owner).bytes(operator);
+                entry.0
+            }))
+            .set(self.retired.iter().cloned())
+            .set(self.settlements.iter().map(|(hash, settlement)| {
+                let mut entry = Encoder::default();
+                entry.bytes(&hash.0).bytes(&settlement.to_bytes());
+                entry.0
+            }))
+            .set(self.collections.iter().map(|(collection, record)| {
+                let mut entry = Encoder::default();
+                entry
+                    .bytes(&collection.0)
+                    .bytes(&record.creator)
+                    .bytes(&record.collection.to_bytes())
+                    .u64(record.minted);
+                entry.0
+            }))
+            .set(self.tokens.iter().map(|(token, record)| {
+                let mut entry = Encoder::default();
+                entry.bytes(&token.0).record(record);
+                entry.0
+            }));
+
+        let mut hasher = Sha256::new();
+        hasher.update(LEDGER_DOMAIN);
+        hasher.update(&encoder.0);
+        Hash(hasher.finish())
+    }
+
+    /// Creates the initial ledger of a chain from its genesis block; malformed
+    /// policies authorize nobody.
+    pub(crate) fn new(genesis: &Block) -> Self {
+        let authorities = match Policy::from_data(&genesis.data) {
+            Ok(Some(policy)) => Some(policy.raw_authorities().unwrap_or_default()),
+            Ok(None) => None,
+            Err(_) => Some(HashSet::new()),
+        };
+        Self {
+            chain: genesis.hash.clone(),
+            tokens: HashMap::new(),
+            authorities,
+            collections: HashMap::new(),
+            height: 0,
+            timestamps: vec![genesis.timestamp],
+            operators: HashSet::new(),
+            retired: HashSet::new(),
+            settlements: HashMap::new(),
+        }
+    }
+
+    /// Checks if the next block follows the chain's rules without applying it,
+    /// see [Ledger::apply] for the rules.
+    pub(crate) fn allows(&self, block: &Block) -> bool {
+        matches!(self.change(block), Ok(Some(_)))
+    }
+
+    /// Applies the next block to this ledger, returning if the block followed
+    /// the chain's rules; blocks which don't are left out of the ledger.
+    ///
+    /// Payloads have to match the data hash their block signed, so they can't
+    /// be swapped out after signing. New tokens, collections and policy updates
+    /// have to be signed by a current authority if the chain has a policy,
+    /// whilst transfers and burns have to be signed by the token's current
+    /// owner and burned tokens can't be used again. Transfers may also be
+    /// signed by a delegate the owner has approved. Keys which have been
+    /// rotated out can't sign blocks or receive tokens. Tokens minted into a
+    /// collection also have to be signed by the collection's creator and fit
+    /// within its supply cap. Settlements have to be signed by one of their
+    /// token's royalty recipients. Locked tokens can't be used until their lock is
+    /// released or refunded.
+    pub(crate) fn apply(&mut self, block: &Block) -> bool {
+        let change = self.change(block);
+        self.height += 1;
+        self.timestamps.push(block.timestamp);
+        if self.timestamps.len() > MEDIAN_TIME_SPAN {
+            self.timestamps.remove(0);
+        }
+        match change {
+            Ok(Some(Change::Policy(authorities))) => self.authorities = Some(authorities),
+            Ok(Some(Change::Collection(collection, record))) => {
+                self.collections.insert(collection, record);
+            }
+            Ok(Some(Change::Mint(tokens))) => {
+                for (token, record) in tokens {
+                    if let Some(collection) = &record.collection {
+                        if let Some(record) = self.collections.get_mut(collection) {
+                            record.minted += 1;
+                        }
+                    }
+                    self.tokens.insert(token, record);
+                }
+            }
+            Ok(Some(Change::Token(token, record))) => {
+                self.tokens.insert(token, record);
+            }
+            Ok(Some(Change::Transfer(token, record, settlement))) => {
+                self.tokens.insert(token, record);
+                if let Some(settlement) = settlement {
+                    self.settlements.remove(&settlement);
+                }
+            }
+            Ok(Some(Change::Settlement(hash, settlement))) => {
+                self.settlements.insert(hash, settlement);
+            }
+            Ok(Some(Change::Operator(operator, true))) => {
+                self.operators.insert(operator);
+            }
+            Ok(Some(Change::Operator(operator, false))) => {
+                self.operators.remove(&operator);
+            }
+            Ok(Some(Change::Rotation(old, new))) => self.rotate(old, new),
+            Ok(Some(Change::Nothing)) => (),
+            Ok(None) | Err(_) => return false,
+        }
+        true
+    }
+
+    /// Works out the change the next block makes, or [None] if it isn't allowed.
+    fn change(&self, block: &Block) -> Result<Option<Change>> {
+        if !block.data.is_intact() {
+            return Ok(None);
+        }
+        let signer = match block.ownership {
+            Ownership::Genesis => return Ok(None),
+            _ => block.ownership.to_raw_public()?,
+        };
+        if self.retired.contains(&signer) {
+            return Ok(None);
+        }
+        let authorized = self
+            .authorities
+            .as_ref()
+            .map_or(true, |authorities| authorities.contains(&signer));
+
+        // malformed payloads are treated as opaque data which isn't a token
+        let kind = match BlockKind::from_data(&block.data) {
+            Ok(kind) => kind,
+            Err(_) => return Ok(authorized.then_some(Change::Nothing)),
+        };
+
+        let change = match kind {
+            BlockKind::Policy(policy) => match &self.authorities {
+                // policy updates are opaque data on chains without a policy
+                None => Change::Nothing,
+                Some(_) if authorized => Change::Policy(policy.raw_authorities()?),
+                Some(_) => return Ok(None),
+            },
+            BlockKind::Transfer(transfer) => {
+                let owner = transfer.recipient.to_raw_public()?;
+                if self.retired.contains(&owner) {
+                    return Ok(None);
+                }
+                // settlements are used up by the first transfer referencing them
+                let token = transfer.token;
+                let settlement = transfer
+                    .settlement
+                    .filter(|hash| self.settlement(&token, hash).is_some());
+                match self.transferable(&token, &signer) {
+                    Some(record) => Change::Transfer(
+                        token,
+                        Record {
+                            owner,
+                            approved: None,
+                            ..record
+                        },
+                        settlement,
+                    ),
+                    None => return Ok(None),
+                }
+            }
+            BlockKind::Burn(burn) => match self.owned(&burn.token, &signer) {
+                Some(record) => Change::Token(
+                    burn.token,
+                    Record {
+                        burned: true,
+                        ..record
+                    },
+                ),
+                None => return Ok(None),
+            },
+            BlockKind::MetadataUpdate { token, metadata } => match self.owned(&token, &signer) {
+                Some(record) => Change::Token(token, Record { metadata, ..record }),
+                None => return Ok(None),
+            },
+            // only whoever was paid can vouch for a settlement
+            BlockKind::Settlement(settlement) => match self.tokens.get(&settlement.token) {
+                Some(record) if record.royalties.iter().any(|(key, _)| key == &signer) => {
+                    Change::Settlement(block.hash.clone(), settlement)
+                }
+                _ => return Ok(None),
+            },
+            BlockKind::Lock(lock) => match self.owned(&lock.token, &signer) {
+                _ if self.retired.contains(&lock.recipient.to_raw_public()?) => return Ok(None),
+                Some(record) => Change::Transfer(
+                    lock.token.clone(),
+                    Record {
+                        locked: Some(PendingLock::new(&lock)?),
+                        ..record
+                    },
+                    lock.settlement
+                        .clone()
+                        .filter(|hash| self.settlement(&lock.token, hash).is_some()),
+                ),
+                None => return Ok(None),
+            },
+            BlockKind::Release(token) => match self.pending(&token) {
+                Some((record, pending))
+                    if pending.releasable_by(&signer, self.height + 1, self.median_time()) =>
+                {
+                    Change::Token(
+                        token,
+                        Record {
+                            owner: pending.recipient,
+                            locked: None,
+                            approved: None,
+                            ..record
+                        },
+                    )
+                }
+                _ => return Ok(None),
+            },
+            BlockKind::Refund(token) => match self.pending(&token) {
+                Some((record, pending)) if pending.refundable_by(&signer) => Change::Token(
+                    token,
+                    Record {
+                        locked: None,
+                        ..record
+                    },
+                ),
+                _ => return Ok(None),
+            },
+            BlockKind::Approve(approval) => {
+                let delegate = approval
+                    .delegate
+                    .raw_public_key()
+                    .map_err(Error::KeyPublic)?;
+                match approval.token {
+                    Some(token) => match self.owned(&token, &signer) {
+                        Some(record) => Change::Token(
+                            token,
+                            Record {
+                                approved: Some(delegate),
+                                ..record
+                            },
+                        ),
+                        None => return Ok(None),
+                    },
+                    None => Change::Operator((signer, delegate), true),
+                }
+            }
+            BlockKind::Revoke(approval) => {
+                let delegate = approval
+                    .delegate
+                    .raw_public_key()
+                    .map_err(Error::KeyPublic)?;
+                match approval.token {
+                    Some(token) => match self.owned(&token, &signer) {
+                        Some(record) if record.approved.as_ref() == Some(&delegate) => {
+                            Change::Token(
+                                token,
+                                Record {
+                                    approved: None,
+                                    ..record
+                                },
+                            )
+                        }
+                        _ => return Ok(None),
+                    },
+                    None if self.operators.contains(&(signer.clone(), delegate.clone())) => {
+                        Change::Operator((signer, delegate), false)
+                    }
+                    None => return Ok(None),
+                }
+            }
+            BlockKind::Rotation(rotation) => {
+                let key = rotation.key.raw_public_key().map_err(Error::KeyPublic)?;
+                if key == signer
+                    || self.retired.contains(&key)
+                    || self.shares_multisig(&signer, &key)
+                {
+                    return Ok(None);
+                }
+                Change::Rotation(signer, key)
+            }
+            BlockKind::Mint {
+                collection: Some(collection),
+                metadata,
+            } => match self.collections.get(&collection) {
+                Some(record) if authorized && record.allows_mint(&signer, 1) => {
+                    self.new_tokens(block, signer, metadata, Some(collection), None)
+                }
+                _ => return Ok(None),
+            },
+            BlockKind::MintEditions {
+                collection: Some(collection),
+                editions,
+                metadata,
+            } => match self.collections.get(&collection) {
+                Some(record) if authorized && record.allows_mint(&signer, editions.into()) => {
+                    self.new_tokens(block, signer, metadata, Some(collection), Some(editions))
+                }
+                _ => return Ok(None),
+            },
+            BlockKind::Mint { .. }
+            | BlockKind::MintEditions { .. }
+            | BlockKind::Opaque(_)
+            | BlockKind::CreateCollection(_)
+                if !authorized =>
+            {
+                return Ok(None)
+            }
+            BlockKind::Mint { metadata, .. } => {
+                self.new_tokens(block, signer, metadata, None, None)
+            }
+            BlockKind::MintEditions {
+                editions, metadata, ..
+            } => self.new_tokens(block, signer, metadata, None, Some(editions)),
+            BlockKind::Opaque(_) => self.new_tokens(block, signer, vec![], None, None),
+            BlockKind::CreateCollection(collection) => Change::Collection(
+                CollectionId::new(&self.chain, &block.hash),
+                CollectionRecord {
+                    creator: signer,
+                    collection,
+                    minted: 0,
+                },
+            ),
+        };
+        Ok(Some(change))
+    }
+
+    /// Creates the change for a new token minted by the `block`, or for a run
+    /// of new tokens if it mints `editions`.
+    fn new_tokens(
+        &self,
+        block: &Block,
+        owner: Vec<u8>,
+        metadata: Vec<u8>,
+        collection: Option<CollectionId>,
+        editions: Option<u32>,
+    ) -> Change {
+        // malformed royalties are treated as plain metadata
+        let royalties = split_royalties(&metadata)
+            .map(|(royalties, _)| royalties)
+            .unwrap_or_default();
+        let record = |edition| Record {
+            mint: block.hash.clone(),
+            owner: owner.clone(),
+            metadata: metadata.clone(),
+            burned: false,
+            collection: collection.clone(),
+            edition,
+            royalties: royalties.clone(),
+            locked: None,
+            approved: None,
+        };
+        let tokens = match editions {
+            Some(total) => (1..=total)
+                .map(|number| {
+                    let token = TokenId::edition(&self.chain, &block.hash, number);
+                    (token, record(Some(Edition { number, total })))
+                })
+                .collect(),
+            None => vec![(TokenId::new(&self.chain, &block.hash), record(None))],
+        };
+        Change::Mint(tokens)
+    }
+
+    /// Gets the record of a token if it's owned by the `signer` and hasn't been
+    /// burned or locked.
+    fn owned(&self, token: &TokenId, signer: &[u8]) -> Option<Record> {
+        self.tokens
+            .get(token)
+            .filter(|record| !record.burned && record.locked.is_none() && record.owner == signer)
+            .cloned()
+    }
+
+    /// Gets the record of a token if the `signer` may transfer it, being either
+    /// its owner or an approved delegate, and it hasn't been burned or locked.
+    fn transferable(&self, token: &TokenId, signer: &[u8]) -> Option<Record> {
+        self.tokens
+            .get(token)
+            .filter(|record| !record.burned && record.locked.is_none())
+            .filter(|record| self.may_transfer(record, signer))
+            .cloned()
+    }
+
+    /// Checks if the `signer` is the owner of a token's `record` or a delegate
+    /// they've approved to transfer it.
+    pub(crate) fn may_transfer(&self, record: &Record, signer: &[u8]) -> bool {
+        record.owner == signer
+            || record.approved.as_deref() == Some(signer)
+            || self
+                .operators
+                .contains(&(record.owner.clone(), signer.to_vec()))
+    }
+
+    /// Gets the record of a token alongside the lock it's waiting on, if it
+    /// hasn't been burned.
+    fn pending(&self, token: &TokenId) -> Option<(Record, PendingLock)> {
+        let record = self.tokens.get(token).filter(|record| !record.burned)?;
+        let pending = record.locked.clone()?;
+        Some((record.clone(), pending))
+    }
+}
+
+/// Canonical encoding of a [Ledger] which [Ledger::digest] is taken over, where
+/// every field is length-prefixed so adjacent fields can't run into each other
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn u64(&mut self, value: u64) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn option<T>(&mut self, value: Option<T>, encode: impl FnOnce(&mut Self, T)) -> &mut Self {
+        match value {
+            Some(value) => {
+                self.0.push(1);
+                encode(self, value);
+            }
+            None => self.0.push(0),
+        }
+        self
+    }
+
+    /// Encodes unordered `entries` by sorting them first.
+    fn set(&mut self, entries: impl Iterator<Item = Vec<u8>>) -> &mut Self {
+        let mut entries: Vec<_> = entries.collect();
+        entries.sort_unstable();
+        self.u64(entries.len() as u64);
+        for entry in entries.iter() {
+            self.bytes(entry);
+        }
+        self
+    }
+
+    fn record(&mut self, record: &Record) -> &mut Self {
+        self.bytes(&record.mint.0)
+            .bytes(&record.owner)
+            .bytes(&record.metadata)
+            .u64(record.burned as u64)
+            .option(record.collection.as_ref(), |encoder, collection| {
+                encoder.bytes(&collection.0);
+            })
+            .option(record.edition.as_ref(), |encoder, edition| {
+                encoder.u64(edition.number as u64).u64(edition.total as u64);
+            })
+            .u64(record.royalties.len() as u64);
+        for (recipient, basis_points) in record.royalties.iter() {
+            self.bytes(recipient).u64(*basis_points as u64);
+        }
+        self.option(record.locked.as_ref(), |encoder, locked| {
+            encoder
+                .bytes(&locked.recipient)
+                .option(locked.height, |encoder, height| {
+                    encoder.u64(height);
+                })
+                .option(locked.timestamp, |encoder, timestamp| {
+                    encoder.u64(timestamp);
+                })
+                .option(locked.arbiter.as_ref(), |encoder, arbiter| {
+                    encoder.bytes(arbiter);
+                });
+        })
+        .option(record.approved.as_ref(), |encoder, approved| {
+            encoder.bytes(approved);
+        })
+    }
+}
+
+/// Checks that every block, starting from the genesis block, follows the rules
+/// of the chain and the royalty `rule` if there is one; see [Ledger::apply]
+/// for the rules.
+///
+/// Blocks up to the `base` snapshot are trusted as-is, as long as the block at
+/// its height matches the snapshot.
+pub(crate) fn verify_ledger<'a>(
+    base: Option<&Snapshot>,
+    blocks: impl IntoIterator<Item = &'a Block>,
+    rule: Option<&dyn RoyaltyRule>,
+) -> Result<bool> {
+    let mut blocks = blocks.into_iter();
+    let ledger = match base {
+        Some(base) => match blocks.nth(base.height) {
+            Some(block) if block.hash == base.tip => base.ledger.clone(),
+            _ => return Ok(false),
+        },
+        None => {
+            let genesis = blocks.next().ok_or(Error::ChainEmpty)?;
+            if Policy::from_data(&genesis.data).is_err() {
+                return Ok(false);
+            }
+            Ledger::new(genesis)
+        }
+    };
+
+    Ok(ledger.folded(blocks, rule)?.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChainTree, Transfer};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn incremental_matches_replay() {
+        let keys: Vec<_> = (0..3).map(|_| PKey::generate_ed25519().unwrap()).collect();
+        let mut chain = Chain::default();
+        let mut tokens = vec![];
+
+        for round in 0..12 {
+            let owner = &keys[round % keys.len()];
+            match round % 3 {
+                0 => {
+                    chain
+                        .push_signed(format!("token {}", round), owner)
+                        .unwrap();
+                    tokens.push(chain.token_id(&chain.tip().hash).unwrap());
+                }
+                1 => {
+                    let token = &tokens[round % tokens.len()];
+                    let current = chain.ledger().owner(token).unwrap().unwrap();
+                    let current = keys.iter().find(|key| key.public_eq(&current)).unwrap();
+                    chain.transfer(token, current, owner).unwrap();
+                }
+                _ => {
+                    // invalid transfers are rejected before reaching the ledger
+                    let transfer = Transfer::new(TokenId([0; 32]), owner).unwrap();
+                    assert!(matches!(
+                        chain.push_signed(transfer.to_bytes().unwrap(), owner),
+                        Err(Error::Unauthorized)
+                    ));
+                }
+            }
+            let replayed = Ledger::replay(&chain);
+            assert_eq!(chain.ledger(), &replayed);
+            assert_eq!(chain.ledger().digest(), replayed.digest());
+        }
+
+        assert_eq!(chain.ledger().len(), tokens.len());
+        let held: usize = keys
+            .iter()
+            .map(|key| chain.ledger().tokens_of(key).unwrap().len())
+            .sum();
+        assert_eq!(held, tokens.len());
+
+        let blocks: Vec<Block> = chain.clone().into();
+        assert_eq!(Chain::try_from(blocks).unwrap().ledger(), chain.ledger());
+    }
+
+    #[test]
+    fn rebuilt_after_reorg() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut ours = Chain::default();
+        ours.push_signed("token", &alice).unwrap();
+        let token = ours.token_id(&ours.tip().hash).unwrap();
+
+        let mut theirs = ours.clone();
+        ours.transfer(&token, &alice, &bob).unwrap();
+        theirs.extend_data(vec!["first", "second"]).unwrap();
+
+        let mut tree = ChainTree::new(&ours);
+        tree.extend(&theirs).unwrap();
+        let reorg = tree.reorg(&ours.tip().hash, &theirs.tip().hash).unwrap();
+        assert!(reorg.apply(&mut ours).unwrap());
+
+        assert_eq!(ours.ledger(), &Ledger::replay(&ours));
+        assert!(ours
+            .ledger()
+            .owner(&token)
+            .unwrap()
+            .unwrap()
+            .public_eq(&alice));
+    }
+}
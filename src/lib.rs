@@ -38,6 +38,17 @@
 //! onft = "0.1.0-beta.2"
 //! ```
 //!
+//! # Features
+//!
+//! Onft has a few optional features which can be enabled inside of your `Cargo.toml` file:
+//!
+//! - `serde`: (de)serialization of blocks and chains
+//! - `net`: reference TCP peer protocol for gossiping blocks, enabling `serde`
+//! - `tokio`: asynchronous node for the peer protocol using tokio, enabling `net`
+//! - `store`: persistent file storage for chains, enabling `serde`
+//! - `cli`: the `onft` command-line tool for chains stored on disk, enabling `store`
+//! - `server`: HTTP/JSON REST API and the `onft-server` binary, enabling `store` and `tokio`
+//!
 //! # Licensing
 //!
 //! This project is dual-licensed under both the [MIT](https://en.wikipedia.org/wiki/MIT_License) and [Apache](https://en.wikipedia.org/wiki/Apache_License) licenses, so feel free to use either at your discretion.
@@ -50,19 +61,69 @@
 )]
 
 pub mod error;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(feature = "server")]
+pub mod server;
 
+mod approval;
 mod block;
+mod burn;
 mod chain;
+mod checkpoint;
+mod collection;
+mod consensus;
+mod edition;
+mod fork;
 mod hash;
+mod header;
+mod kind;
+mod ledger;
+mod lock;
+mod merkle;
+mod multisig;
+mod policy;
+mod rotation;
+mod royalty;
+mod snapshot;
+#[cfg(feature = "store")]
+mod store;
+mod sync;
+mod token;
+mod transfer;
 
-pub use block::{Block, BlockData, Ownership};
+pub use approval::Approval;
+pub use block::{Block, BlockData, Ownership, MAX_CLOCK_DRIFT};
+pub use burn::Burn;
 pub use chain::Chain;
+pub use checkpoint::Checkpoint;
+pub use collection::{Collection, CollectionId};
+pub use consensus::{Consensus, Open, ProofOfAuthority, ProofOfWork};
+pub use edition::Edition;
 pub use error::Result;
-pub use hash::Hash;
+pub use fork::{Branch, ChainTree, CustomRule, ForkChoice, Reorg};
+pub use hash::{from_hex, to_hex, Hash};
+pub use header::{BlockHeader, HeaderChain};
+pub use kind::BlockKind;
+pub use ledger::Ledger;
+pub use lock::{Condition, Lock};
+pub use merkle::{verify_inclusion, InclusionProof};
+pub use multisig::{Cosignature, MultiSig};
+pub use policy::Policy;
+pub use rotation::Rotation;
+pub use royalty::{
+    RequireSettlement, Royalty, RoyaltyRule, Settlement, TokenMetadata, MAX_BASIS_POINTS,
+};
+pub use snapshot::Snapshot;
+#[cfg(feature = "store")]
+pub use store::Store;
+pub use sync::Delta;
+pub use token::TokenId;
+pub use transfer::Transfer;
 
 /// Defines the breaking ABI protocol version this release uses for (de)serialization
 #[cfg(feature = "serde")]
-pub const PROTO_VERSION: u8 = 1;
+pub const PROTO_VERSION: u8 = 2;
 
 /// Defines the default initializer for SHA-256 hashes, used for genesis hashes
 pub(crate) const DEFAULT_GENESIS: [u8; 32] = [
@@ -91,5 +152,8 @@ pub(crate) const DEFAULT_GENESIS: [u8; 32] = [
 /// ```
 pub mod prelude {
     pub use crate::error::{SignerError, VerifierError};
-    pub use crate::{error, Block, BlockData, Chain, Hash, Ownership};
+    pub use crate::{
+        error, Block, BlockData, BlockKind, Burn, Chain, ChainTree, Collection, CollectionId,
+        Delta, ForkChoice, Hash, Ownership, TokenId, Transfer,
+    };
 }
@@ -0,0 +1,484 @@
+//! Contains [Lock], [Condition] and implementations
+
+use crate::kind::{tagged, LOCK_TAG};
+use crate::{error::Error, BlockData, BlockKind, Chain, Hash, Ledger, Ownership, Result, TokenId};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+
+/// Condition which has to be met before a [Lock] can be released
+#[derive(Debug, Clone)]
+pub enum Condition {
+    /// Released by the recipient once the chain reaches this height, being the
+    /// index of the releasing block.
+    Height(u64),
+    /// Released by the recipient once the [median time](Ledger::median_time) of
+    /// the blocks before the releasing block is at or after this many seconds
+    /// since the Unix epoch.
+    Timestamp(u64),
+    /// Released or refunded only by this arbiter key.
+    Arbiter(PKey<Public>),
+}
+
+impl Condition {
+    /// Creates an escrow condition released or refunded by the `arbiter`.
+    pub fn arbiter(arbiter: &PKeyRef<impl HasPublic>) -> Result<Self> {
+        let raw = arbiter.raw_public_key().map_err(Error::KeyPublic)?;
+        PKey::public_key_from_raw_bytes(&raw, Id::ED25519)
+            .map(Self::Arbiter)
+            .map_err(Error::KeyRawPublic)
+    }
+}
+
+/// Transfer of a token which only completes once a [Condition] has been met
+///
+/// # Using
+///
+/// The current owner of a token locks it for a recipient, after which the token
+/// can't be used by anyone until a later block releases it over to the
+/// recipient. Time-locked transfers are released by the recipient once the
+/// chain reaches a given height or time, whilst escrow transfers are released by a
+/// designated arbiter, who may instead refund the token back to its owner.
+/// [Chain::verify] checks every lock, release and refund. You can, in high level
+/// terms, do the following:
+///
+/// - Lock a token for a recipient: [Chain::lock]
+/// - Complete the transfer once its condition is met: [Chain::release]
+/// - Cancel an escrow transfer as its arbiter: [Chain::refund]
+/// - Check if a token is locked: [Ledger::is_locked]
+///
+/// Time locks by timestamp go by the [median time](Ledger::median_time) of the
+/// blocks before the releasing block rather than its own timestamp, as the
+/// recipient could otherwise date their release up to
+/// [MAX_CLOCK_DRIFT](crate::MAX_CLOCK_DRIFT) ahead of the clock.
+/// Locking a token counts as selling it when it comes to royalties, so a chain
+/// with a [RoyaltyRule](crate::RoyaltyRule) may require the lock to reference a
+/// settlement using [Chain::lock_settled].
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use onft::Condition;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let seller = PKey::generate_ed25519().unwrap();
+///     let buyer = PKey::generate_ed25519().unwrap();
+///     let arbiter = PKey::generate_ed25519().unwrap();
+///
+///     let mut chain = Chain::default();
+///     let token = chain.mint("Rare stamp", &seller)?;
+///     chain.lock(&token, &seller, &buyer, Condition::arbiter(&arbiter)?)?;
+///
+///     // the buyer can't take it without the arbiter
+///     assert!(chain.release(&token, &buyer).is_err());
+///     chain.release(&token, &arbiter)?;
+///
+///     assert!(chain.ledger().owner(&token)?.unwrap().public_eq(&buyer));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Lock {
+    /// Identifier of the locked token.
+    pub token: TokenId,
+    /// Owner of the token once the lock is released.
+    pub recipient: Ownership,
+    /// Condition to be met before the lock can be released.
+    pub condition: Condition,
+    /// Hash of the block containing the royalty [Settlement](crate::Settlement)
+    /// for this lock, if there is one.
+    pub settlement: Option<Hash>,
+}
+
+impl Lock {
+    /// Creates a new lock of the `token` for the `recipient`, released once the
+    /// `condition` is met.
+    pub fn new(
+        token: TokenId,
+        recipient: &PKeyRef<impl HasPublic>,
+        condition: Condition,
+    ) -> Result<Self> {
+        let raw = recipient.raw_public_key().map_err(Error::KeyPublic)?;
+        Ok(Self {
+            token,
+            recipient: Ownership::Them(
+                PKey::public_key_from_raw_bytes(&raw, Id::ED25519).map_err(Error::KeyRawPublic)?,
+            ),
+            condition,
+            settlement: None,
+        })
+    }
+
+    /// Encodes this lock into bytes to be used as block data.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut payload = self.token.0.to_vec();
+        match &self.condition {
+            Condition::Height(height) => {
+                payload.push(0);
+                payload.extend_from_slice(&height.to_le_bytes());
+            }
+            Condition::Arbiter(arbiter) => {
+                payload.push(1);
+                payload.extend(arbiter.raw_public_key().map_err(Error::KeyPublic)?);
+            }
+            Condition::Timestamp(timestamp) => {
+                payload.push(2);
+                payload.extend_from_slice(&timestamp.to_le_bytes());
+            }
+        }
+        match &self.settlement {
+            Some(settlement) => {
+                payload.push(1);
+                payload.extend_from_slice(&settlement.0[..]);
+            }
+            None => payload.push(0),
+        }
+        payload.extend(self.recipient.to_raw_public()?);
+        Ok(tagged(LOCK_TAG, &payload))
+    }
+
+    /// Decodes a lock from block data, returning [None] if the data doesn't
+    /// contain a lock at all, see [BlockKind::from_data].
+    pub fn from_data(data: &BlockData) -> Result<Option<Self>> {
+        match BlockKind::from_data(data)? {
+            BlockKind::Lock(lock) => Ok(Some(lock)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decodes the payload of a lock, after its tag.
+    pub(crate) fn from_payload(payload: &[u8]) -> Result<Self> {
+        if payload.len() < 33 {
+            return Err(Error::InvalidPayload);
+        }
+        let token = TokenId(payload[..32].try_into().unwrap());
+        let (condition, rest) = match payload[32] {
+            0 if payload.len() >= 41 => {
+                let height = u64::from_le_bytes(payload[33..41].try_into().unwrap());
+                (Condition::Height(height), &payload[41..])
+            }
+            1 if payload.len() >= 65 => {
+                let arbiter = PKey::public_key_from_raw_bytes(&payload[33..65], Id::ED25519)
+                    .map_err(|_| Error::InvalidPayload)?;
+                (Condition::Arbiter(arbiter), &payload[65..])
+            }
+            2 if payload.len() >= 41 => {
+                let timestamp = u64::from_le_bytes(payload[33..41].try_into().unwrap());
+                (Condition::Timestamp(timestamp), &payload[41..])
+            }
+            _ => return Err(Error::InvalidPayload),
+        };
+        let (settlement, recipient) = match rest {
+            [0, recipient @ ..] => (None, recipient),
+            [1, settlement @ ..] if settlement.len() >= 32 => (
+                Some(Hash(settlement[..32].try_into().unwrap())),
+                &settlement[32..],
+            ),
+            _ => return Err(Error::InvalidPayload),
+        };
+        let recipient = Ownership::from_raw_owner(recipient).map_err(|_| Error::InvalidPayload)?;
+        Ok(Self {
+            token,
+            recipient,
+            condition,
+            settlement,
+        })
+    }
+}
+
+/// Lock waiting to be released inside of a [Ledger]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PendingLock {
+    /// Raw identity of the owner once released.
+    pub(crate) recipient: Vec<u8>,
+    /// Height the lock can be released from by the recipient, if time-locked.
+    pub(crate) height: Option<u64>,
+    /// Timestamp the lock can be released from by the recipient, if
+    /// time-locked.
+    pub(crate) timestamp: Option<u64>,
+    /// Raw public key of the arbiter, if held in escrow.
+    pub(crate) arbiter: Option<Vec<u8>>,
+}
+
+impl PendingLock {
+    /// Creates the pending form of a `lock` once it's been added.
+    pub(crate) fn new(lock: &Lock) -> Result<Self> {
+        let (height, timestamp, arbiter) = match &lock.condition {
+            Condition::Height(height) => (Some(*height), None, None),
+            Condition::Timestamp(timestamp) => (None, Some(*timestamp), None),
+            Condition::Arbiter(arbiter) => (
+                None,
+                None,
+                Some(arbiter.raw_public_key().map_err(Error::KeyPublic)?),
+            ),
+        };
+        Ok(Self {
+            recipient: lock.recipient.to_raw_public()?,
+            height,
+            timestamp,
+            arbiter,
+        })
+    }
+
+    /// Checks if the `signer` of a block at `height` may release this lock, with
+    /// the chain's median time being `median_time` before the block.
+    pub(crate) fn releasable_by(&self, signer: &[u8], height: u64, median_time: u64) -> bool {
+        match (self.height, self.timestamp, &self.arbiter) {
+            (Some(unlocks), _, _) => self.recipient == signer && height >= unlocks,
+            (_, Some(unlocks), _) => self.recipient == signer && median_time >= unlocks,
+            (None, None, Some(arbiter)) => arbiter == signer,
+            (None, None, None) => false,
+        }
+    }
+
+    /// Checks if the `signer` may refund this lock back to the token's owner.
+    pub(crate) fn refundable_by(&self, signer: &[u8]) -> bool {
+        self.arbiter.as_deref() == Some(signer)
+    }
+}
+
+impl Ledger {
+    /// Checks if a token is locked in a transfer which hasn't been released or
+    /// refunded yet.
+    pub fn is_locked(&self, token: &TokenId) -> bool {
+        self.tokens
+            .get(token)
+            .is_some_and(|record| record.locked.is_some())
+    }
+}
+
+impl Chain {
+    /// Locks the `token` for the `recipient` until the `condition` is met,
+    /// adding a new block signed by its current `owner`; chainable method.
+    pub fn lock(
+        &mut self,
+        token: &TokenId,
+        owner: &PKey<Private>,
+        recipient: &PKeyRef<impl HasPublic>,
+        condition: Condition,
+    ) -> Result<&mut Self> {
+        self.push_locked(&Lock::new(token.clone(), recipient, condition)?, owner)
+    }
+
+    /// Adds a new block containing the `lock`, signed by the current `owner` of
+    /// its token.
+    pub(crate) fn push_locked(&mut self, lock: &Lock, owner: &PKey<Private>) -> Result<&mut Self> {
+        self.check_owner(&lock.token, owner)?;
+        self.push_signed(lock.to_bytes()?, owner)
+    }
+
+    /// Releases the locked `token` over to its recipient, adding a new block
+    /// signed by the recipient of a time lock or the arbiter of an escrow;
+    /// chainable method.
+    pub fn release(&mut self, token: &TokenId, keypair: &PKey<Private>) -> Result<&mut Self> {
+        let signer = keypair.raw_public_key().map_err(Error::KeyPublic)?;
+        let height = self.ledger.height + 1;
+        match self.pending(token)? {
+            pending if pending.releasable_by(&signer, height, self.ledger.median_time()) => {
+                self.push_kind(&BlockKind::Release(token.clone()), keypair)
+            }
+            _ => Err(Error::NotReleasable),
+        }
+    }
+
+    /// Refunds the `token` held in escrow back to its owner, adding a new block
+    /// signed by its `arbiter`; chainable method.
+    pub fn refund(&mut self, token: &TokenId, arbiter: &PKey<Private>) -> Result<&mut Self> {
+        let signer = arbiter.raw_public_key().map_err(Error::KeyPublic)?;
+        match self.pending(token)? {
+            pending if pending.refundable_by(&signer) => {
+                self.push_kind(&BlockKind::Refund(token.clone()), arbiter)
+            }
+            _ => Err(Error::NotReleasable),
+        }
+    }
+
+    /// Gets the lock a token is waiting on.
+    fn pending(&self, token: &TokenId) -> Result<&PendingLock> {
+        match self.ledger.tokens.get(token) {
+            Some(record) if record.burned => Err(Error::Burned),
+            Some(record) => record.locked.as_ref().ok_or(Error::NotReleasable),
+            None => Err(Error::NotToken),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Block, RequireSettlement, Royalty, Settlement, TokenMetadata};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn time_locks() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("token", &alice).unwrap();
+        chain
+            .lock(&token, &alice, &bob, Condition::Height(4))
+            .unwrap();
+
+        assert!(chain.ledger().is_locked(&token));
+        assert!(matches!(
+            chain.transfer(&token, &alice, &bob),
+            Err(Error::Locked)
+        ));
+        assert!(matches!(
+            chain.release(&token, &bob),
+            Err(Error::NotReleasable)
+        ));
+        chain.push_data("filler").unwrap();
+        assert!(matches!(
+            chain.release(&token, &alice),
+            Err(Error::NotReleasable)
+        ));
+        chain.release(&token, &bob).unwrap();
+
+        assert!(!chain.ledger().is_locked(&token));
+        assert!(chain
+            .ledger()
+            .owner(&token)
+            .unwrap()
+            .unwrap()
+            .public_eq(&bob));
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn timestamp_locks() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("token", &alice).unwrap();
+        let unlocks = crate::block::now() + 60;
+        chain
+            .lock(&token, &alice, &bob, Condition::Timestamp(unlocks))
+            .unwrap();
+        let decoded = Lock::from_data(&chain.tip().data).unwrap().unwrap();
+        assert!(matches!(decoded.condition, Condition::Timestamp(t) if t == unlocks));
+
+        // heights don't matter, only the timestamps of the blocks before
+        chain.extend_data(vec!["filler"; 4]).unwrap();
+        assert!(matches!(
+            chain.release(&token, &bob),
+            Err(Error::NotReleasable)
+        ));
+        let release = BlockKind::Release(token.clone()).to_bytes().unwrap();
+        let early = Block::new_signed(chain.tip(), release.clone(), &bob).unwrap();
+        assert!(!chain.ledger().allows(&early));
+
+        // blocks can't claim to be from after the lock by tampering with it
+        let mut tampered = early.clone();
+        tampered.timestamp = unlocks;
+        assert!(!tampered.verify(chain.tip()).unwrap());
+
+        // nor by dating the release itself ahead of the clock
+        let dated = Block::new_signed_at(chain.tip(), release.clone(), &bob, unlocks).unwrap();
+        assert!(dated.follows_in_time(chain.tip()));
+        assert!(!chain.ledger().allows(&dated));
+        let mut blocks = chain.blocks.clone();
+        blocks.push(dated);
+        let forged = Chain::try_from(blocks).unwrap();
+        assert!(forged.ledger().is_locked(&token));
+        assert!(!forged.verify().unwrap());
+
+        // a single block dated ahead doesn't move the median time on its own
+        let ahead = Block::new_signed_at(chain.tip(), "ahead", &alice, unlocks).unwrap();
+        chain.push_sealed(ahead).unwrap();
+        assert!(chain.ledger().median_time() < unlocks);
+        assert!(matches!(
+            chain.release(&token, &bob),
+            Err(Error::NotReleasable)
+        ));
+
+        // once most recent blocks are from after the lock it can be released
+        while chain.ledger().median_time() < unlocks {
+            chain.push_data("filler").unwrap();
+        }
+        chain.release(&token, &bob).unwrap();
+        assert!(chain
+            .ledger()
+            .owner(&token)
+            .unwrap()
+            .unwrap()
+            .public_eq(&bob));
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn escrow() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let arbiter = PKey::generate_ed25519().unwrap();
+        let condition = || Condition::arbiter(&arbiter).unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("token", &alice).unwrap();
+
+        chain.lock(&token, &alice, &bob, condition()).unwrap();
+        assert!(matches!(
+            chain.refund(&token, &bob),
+            Err(Error::NotReleasable)
+        ));
+        chain.refund(&token, &arbiter).unwrap();
+        assert!(chain
+            .ledger()
+            .owner(&token)
+            .unwrap()
+            .unwrap()
+            .public_eq(&alice));
+
+        chain.lock(&token, &alice, &bob, condition()).unwrap();
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn settled_locks() {
+        let artist = PKey::generate_ed25519().unwrap();
+        let buyer = PKey::generate_ed25519().unwrap();
+        let arbiter = PKey::generate_ed25519().unwrap();
+        let condition = || Condition::arbiter(&arbiter).unwrap();
+        let mut chain = Chain::default();
+        chain.set_royalty_rule(RequireSettlement);
+        let metadata = TokenMetadata::new("art", vec![Royalty::new(&artist, 1_000).unwrap()]);
+        let token = chain.mint(metadata.to_bytes().unwrap(), &artist).unwrap();
+
+        // locking is a sale, so it can't dodge the royalty rule
+        assert!(matches!(
+            chain.lock(&token, &artist, &buyer, condition()),
+            Err(Error::Unsettled)
+        ));
+        let paid = chain
+            .settle(&Settlement::new(token.clone(), 1_000, 100), &artist)
+            .unwrap();
+        chain
+            .lock_settled(&token, &artist, &buyer, condition(), &paid)
+            .unwrap();
+        let decoded = Lock::from_data(&chain.tip().data).unwrap().unwrap();
+        assert_eq!(decoded.settlement, Some(paid.clone()));
+        chain.refund(&token, &arbiter).unwrap();
+        assert!(matches!(
+            chain.lock_settled(&token, &artist, &buyer, condition(), &paid),
+            Err(Error::Unsettled)
+        ));
+        assert!(chain.verify().unwrap());
+
+        // forged locks without a settlement break the rule once released
+        let lock = Lock::new(token.clone(), &buyer, condition()).unwrap();
+        let locked = Block::new_signed(chain.tip(), lock.to_bytes().unwrap(), &artist).unwrap();
+        let release = BlockKind::Release(token).to_bytes().unwrap();
+        let released = Block::new_signed(&locked, release, &arbiter).unwrap();
+        let mut blocks: Vec<Block> = chain.into();
+        blocks.extend([locked, released]);
+        let mut tampered = Chain::try_from(blocks).unwrap();
+        assert!(tampered.verify().unwrap());
+        tampered.set_royalty_rule(RequireSettlement);
+        assert!(!tampered.verify().unwrap());
+    }
+}
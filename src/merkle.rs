@@ -0,0 +1,256 @@
+//! Contains [InclusionProof], [verify_inclusion] and implementations
+
+use crate::chain::verify_genesis;
+use crate::{error::Error, Block, Chain, Hash, Result};
+use openssl::sha::Sha256;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Prefix of hashed leaves, keeping them distinct from inner nodes
+const LEAF_PREFIX: u8 = 0;
+
+/// Prefix of hashed inner nodes, keeping them distinct from leaves
+const NODE_PREFIX: u8 = 1;
+
+/// Proof that a single block is included within a chain's Merkle root
+///
+/// # Using
+///
+/// The Merkle root of a chain is computed over every block hash in order using
+/// [Chain::merkle_root]. A light client knowing just this root can then check
+/// that a block is part of the chain with a proof from [Chain::inclusion_proof]
+/// using [verify_inclusion], without needing any of the other blocks.
+///
+/// Only block hashes are inside of the tree, so proofs also carry the hash of
+/// the block before the proven one. This lets the proven block's signature be
+/// checked, which binds its data, timestamp and ownership to the hash in the
+/// tree.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use onft::verify_inclusion;
+///
+/// fn main() -> onft::Result<()> {
+///     let mut chain = Chain::default();
+///     chain.extend_data(vec!["Hello", "world"])?;
+///
+///     let root = chain.merkle_root();
+///     let proof = chain.inclusion_proof(1)?;
+///
+///     assert!(verify_inclusion(&root, &proof, &chain.blocks()[1]));
+///     assert!(!verify_inclusion(&root, &proof, &chain.blocks()[2]));
+///     Ok(())
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    /// Height of the proven block within the chain.
+    pub height: usize,
+    /// Amount of blocks the chain had when the root was computed.
+    pub leaves: usize,
+    /// Hash of the block before the proven block, or [None] for the genesis
+    /// block.
+    pub previous: Option<Hash>,
+    /// Sibling hashes from the bottom of the tree upwards, skipping levels where
+    /// the node had no sibling.
+    pub siblings: Vec<Hash>,
+}
+
+impl Chain {
+    /// Computes the Merkle root over the hashes of every block in the chain.
+    pub fn merkle_root(&self) -> Hash {
+        let mut level: Vec<Hash> = self
+            .blocks
+            .iter()
+            .map(|block| hash_leaf(&block.hash))
+            .collect();
+        while level.len() > 1 {
+            level = next_level(&level);
+        }
+        // never empty due to the chain invariant
+        level.remove(0)
+    }
+
+    /// Creates a proof that the block at `height` is included within the
+    /// current [Chain::merkle_root], see [InclusionProof] for more information.
+    pub fn inclusion_proof(&self, height: usize) -> Result<InclusionProof> {
+        if height >= self.blocks.len() {
+            return Err(Error::UnknownBlock);
+        }
+
+        let mut siblings = vec![];
+        let mut index = height;
+        let mut level: Vec<Hash> = self
+            .blocks
+            .iter()
+            .map(|block| hash_leaf(&block.hash))
+            .collect();
+        while level.len() > 1 {
+            if let Some(sibling) = level.get(index ^ 1) {
+                siblings.push(sibling.clone());
+            }
+            level = next_level(&level);
+            index /= 2;
+        }
+
+        Ok(InclusionProof {
+            height,
+            leaves: self.blocks.len(),
+            previous: height
+                .checked_sub(1)
+                .map(|previous| self.blocks[previous].hash.clone()),
+            siblings,
+        })
+    }
+}
+
+/// Verifies that `block` is included within the Merkle `root` of a chain using
+/// a `proof` created by [Chain::inclusion_proof].
+///
+/// Besides its hash being inside of the tree, the block's payload has to match
+/// its data hash and its signature has to verify against the previous hash in
+/// the proof, so a block with a real hash but swapped contents isn't included.
+/// Blocks whose payload has been pruned can't be proven.
+pub fn verify_inclusion(root: &Hash, proof: &InclusionProof, block: &Block) -> bool {
+    if proof.height >= proof.leaves {
+        return false;
+    }
+    let intact = match &proof.previous {
+        None => proof.height == 0 && verify_genesis(block, true),
+        Some(previous) => {
+            proof.height > 0 && block.data.is_intact() && block.verify(previous).unwrap_or(false)
+        }
+    };
+    if !intact {
+        return false;
+    }
+
+    let mut hash = hash_leaf(&block.hash);
+    let mut siblings = proof.siblings.iter();
+    let (mut index, mut width) = (proof.height, proof.leaves);
+    while width > 1 {
+        if index ^ 1 < width {
+            hash = match siblings.next() {
+                Some(sibling) if index % 2 == 0 => hash_node(&hash, sibling),
+                Some(sibling) => hash_node(sibling, &hash),
+                None => return false,
+            };
+        }
+        index /= 2;
+        width = width.div_ceil(2);
+    }
+    siblings.next().is_none() && &hash == root
+}
+
+/// Hashes each pair of nodes in a level together, carrying an unpaired last
+/// node up as-is.
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_node(left, right),
+            _ => pair[0].clone(),
+        })
+        .collect()
+}
+
+fn hash_leaf(hash: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(&hash.0[..]);
+    Hash(hasher.finish())
+}
+
+fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(&left.0[..]);
+    hasher.update(&right.0[..]);
+    Hash(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BlockData;
+    use openssl::pkey::PKey;
+
+    #[test]
+    fn proofs_for_every_size() {
+        let mut chain = Chain::default();
+        for size in 1..=9 {
+            let root = chain.merkle_root();
+            for height in 0..size {
+                let proof = chain.inclusion_proof(height).unwrap();
+                assert!(verify_inclusion(&root, &proof, &chain.blocks[height]));
+                if size > 1 {
+                    let other = &chain.blocks[(height + 1) % size];
+                    assert!(!verify_inclusion(&root, &proof, other));
+                }
+            }
+            assert!(chain.inclusion_proof(size).is_err());
+            chain.push_data("Hello, world!").unwrap();
+        }
+    }
+
+    #[test]
+    fn tampered_proofs() {
+        let mut chain = Chain::default();
+        chain.extend_data(vec!["first", "second", "third"]).unwrap();
+        let root = chain.merkle_root();
+        let proof = chain.inclusion_proof(2).unwrap();
+
+        let mut moved = proof.clone();
+        moved.height = 3;
+        assert!(!verify_inclusion(&root, &moved, &chain.blocks[2]));
+
+        let mut truncated = proof.clone();
+        truncated.siblings.pop();
+        assert!(!verify_inclusion(&root, &truncated, &chain.blocks[2]));
+
+        let mut extended = proof.clone();
+        extended.siblings.push(Hash::default());
+        assert!(!verify_inclusion(&root, &extended, &chain.blocks[2]));
+
+        let mut relinked = proof.clone();
+        relinked.previous = Some(Hash::default());
+        assert!(!verify_inclusion(&root, &relinked, &chain.blocks[2]));
+    }
+
+    #[test]
+    fn tampered_blocks() {
+        let owner = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.push_signed("first", &owner).unwrap();
+        chain.push_data("second").unwrap();
+        let root = chain.merkle_root();
+        let proof = chain.inclusion_proof(1).unwrap();
+        assert!(verify_inclusion(&root, &proof, &chain.blocks[1]));
+
+        // blocks keeping their real hash but with swapped contents
+        let mut swapped = chain.blocks[1].clone();
+        swapped.data = BlockData::new("forged").unwrap();
+        assert!(!verify_inclusion(&root, &proof, &swapped));
+
+        let mut payload = chain.blocks[1].clone();
+        payload.data.inner = b"forged".to_vec();
+        assert!(!verify_inclusion(&root, &proof, &payload));
+
+        let mut owned = chain.blocks[1].clone();
+        owned.ownership = PKey::generate_ed25519().unwrap().into();
+        assert!(!verify_inclusion(&root, &proof, &owned));
+
+        let mut dated = chain.blocks[1].clone();
+        dated.timestamp += 1;
+        assert!(!verify_inclusion(&root, &proof, &dated));
+
+        let mut genesis = chain.blocks[0].clone();
+        genesis.data.inner = b"forged".to_vec();
+        let proof = chain.inclusion_proof(0).unwrap();
+        assert!(verify_inclusion(&root, &proof, &chain.blocks[0]));
+        assert!(!verify_inclusion(&root, &proof, &genesis));
+    }
+}
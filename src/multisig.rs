@@ -0,0 +1,430 @@
+//! Contains [MultiSig] and implementations
+
+use crate::block::{next_timestamp, now};
+use crate::error::{Error, SignerError, VerifierError};
+use crate::hash::{commitment, hash_triplet, signed_message};
+use crate::{Block, BlockData, Chain, Hash, Ownership, Result, TokenId, Transfer};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+use openssl::sha::sha512;
+use openssl::sign::{Signer, Verifier};
+
+/// Signature made by one of the keys of a [MultiSig], alongside the index of
+/// the key which made it
+pub type Cosignature = (u8, [u8; Hash::SIG_LEN]);
+
+/// Ownership shared between several keys, a threshold of which have to sign
+///
+/// # Using
+///
+/// Blocks owned by a multi-signature carry a [Cosignature] from at least
+/// `threshold` of its keys, each signing the same message a single key would,
+/// which [Block::verify] checks. Tokens minted by or transferred to a
+/// multi-signature can then only be used by blocks carrying enough signatures
+/// from its keys. You can, in high level terms, do the following:
+///
+/// - Create a block signed by multiple keys: [Block::new_multisig]
+/// - Add a block signed by multiple keys to a chain: [Chain::push_multisig]
+/// - Transfer a token over to a multi-signature: [Chain::transfer_to_multisig]
+/// - Transfer a token owned by a multi-signature: [Chain::transfer_multisig]
+/// - Find the owner of a multi-signature token: [Ledger::ownership](crate::Ledger::ownership)
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use onft::MultiSig;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let keys: Vec<_> = (0..3).map(|_| PKey::generate_ed25519().unwrap()).collect();
+///     let guild = MultiSig::new(2, keys.iter().map(|key| &**key).collect())?;
+///     let buyer = PKey::generate_ed25519().unwrap();
+///
+///     let mut chain = Chain::default();
+///     let token = chain.mint("Guild banner", &keys[0])?;
+///     chain.transfer_to_multisig(&token, &keys[0], &guild)?;
+///
+///     // one guild member isn't enough
+///     assert!(chain.transfer_multisig(&token, &guild, &[&keys[0]], &buyer).is_err());
+///     chain.transfer_multisig(&token, &guild, &[&keys[0], &keys[2]], &buyer)?;
+///
+///     assert!(chain.verify()?);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultiSig {
+    /// Amount of keys which have to sign, from 1 up to the amount of keys.
+    pub threshold: u8,
+    /// Public keys which may sign, in order of their cosignature indexes.
+    pub keys: Vec<PKey<Public>>,
+}
+
+impl MultiSig {
+    /// Creates a new multi-signature requiring `threshold` of the given `keys`,
+    /// returning [Error::InvalidMultiSig] if the threshold can't be met or
+    /// there are more than 255 keys.
+    pub fn new(threshold: u8, keys: Vec<&PKeyRef<impl HasPublic>>) -> Result<Self> {
+        let keys = keys
+            .into_iter()
+            .map(|key| {
+                let raw = key.raw_public_key().map_err(Error::KeyPublic)?;
+                PKey::public_key_from_raw_bytes(&raw, Id::ED25519).map_err(Error::KeyRawPublic)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let multisig = Self { threshold, keys };
+        multisig.check()?;
+        Ok(multisig)
+    }
+
+    /// Encodes this multi-signature into bytes, which are used as its identity
+    /// in place of a single raw public key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.check()?;
+        let mut bytes = vec![self.threshold, self.keys.len() as u8];
+        for key in self.keys.iter() {
+            bytes.extend(key.raw_public_key().map_err(Error::KeyPublic)?);
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a multi-signature from bytes encoded by [MultiSig::to_bytes].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes {
+            [threshold, count, keys @ ..] if keys.len() == *count as usize * 32 => {
+                let keys = keys
+                    .chunks(32)
+                    .map(|raw| {
+                        PKey::public_key_from_raw_bytes(raw, Id::ED25519)
+                            .map_err(Error::KeyRawPublic)
+                    })
+                    .collect::<Result<_>>()?;
+                let multisig = Self {
+                    threshold: *threshold,
+                    keys,
+                };
+                multisig.check()?;
+                Ok(multisig)
+            }
+            _ => Err(Error::InvalidMultiSig),
+        }
+    }
+
+    /// Signs the message for a block following the `previous` hash with the
+    /// given `data_hash` using each of the `signers`, returning the digest used
+    /// as the block's signature alongside every cosignature.
+    pub(crate) fn sign(
+        &self,
+        previous: &Hash,
+        data_hash: [u8; 32],
+        signers: &[&PKey<Private>],
+    ) -> Result<([u8; Hash::SIG_LEN], Vec<Cosignature>)> {
+        let raw_keys = self.raw_keys()?;
+        let mut cosignatures = vec![];
+        for signer in signers.iter() {
+            let raw = signer.raw_public_key().map_err(Error::KeyPublic)?;
+            let index = raw_keys
+                .iter()
+                .position(|key| key == &raw)
+                .ok_or(Error::ThresholdNotMet)?;
+            let mut signature = [0; Hash::SIG_LEN];
+            Signer::new_without_digest(signer)
+                .map_err(SignerError::Create)?
+                .sign_oneshot(&mut signature, &signed_message(previous, data_hash))
+                .map_err(SignerError::Update)?;
+            cosignatures.push((index as u8, signature));
+        }
+        cosignatures.sort_unstable_by_key(|(index, _)| *index);
+        cosignatures.dedup_by_key(|(index, _)| *index);
+        if cosignatures.len() < self.threshold as usize {
+            return Err(Error::ThresholdNotMet);
+        }
+        Ok((self.digest(&cosignatures)?, cosignatures))
+    }
+
+    /// Verifies the `hash` of a block following the `previous` hash, checking
+    /// that enough distinct keys signed it and that its `signature` is the
+    /// digest of its `cosignatures`.
+    pub(crate) fn verify(
+        &self,
+        hash: &Hash,
+        previous: &Hash,
+        signature: [u8; Hash::SIG_LEN],
+        cosignatures: &[Cosignature],
+        data_hash: [u8; 32],
+    ) -> Result<bool> {
+        if self.check().is_err()
+            || cosignatures.len() < self.threshold as usize
+            || !cosignatures.windows(2).all(|pair| pair[0].0 < pair[1].0)
+            || self.digest(cosignatures)? != signature
+        {
+            return Ok(false);
+        }
+
+        let message = signed_message(previous, data_hash);
+        for (index, cosignature) in cosignatures.iter() {
+            let key = match self.keys.get(*index as usize) {
+                Some(key) => key,
+                None => return Ok(false),
+            };
+            let verified = Verifier::new_without_digest(key)
+                .map_err(VerifierError::Create)?
+                .verify_oneshot(cosignature, &message)
+                .map_err(VerifierError::Execute)?;
+            if !verified {
+                return Ok(false);
+            }
+        }
+        Ok(hash.0 == hash_triplet(previous, signature, data_hash))
+    }
+
+    /// Checks the threshold can be met by distinct keys.
+    fn check(&self) -> Result<()> {
+        let raw_keys = self.raw_keys()?;
+        let distinct = raw_keys
+            .iter()
+            .enumerate()
+            .all(|(index, key)| !raw_keys[..index].contains(key));
+        if self.threshold == 0
+            || self.threshold as usize > self.keys.len()
+            || self.keys.len() > u8::MAX as usize
+            || !distinct
+        {
+            Err(Error::InvalidMultiSig)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Gets the raw public key of every key in order.
+    pub(crate) fn raw_keys(&self) -> Result<Vec<Vec<u8>>> {
+        self.keys
+            .iter()
+            .map(|key| key.raw_public_key().map_err(Error::KeyPublic))
+            .collect()
+    }
+
+    /// Hashes this multi-signature alongside its `cosignatures`, committing the
+    /// block's hash to both.
+    fn digest(&self, cosignatures: &[Cosignature]) -> Result<[u8; Hash::SIG_LEN]> {
+        let mut bytes = self.to_bytes()?;
+        for (index, signature) in cosignatures.iter() {
+            bytes.push(*index);
+            bytes.extend_from_slice(&signature[..]);
+        }
+        Ok(sha512(&bytes))
+    }
+}
+
+impl<'a> Block {
+    /// Creates a new block from the previous block in a chain alongside the data
+    /// contained within this block, owned by a `multisig` and signed by enough
+    /// of its keys from the `signers`.
+    pub fn new_multisig(
+        previous_hash: impl Into<&'a Hash>,
+        data: impl Into<Vec<u8>>,
+        multisig: &MultiSig,
+        signers: &[&PKey<Private>],
+    ) -> Result<Self> {
+        Self::new_multisig_at(previous_hash, data, multisig, signers, now())
+    }
+
+    /// Creates a new block owned by a `multisig` like [Block::new_multisig],
+    /// with the given `timestamp` instead of the current time.
+    pub(crate) fn new_multisig_at(
+        previous_hash: impl Into<&'a Hash>,
+        data: impl Into<Vec<u8>>,
+        multisig: &MultiSig,
+        signers: &[&PKey<Private>],
+        timestamp: u64,
+    ) -> Result<Self> {
+        let previous = previous_hash.into();
+        let data = BlockData::new(data.into())?;
+        let data_hash = commitment(data.hash, timestamp);
+        let (signature, cosignatures) = multisig.sign(previous, data_hash, signers)?;
+        Ok(Self {
+            hash: Hash(hash_triplet(previous, signature, data_hash)),
+            ownership: Ownership::MultiSig(multisig.clone()),
+            signature,
+            cosignatures,
+            data,
+            timestamp,
+            nonce: 0,
+        })
+    }
+}
+
+impl Chain {
+    /// Adds a new single block to the chain via new data, owned by a `multisig`
+    /// and signed by enough of its keys from the `signers`; chainable method.
+    pub fn push_multisig(
+        &mut self,
+        data: impl Into<Vec<u8>>,
+        multisig: &MultiSig,
+        signers: &[&PKey<Private>],
+    ) -> Result<&mut Self> {
+        let timestamp = next_timestamp(self.tip().timestamp);
+        let block = Block::new_multisig_at(&self.tip().hash, data, multisig, signers, timestamp)?;
+        self.push_sealed(block)
+    }
+
+    /// Transfers the `token` over to a `recipient` multi-signature, adding a new
+    /// block signed by its current `owner`; chainable method.
+    pub fn transfer_to_multisig(
+        &mut self,
+        token: &TokenId,
+        owner: &PKey<Private>,
+        recipient: &MultiSig,
+    ) -> Result<&mut Self> {
+        self.check_transferrer(token, owner)?;
+        let transfer = Transfer::to_multisig(token.clone(), recipient.clone());
+        self.push_signed(transfer.to_bytes()?, owner)
+    }
+
+    /// Transfers the `token` owned by a `multisig` over to the `recipient`,
+    /// adding a new block signed by enough of its keys from the `signers`;
+    /// chainable method.
+    pub fn transfer_multisig(
+        &mut self,
+        token: &TokenId,
+        multisig: &MultiSig,
+        signers: &[&PKey<Private>],
+        recipient: &PKeyRef<impl HasPublic>,
+    ) -> Result<&mut Self> {
+        if self.owner_of(token)? != multisig.to_bytes()? {
+            return Err(Error::NotOwner);
+        }
+        let transfer = Transfer::new(token.clone(), recipient)?;
+        self.push_multisig(transfer.to_bytes()?, multisig, signers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Condition, Lock};
+
+    fn guild(threshold: u8) -> (Vec<PKey<Private>>, MultiSig) {
+        let keys: Vec<_> = (0..3).map(|_| PKey::generate_ed25519().unwrap()).collect();
+        let multisig = MultiSig::new(threshold, keys.iter().map(|key| &**key).collect()).unwrap();
+        (keys, multisig)
+    }
+
+    #[test]
+    fn threshold_signatures() {
+        let (keys, multisig) = guild(2);
+        let outsider = PKey::generate_ed25519().unwrap();
+        let genesis = Block::default();
+
+        let block =
+            Block::new_multisig(&genesis, "data", &multisig, &[&keys[2], &keys[0]]).unwrap();
+        assert!(block.verify(&genesis).unwrap());
+        assert!(block.header().verify(&genesis.hash).unwrap());
+
+        // duplicate and outside signers don't count towards the threshold
+        assert!(matches!(
+            Block::new_multisig(&genesis, "data", &multisig, &[&keys[1], &keys[1]]),
+            Err(Error::ThresholdNotMet)
+        ));
+        assert!(matches!(
+            Block::new_multisig(&genesis, "data", &multisig, &[&keys[1], &outsider]),
+            Err(Error::ThresholdNotMet)
+        ));
+
+        // stripping a cosignature breaks the block
+        let mut stripped = block.clone();
+        stripped.cosignatures.pop();
+        assert!(!stripped.verify(&genesis).unwrap());
+        let mut lowered = block;
+        if let Ownership::MultiSig(multisig) = &mut lowered.ownership {
+            multisig.threshold = 1;
+        }
+        assert!(!lowered.verify(&genesis).unwrap());
+
+        assert!(MultiSig::new(0, vec![&*keys[0]]).is_err());
+        assert!(MultiSig::new(2, vec![&*keys[0], &*keys[0]]).is_err());
+        let bytes = multisig.to_bytes().unwrap();
+        assert_eq!(
+            MultiSig::from_bytes(&bytes).unwrap().to_bytes().unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn guild_owned_tokens() {
+        let (keys, multisig) = guild(2);
+        let buyer = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain
+            .push_multisig("banner", &multisig, &[&keys[0], &keys[1]])
+            .unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+
+        assert!(matches!(
+            chain.ledger().ownership(&token).unwrap(),
+            Some(Ownership::MultiSig(_))
+        ));
+        assert!(matches!(
+            chain.transfer(&token, &keys[0], &buyer),
+            Err(Error::NotOwner)
+        ));
+        chain
+            .transfer_multisig(&token, &multisig, &[&keys[1], &keys[2]], &buyer)
+            .unwrap();
+        assert!(chain
+            .ledger()
+            .owner(&token)
+            .unwrap()
+            .unwrap()
+            .public_eq(&buyer));
+        chain
+            .transfer_to_multisig(&token, &buyer, &multisig)
+            .unwrap();
+        assert!(chain.verify().unwrap());
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&chain).unwrap();
+            let decoded: Chain = serde_json::from_str(&json).unwrap();
+            assert!(decoded.verify().unwrap());
+        }
+    }
+
+    #[test]
+    fn guild_escrow() {
+        let (keys, multisig) = guild(2);
+        let seller = PKey::generate_ed25519().unwrap();
+        let arbiter = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("banner", &seller).unwrap();
+
+        // the guild buys the token through an escrow
+        let lock = Lock {
+            token: token.clone(),
+            recipient: Ownership::MultiSig(multisig.clone()),
+            condition: Condition::arbiter(&arbiter).unwrap(),
+            settlement: None,
+        };
+        chain
+            .push_signed(lock.to_bytes().unwrap(), &seller)
+            .unwrap();
+        let decoded = Lock::from_data(&chain.tip().data).unwrap().unwrap();
+        assert_eq!(
+            decoded.recipient.to_raw_public().unwrap(),
+            multisig.to_bytes().unwrap()
+        );
+        chain.release(&token, &arbiter).unwrap();
+
+        let owner = chain.ledger().ownership(&token).unwrap().unwrap();
+        assert_eq!(owner.to_raw_public().unwrap(), multisig.to_bytes().unwrap());
+        assert!(matches!(
+            chain.ledger().owner(&token),
+            Err(Error::KeyRawPublic(_))
+        ));
+        assert!(Ownership::from_raw_public(&multisig.to_bytes().unwrap()).is_err());
+        chain
+            .transfer_multisig(&token, &multisig, &[&keys[0], &keys[2]], &seller)
+            .unwrap();
+        assert!(chain.verify().unwrap());
+    }
+}
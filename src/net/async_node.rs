@@ -0,0 +1,244 @@
+//! Contains [AsyncNode] and implementations
+
+use super::{decode_frame, decode_frame_len, encode_frame, respond, Message};
+use crate::error::{Error, NetError, Result};
+use crate::{Block, Chain, PROTO_VERSION};
+use std::io::{self, ErrorKind};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+
+/// Amount of block-appended events kept for slow subscribers before they lag
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// Non-blocking peer which serves and synchronizes a shared [Chain] over TCP
+/// using tokio, enabled with the `tokio` feature
+///
+/// # Using
+///
+/// This node speaks the exact same protocol as the blocking [Node](super::Node)
+/// but accepts any amount of connections concurrently, with access to the
+/// underlying chain being serialized between them. You can, in high level
+/// terms, do the following:
+///
+/// - Serve incoming connections from peers: [AsyncNode::serve]
+/// - Synchronize with a listening peer in both directions: [AsyncNode::sync]
+/// - Add new data to the underlying chain: [AsyncNode::push_data]
+/// - Get notified of every block appended to the chain: [AsyncNode::subscribe]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::net::AsyncNode;
+/// use tokio::net::TcpListener;
+///
+/// #[tokio::main]
+/// async fn main() -> onft::Result<()> {
+///     let listener = TcpListener::bind("127.0.0.1:0").await?;
+///     let addr = listener.local_addr()?;
+///
+///     let server = AsyncNode::default();
+///     let mut events = server.subscribe();
+///     tokio::spawn({
+///         let server = server.clone();
+///         async move { server.serve(listener).await }
+///     });
+///
+///     let client = AsyncNode::default();
+///     let block = client.push_data("Hello, world!").await?;
+///     client.sync(addr).await?;
+///
+///     assert_eq!(events.recv().await.unwrap().hash, block.hash);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncNode {
+    chain: Arc<Mutex<Chain>>,
+    events: broadcast::Sender<Block>,
+}
+
+impl AsyncNode {
+    /// Creates a new node from an existing chain.
+    pub fn new(chain: Chain) -> Self {
+        Self {
+            chain: Arc::new(Mutex::new(chain)),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Locks and gets the underlying chain, which shouldn't be held onto whilst
+    /// this node is talking to peers.
+    ///
+    /// Blocks appended through this lock aren't sent to subscribers, so prefer
+    /// [AsyncNode::push_data] where possible.
+    pub async fn chain(&self) -> MutexGuard<'_, Chain> {
+        self.chain.lock().await
+    }
+
+    /// Subscribes to every block appended to the chain from now on, including
+    /// blocks gained from peers.
+    pub fn subscribe(&self) -> broadcast::Receiver<Block> {
+        self.events.subscribe()
+    }
+
+    /// Adds a new single block to the chain via new data, sending it to all
+    /// subscribers and returning it.
+    pub async fn push_data(&self, data: impl Into<Vec<u8>>) -> Result<Block> {
+        let data = data.into();
+        self.update(move |chain| {
+            chain.push_data(data)?;
+            Ok(chain.tip().clone())
+        })
+        .await
+    }
+
+    /// Serves incoming connections forever, handling each connection on a new
+    /// task; see [AsyncNode::handle] for more information.
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let node = self.clone();
+            tokio::spawn(async move { node.handle(stream).await });
+        }
+    }
+
+    /// Handles a single connection from a peer until it disconnects, answering
+    /// each of its requests in turn.
+    pub async fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let version = match read_message(&mut stream).await? {
+            Message::Handshake(version) => version,
+            _ => return Err(NetError::Unexpected.into()),
+        };
+        write_message(&mut stream, &Message::Handshake(PROTO_VERSION)).await?;
+        if version != PROTO_VERSION {
+            return Err(NetError::Version(version).into());
+        }
+
+        loop {
+            let request = match read_message(&mut stream).await {
+                Ok(request) => request,
+                Err(Error::Net(NetError::Io(err))) if err.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(())
+                }
+                Err(err) => return Err(err),
+            };
+            let frame = self.update(move |chain| respond(chain, request)).await?;
+            stream.write_all(&frame).await?;
+            stream.flush().await?;
+        }
+    }
+
+    /// Synchronizes with a listening peer, first fetching any blocks we're
+    /// missing batch-by-batch and then announcing any blocks the peer is missing, returning if
+    /// both now share the same latest block.
+    ///
+    /// If the two chains have forked, neither is changed and `false` is returned.
+    pub async fn sync(&self, addr: impl ToSocketAddrs) -> Result<bool> {
+        let mut stream = TcpStream::connect(addr).await?;
+        write_message(&mut stream, &Message::Handshake(PROTO_VERSION)).await?;
+        match read_message(&mut stream).await? {
+            Message::Handshake(PROTO_VERSION) => (),
+            Message::Handshake(version) => return Err(NetError::Version(version).into()),
+            _ => return Err(NetError::Unexpected.into()),
+        }
+
+        let mut tip = self.chain().await.tip().hash.clone();
+        loop {
+            match request(&mut stream, Message::GetBlocksFrom(tip)).await? {
+                Message::Blocks(delta, more) => {
+                    let last = delta.blocks.last().map(|block| block.hash.clone());
+                    let applied = self.update(move |chain| chain.apply_delta(&delta)).await?;
+                    match last {
+                        Some(last) if applied && more => tip = last,
+                        _ => break,
+                    }
+                }
+                Message::Unknown => break,
+                _ => return Err(NetError::Unexpected.into()),
+            }
+        }
+
+        let their_tip = match request(&mut stream, Message::GetTip).await? {
+            Message::Tip(hash, _) => hash,
+            _ => return Err(NetError::Unexpected.into()),
+        };
+        let delta = match self.chain().await.delta(&their_tip) {
+            Ok(delta) => delta,
+            Err(Error::UnknownBlock) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        let mut previous = delta.from;
+        for block in delta.blocks {
+            let hash = block.hash.clone();
+            match request(&mut stream, Message::AnnounceBlock(previous, block)).await? {
+                Message::Accepted(true) => previous = hash,
+                Message::Accepted(false) => return Ok(false),
+                _ => return Err(NetError::Unexpected.into()),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Runs an operation on the locked chain, sending every block it appended to
+    /// all subscribers afterwards.
+    ///
+    /// Operations verify and hash blocks, so they're run on a blocking thread to
+    /// keep them off of the runtime's threads.
+    async fn update<T: Send + 'static>(
+        &self,
+        op: impl FnOnce(&mut Chain) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let mut chain = self.chain.clone().lock_owned().await;
+        let len = chain.blocks.len();
+        let (chain, output) = tokio::task::spawn_blocking(move || {
+            let output = op(&mut chain);
+            (chain, output)
+        })
+        .await
+        .map_err(io::Error::from)?;
+        let output = output?;
+
+        for block in chain.blocks[len..].iter() {
+            // no subscribers isn't an error
+            let _ = self.events.send(block.clone());
+        }
+        Ok(output)
+    }
+}
+
+impl Default for AsyncNode {
+    fn default() -> Self {
+        Self::new(Chain::default())
+    }
+}
+
+/// Writes a single framed message to a peer.
+async fn write_message(stream: &mut (impl AsyncWrite + Unpin), message: &Message) -> Result<()> {
+    stream.write_all(&encode_frame(message)?).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Reads a single framed message from a peer.
+async fn read_message(stream: &mut (impl AsyncRead + Unpin)) -> Result<Message> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+
+    let len = decode_frame_len(len)?;
+    // grown as the body arrives rather than trusting the announced length
+    let mut body = vec![];
+    stream.take(len as u64).read_to_end(&mut body).await?;
+    if body.len() < len {
+        return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+    }
+    decode_frame(&body)
+}
+
+/// Sends a single request to a peer and reads its response.
+async fn request(stream: &mut TcpStream, message: Message) -> Result<Message> {
+    write_message(stream, &message).await?;
+    read_message(stream).await
+}
@@ -0,0 +1,309 @@
+//! Reference peer protocol for gossiping blocks over TCP, enabled with the `net`
+//! feature
+//!
+//! # Protocol
+//!
+//! Every [Message] is sent as a single frame, being a big-endian [u32] length
+//! followed by that many bytes of JSON. Once connected, both peers send a
+//! [Message::Handshake] containing their [PROTO_VERSION] and disconnect if the
+//! versions differ. From then on the connecting peer sends requests which the
+//! listening peer answers one-by-one:
+//!
+//! | Request                   | Response                                       |
+//! | ------------------------- | ---------------------------------------------- |
+//! | [Message::GetTip]         | [Message::Tip]                                 |
+//! | [Message::GetBlocksFrom]  | [Message::Blocks] or [Message::Unknown]        |
+//! | [Message::AnnounceBlock]  | [Message::Accepted]                            |
+//!
+//! Blocks are sent in batches of up to [MAX_BATCH_LEN] bytes so they always fit
+//! inside of a frame, with peers asking for the blocks following the last one
+//! they were sent until no more are left. Blocks too large to fit inside of a
+//! frame on their own can't be sent at all.
+//!
+//! This protocol is deliberately simple and doesn't decide which branch wins if
+//! two peers have forked, see [ChainTree](crate::ChainTree) for that.
+//!
+//! # Example
+//!
+//! ```rust
+//! use onft::net::Node;
+//! use onft::prelude::*;
+//! use std::net::TcpListener;
+//!
+//! fn main() -> onft::Result<()> {
+//!     let listener = TcpListener::bind("127.0.0.1:0")?;
+//!     let addr = listener.local_addr()?;
+//!
+//!     let mut chain = Chain::default();
+//!     chain.push_data("Hello, world!")?;
+//!     Node::new(chain).spawn(listener);
+//!
+//!     let node = Node::default();
+//!     node.sync(addr)?;
+//!     assert!(node.chain().verify()?);
+//!     Ok(())
+//! }
+//! ```
+
+#[cfg(feature = "tokio")]
+mod async_node;
+mod node;
+
+#[cfg(feature = "tokio")]
+pub use async_node::AsyncNode;
+pub use node::Node;
+
+use crate::error::{Error, NetError, Result};
+use crate::{Block, Chain, Delta, Hash, PROTO_VERSION};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::io::{ErrorKind, Read, Write};
+
+/// Largest frame in bytes which will be read from a peer
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Largest amount of encoded block bytes sent in reply to a single
+/// [Message::GetBlocksFrom], leaving plenty of room within [MAX_FRAME_LEN]
+pub const MAX_BATCH_LEN: usize = MAX_FRAME_LEN / 2;
+
+/// Single message sent between two peers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Message {
+    /// Opening message sent by both peers containing their protocol version.
+    Handshake(u8),
+    /// Requests the hash and height of the latest block.
+    GetTip,
+    /// Hash and height of the latest block.
+    Tip(Hash, usize),
+    /// Requests the blocks following the given block.
+    GetBlocksFrom(Hash),
+    /// Batch of blocks following the requested block, and whether more blocks
+    /// follow on from the batch.
+    Blocks(Delta, bool),
+    /// Announces a new block which directly follows the given block.
+    AnnounceBlock(Hash, Block),
+    /// Whether an announced block was verified and appended.
+    Accepted(bool),
+    /// The requested block isn't known.
+    Unknown,
+}
+
+/// Writes a single framed message to a peer.
+pub fn write_message(stream: &mut impl Write, message: &Message) -> Result<()> {
+    stream.write_all(&encode_frame(message)?)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Reads a single framed message from a peer.
+pub fn read_message(stream: &mut impl Read) -> Result<Message> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+
+    let len = decode_frame_len(len)?;
+    // grown as the body arrives rather than trusting the announced length
+    let mut body = vec![];
+    stream.take(len as u64).read_to_end(&mut body)?;
+    if body.len() < len {
+        return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+    }
+    decode_frame(&body)
+}
+
+/// Sends our handshake and checks the one sent back by a peer.
+pub fn handshake(stream: &mut (impl Read + Write)) -> Result<()> {
+    write_message(stream, &Message::Handshake(PROTO_VERSION))?;
+    match read_message(stream)? {
+        Message::Handshake(PROTO_VERSION) => Ok(()),
+        Message::Handshake(version) => Err(NetError::Version(version).into()),
+        _ => Err(NetError::Unexpected.into()),
+    }
+}
+
+/// Encodes a message into a complete frame, including its length.
+pub(crate) fn encode_frame(message: &Message) -> Result<Vec<u8>> {
+    frame(serde_json::to_vec(message).map_err(NetError::Encode)?)
+}
+
+/// Prefixes an encoded body with its length, ensuring a peer will read it.
+fn frame(body: Vec<u8>) -> Result<Vec<u8>> {
+    if body.len() > MAX_FRAME_LEN {
+        return Err(NetError::FrameTooLarge(body.len()).into());
+    }
+
+    let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+    frame.extend(body);
+    Ok(frame)
+}
+
+/// Decodes the length of a frame's body, ensuring it isn't too large.
+pub(crate) fn decode_frame_len(len: [u8; 4]) -> Result<usize> {
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        Err(NetError::FrameTooLarge(len).into())
+    } else {
+        Ok(len)
+    }
+}
+
+/// Decodes the body of a frame into a message.
+pub(crate) fn decode_frame(body: &[u8]) -> Result<Message> {
+    Ok(serde_json::from_slice(body).map_err(NetError::Decode)?)
+}
+
+/// Creates the encoded frame answering a single request from a peer using the
+/// given chain, shared between all node implementations.
+pub(crate) fn respond(chain: &mut Chain, request: Message) -> Result<Vec<u8>> {
+    let response = match request {
+        Message::GetTip => Message::Tip(chain.tip().hash.clone(), chain.blocks.len() - 1),
+        Message::GetBlocksFrom(hash) => match batch(chain, &hash, MAX_BATCH_LEN) {
+            Err(Error::UnknownBlock) => Message::Unknown,
+            frame => return frame,
+        },
+        Message::AnnounceBlock(previous, block) => {
+            let delta = Delta {
+                from: previous,
+                blocks: vec![block],
+            };
+            match chain.apply_delta(&delta) {
+                Ok(accepted) => Message::Accepted(accepted),
+                Err(Error::UnknownBlock) => Message::Accepted(false),
+                Err(err) => return Err(err),
+            }
+        }
+        _ => return Err(NetError::Unexpected.into()),
+    };
+    encode_frame(&response)
+}
+
+/// [Message::Blocks] containing blocks which have already been encoded, sent
+/// just like the message itself
+#[derive(Serialize)]
+enum EncodedMessage<'a> {
+    Blocks(EncodedDelta<'a>, bool),
+}
+
+/// [Delta] containing blocks which have already been encoded
+#[derive(Serialize)]
+struct EncodedDelta<'a> {
+    from: &'a Hash,
+    blocks: Vec<Box<RawValue>>,
+}
+
+/// Encodes a [Message::Blocks] frame of the blocks following the `from` block,
+/// holding as many as fit within `limit` encoded bytes but always at least one,
+/// alongside whether more blocks follow on from it.
+///
+/// Each block is encoded once, so its encoding is measured and sent as-is. If
+/// the first block can't fit inside of a frame on its own then
+/// [NetError::FrameTooLarge] is returned, as the peer couldn't read it.
+pub(crate) fn batch(chain: &Chain, from: &Hash, limit: usize) -> Result<Vec<u8>> {
+    let missing = chain.missing(from)?;
+    let mut len = 0;
+    let mut blocks = vec![];
+    for block in missing.iter() {
+        let encoded = serde_json::value::to_raw_value(block).map_err(NetError::Encode)?;
+        len += encoded.get().len();
+        if len > limit && !blocks.is_empty() {
+            break;
+        }
+        blocks.push(encoded);
+    }
+    let more = blocks.len() < missing.len();
+    let message = EncodedMessage::Blocks(EncodedDelta { from, blocks }, more);
+    frame(serde_json::to_vec(&message).map_err(NetError::Encode)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn framing() {
+        let mut buf = vec![];
+        write_message(&mut buf, &Message::GetBlocksFrom(Hash::default())).unwrap();
+        write_message(&mut buf, &Message::Accepted(true)).unwrap();
+
+        let mut cursor = Cursor::new(buf);
+        assert!(matches!(
+            read_message(&mut cursor).unwrap(),
+            Message::GetBlocksFrom(hash) if hash == Hash::default()
+        ));
+        assert!(matches!(
+            read_message(&mut cursor).unwrap(),
+            Message::Accepted(true)
+        ));
+    }
+
+    #[test]
+    fn oversized_frame() {
+        let mut cursor = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        assert!(matches!(
+            read_message(&mut cursor),
+            Err(crate::error::Error::Net(NetError::FrameTooLarge(_)))
+        ));
+
+        // frames cut short of their announced length
+        let mut frame = (MAX_FRAME_LEN as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(b"{}");
+        assert!(matches!(
+            read_message(&mut Cursor::new(frame)),
+            Err(crate::error::Error::Net(NetError::Io(err))) if err.kind() == ErrorKind::UnexpectedEof
+        ));
+    }
+
+    /// Decodes a frame created by [batch] back into its delta.
+    fn decode_batch(frame: Vec<u8>) -> (Delta, bool) {
+        match decode_frame(&frame[4..]).unwrap() {
+            Message::Blocks(delta, more) => (delta, more),
+            message => panic!("Expected blocks, got {:?}", message),
+        }
+    }
+
+    #[test]
+    fn batched_blocks() {
+        let mut chain = Chain::default();
+        chain.extend_data(vec!["first", "second", "third"]).unwrap();
+        let genesis = chain.blocks[0].hash.clone();
+
+        let (delta, more) = decode_batch(batch(&chain, &genesis, MAX_BATCH_LEN).unwrap());
+        assert_eq!(delta.blocks.len(), 3);
+        assert!(!more);
+
+        // tiny limits still make progress one block at a time
+        let mut synced = Chain::default();
+        let mut from = genesis;
+        loop {
+            let (delta, more) = decode_batch(batch(&chain, &from, 1).unwrap());
+            assert_eq!(delta.blocks.len(), 1);
+            assert!(synced.apply_delta(&delta).unwrap());
+            from = synced.tip().hash.clone();
+            if !more {
+                break;
+            }
+        }
+        assert_eq!(synced.tip().hash, chain.tip().hash);
+
+        let (delta, more) = decode_batch(batch(&chain, &from, 1).unwrap());
+        assert!(delta.blocks.is_empty() && !more);
+    }
+
+    #[test]
+    fn oversized_block() {
+        // encoded as a number array, taking up to four bytes per byte of data
+        let mut chain = Chain::default();
+        chain.push_data(vec![255; MAX_FRAME_LEN / 4 + 1]).unwrap();
+        let genesis = chain.blocks[0].hash.clone();
+
+        assert!(matches!(
+            batch(&chain, &genesis, MAX_BATCH_LEN),
+            Err(Error::Net(NetError::FrameTooLarge(_)))
+        ));
+        let announce = Message::AnnounceBlock(genesis, chain.tip().clone());
+        assert!(matches!(
+            encode_frame(&announce),
+            Err(Error::Net(NetError::FrameTooLarge(_)))
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+//! Contains [Node] and implementations
+
+use super::{handshake, read_message, respond, write_message, Message};
+use crate::error::{Error, NetError, Result};
+use crate::{Chain, PROTO_VERSION};
+use std::io::{ErrorKind, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+/// Blocking peer which serves and synchronizes a shared [Chain] over TCP
+///
+/// # Using
+///
+/// Each node wraps a chain which is shared between all of its connections, so
+/// cloning a node is cheap and gives access to the same chain. You can, in high
+/// level terms, do the following:
+///
+/// - Serve incoming connections from peers: [Node::serve] or [Node::spawn]
+/// - Synchronize with a listening peer in both directions: [Node::sync]
+/// - Access the underlying chain: [Node::chain]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::net::Node;
+/// use onft::prelude::*;
+/// use std::net::TcpListener;
+///
+/// fn main() -> onft::Result<()> {
+///     let listener = TcpListener::bind("127.0.0.1:0")?;
+///     let addr = listener.local_addr()?;
+///     let server = Node::default();
+///     server.spawn(listener);
+///
+///     let client = Node::default();
+///     client.chain().push_data("Hello, world!")?;
+///     client.sync(addr)?;
+///
+///     assert_eq!(server.chain().tip().hash, client.chain().tip().hash);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    chain: Arc<Mutex<Chain>>,
+}
+
+impl Node {
+    /// Creates a new node from an existing chain.
+    pub fn new(chain: Chain) -> Self {
+        Self {
+            chain: Arc::new(Mutex::new(chain)),
+        }
+    }
+
+    /// Locks and gets the underlying chain, which shouldn't be held onto whilst
+    /// this node is talking to peers.
+    pub fn chain(&self) -> MutexGuard<'_, Chain> {
+        self.chain
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Serves incoming connections forever, handling each connection on a new
+    /// thread; see [Node::handle] for more information.
+    pub fn serve(&self, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let node = self.clone();
+            let stream = stream?;
+            thread::spawn(move || node.handle(stream));
+        }
+        Ok(())
+    }
+
+    /// Serves incoming connections on a new background thread, see [Node::serve].
+    pub fn spawn(&self, listener: TcpListener) -> JoinHandle<Result<()>> {
+        let node = self.clone();
+        thread::spawn(move || node.serve(listener))
+    }
+
+    /// Handles a single connection from a peer until it disconnects, answering
+    /// each of its requests in turn.
+    pub fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let version = match read_message(&mut stream)? {
+            Message::Handshake(version) => version,
+            _ => return Err(NetError::Unexpected.into()),
+        };
+        write_message(&mut stream, &Message::Handshake(PROTO_VERSION))?;
+        if version != PROTO_VERSION {
+            return Err(NetError::Version(version).into());
+        }
+
+        loop {
+            let request = match read_message(&mut stream) {
+                Ok(request) => request,
+                Err(Error::Net(NetError::Io(err))) if err.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(())
+                }
+                Err(err) => return Err(err),
+            };
+            let frame = respond(&mut self.chain(), request)?;
+            stream.write_all(&frame)?;
+            stream.flush()?;
+        }
+    }
+
+    /// Synchronizes with a listening peer, first fetching any blocks we're
+    /// missing batch-by-batch and then announcing any blocks the peer is missing, returning if
+    /// both now share the same latest block.
+    ///
+    /// If the two chains have forked, neither is changed and `false` is returned.
+    pub fn sync(&self, addr: impl ToSocketAddrs) -> Result<bool> {
+        let mut stream = TcpStream::connect(addr)?;
+        handshake(&mut stream)?;
+
+        let mut tip = self.chain().tip().hash.clone();
+        loop {
+            match request(&mut stream, Message::GetBlocksFrom(tip))? {
+                Message::Blocks(delta, more) => {
+                    let last = delta.blocks.last().map(|block| block.hash.clone());
+                    let applied = self.chain().apply_delta(&delta)?;
+                    match last {
+                        Some(last) if applied && more => tip = last,
+                        _ => break,
+                    }
+                }
+                Message::Unknown => break,
+                _ => return Err(NetError::Unexpected.into()),
+            }
+        }
+
+        let their_tip = match request(&mut stream, Message::GetTip)? {
+            Message::Tip(hash, _) => hash,
+            _ => return Err(NetError::Unexpected.into()),
+        };
+        let delta = match self.chain().delta(&their_tip) {
+            Ok(delta) => delta,
+            Err(Error::UnknownBlock) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        let mut previous = delta.from;
+        for block in delta.blocks {
+            let hash = block.hash.clone();
+            match request(&mut stream, Message::AnnounceBlock(previous, block))? {
+                Message::Accepted(true) => previous = hash,
+                Message::Accepted(false) => return Ok(false),
+                _ => return Err(NetError::Unexpected.into()),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Sends a single request to a peer and reads its response.
+fn request(stream: &mut TcpStream, message: Message) -> Result<Message> {
+    write_message(stream, &message)?;
+    read_message(stream)
+}
@@ -0,0 +1,200 @@
+//! Contains [Policy] and implementations
+
+use crate::kind::{tagged, POLICY_TAG};
+use crate::{error::Error, Block, BlockData, BlockKind, Chain, Result};
+use openssl::pkey::{HasPublic, Id, PKey, Private, Public};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Proof-of-authority policy listing the keys which may mint on a chain
+///
+/// # Using
+///
+/// A chain is created with a policy inside of its genesis block using
+/// [Chain::with_policy], after which [Chain::verify] only accepts new tokens
+/// minted by one of the policy's authorities. Tokens can still be transferred
+/// by whoever owns them. The set of authorities can then be rotated by one of
+/// the current authorities using [Chain::update_policy].
+///
+/// Chains without a policy inside of their genesis block can be appended to by
+/// anyone, unless a [Consensus](crate::Consensus) says otherwise.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use onft::Policy;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let minter = PKey::generate_ed25519().unwrap();
+///     let replacement = PKey::generate_ed25519().unwrap();
+///
+///     let mut chain = Chain::with_policy(&Policy::new(vec![&minter])?)?;
+///     chain.push_signed("Hello, world!", &minter)?;
+///
+///     // anyone else can't mint
+///     assert!(chain.push_data("Hello, world!").is_err());
+///
+///     // rotate to a new minter
+///     chain.update_policy(&Policy::new(vec![&replacement])?, &minter)?;
+///     assert!(chain.push_signed("Hello, world!", &minter).is_err());
+///     chain.push_signed("Hello, world!", &replacement)?;
+///
+///     assert!(chain.verify()?);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// Public keys which are allowed to mint new tokens and update the policy.
+    pub authorities: Vec<PKey<Public>>,
+}
+
+impl Policy {
+    /// Creates a new policy authorizing the given public keys.
+    pub fn new<'a, T: HasPublic + 'a>(
+        authorities: impl IntoIterator<Item = &'a PKey<T>>,
+    ) -> Result<Self> {
+        let authorities = authorities
+            .into_iter()
+            .map(|pkey| {
+                let raw = pkey.raw_public_key().map_err(Error::KeyPublic)?;
+                PKey::public_key_from_raw_bytes(&raw, Id::ED25519).map_err(Error::KeyRawPublic)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { authorities })
+    }
+
+    /// Encodes this policy into bytes to be used as block data.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut payload = vec![];
+        for authority in self.authorities.iter() {
+            payload.extend(authority.raw_public_key().map_err(Error::KeyPublic)?);
+        }
+        Ok(tagged(POLICY_TAG, &payload))
+    }
+
+    /// Decodes a policy from block data, returning [None] if the data doesn't
+    /// contain a policy at all, see [BlockKind::from_data].
+    pub fn from_data(data: &BlockData) -> Result<Option<Self>> {
+        match BlockKind::from_data(data)? {
+            BlockKind::Policy(policy) => Ok(Some(policy)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decodes the payload of a policy, after its tag.
+    pub(crate) fn from_payload(payload: &[u8]) -> Result<Self> {
+        if payload.len() % 32 != 0 {
+            return Err(Error::InvalidPayload);
+        }
+
+        let authorities = payload
+            .chunks(32)
+            .map(|raw| {
+                PKey::public_key_from_raw_bytes(raw, Id::ED25519).map_err(|_| Error::InvalidPayload)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { authorities })
+    }
+
+    /// Gets the raw public keys of the authorities.
+    pub(crate) fn raw_authorities(&self) -> Result<HashSet<Vec<u8>>> {
+        self.authorities
+            .iter()
+            .map(|pkey| pkey.raw_public_key().map_err(Error::KeyPublic))
+            .collect()
+    }
+}
+
+impl Chain {
+    /// Creates an initial blockchain with the `policy` inside of its genesis
+    /// block, see [Policy] for more information.
+    pub fn with_policy(policy: &Policy) -> Result<Self> {
+        let genesis = Block::new_genesis(policy.to_bytes()?)?;
+        Ok(Self::from_parts(vec![genesis], Arc::new(crate::Open), None))
+    }
+
+    /// Replaces the current policy with a new `policy`, adding a new block signed
+    /// by one of the current `authority` keys; chainable method.
+    pub fn update_policy(
+        &mut self,
+        policy: &Policy,
+        authority: &PKey<Private>,
+    ) -> Result<&mut Self> {
+        self.push_signed(policy.to_bytes()?, authority)
+    }
+
+    /// Gets the policy currently in force for this chain, or [None] if the chain
+    /// was created without one.
+    pub fn policy(&self) -> Result<Option<Policy>> {
+        let authorities = match &self.ledger.authorities {
+            Some(authorities) => authorities,
+            None => return Ok(None),
+        };
+        let authorities = authorities
+            .iter()
+            .map(|raw| {
+                PKey::public_key_from_raw_bytes(raw, Id::ED25519).map_err(Error::KeyRawPublic)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(Policy { authorities }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn authorized_minting() {
+        let minter = PKey::generate_ed25519().unwrap();
+        let outsider = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::with_policy(&Policy::new(vec![&minter]).unwrap()).unwrap();
+
+        chain.push_signed("token", &minter).unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+        assert!(matches!(
+            chain.push_signed("token", &outsider),
+            Err(Error::Unauthorized)
+        ));
+
+        // owners don't need to be authorities to transfer
+        chain.transfer(&token, &minter, &outsider).unwrap();
+        chain.transfer(&token, &outsider, &minter).unwrap();
+        assert!(chain.verify().unwrap());
+
+        // blocks sneaked in without the policy being checked
+        let mut blocks: Vec<Block> = chain.into();
+        let previous = &blocks[blocks.len() - 1];
+        blocks.push(Block::new_signed(previous, "token", &outsider).unwrap());
+        assert!(!Chain::try_from(blocks).unwrap().verify().unwrap());
+    }
+
+    #[test]
+    fn rotate_authorities() {
+        let old = PKey::generate_ed25519().unwrap();
+        let new = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::with_policy(&Policy::new(vec![&old]).unwrap()).unwrap();
+
+        let policy = Policy::new(vec![&new]).unwrap();
+        assert!(chain.update_policy(&policy, &new).is_err());
+        chain.update_policy(&policy, &old).unwrap();
+        assert!(chain.push_signed("token", &old).is_err());
+        chain.push_signed("token", &new).unwrap();
+
+        let current = chain.policy().unwrap().unwrap();
+        assert_eq!(
+            current.raw_authorities().unwrap(),
+            policy.raw_authorities().unwrap()
+        );
+        assert!(chain.verify().unwrap());
+
+        // tampered genesis policy
+        let mut blocks: Vec<Block> = chain.into();
+        blocks[0].data = BlockData::new(policy.to_bytes().unwrap()).unwrap();
+        assert!(!Chain::try_from(blocks).unwrap().verify().unwrap());
+    }
+}
@@ -0,0 +1,297 @@
+//! Contains [Rotation] and implementations
+
+use crate::{error::Error, BlockKind, Chain, Ledger, MultiSig, Result};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+use std::iter;
+
+/// Replacement of an owner's key with a new one, such as after it's leaked
+///
+/// # Using
+///
+/// An owner signs a rotation with their old key naming a new public key, after
+/// which the ledger treats everything held by the old key as held by the new
+/// one: its tokens, pending locks, collections, royalties, place in a
+/// [Policy](crate::Policy) and seat in any [MultiSig](crate::MultiSig). The old
+/// key is retired for good, so any later block it signs is rejected and nothing
+/// can be transferred to it, whilst approvals granted by or to it are dropped.
+/// Keys can't be rotated over to a key which already has a seat in one of their
+/// multi-signatures. You can, in high level terms, do the following:
+///
+/// - Rotate over to a new key: [Chain::rotate]
+/// - Check if a key has been rotated out: [Ledger::is_retired]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let old = PKey::generate_ed25519().unwrap();
+///     let new = PKey::generate_ed25519().unwrap();
+///     let thief = PKey::generate_ed25519().unwrap();
+///
+///     let mut chain = Chain::default();
+///     let token = chain.mint("Heirloom", &old)?;
+///     chain.rotate(&old, &new)?;
+///
+///     // a leaked old key is useless now
+///     assert!(chain.transfer(&token, &old, &thief).is_err());
+///     assert_eq!(chain.ledger().tokens_of(&new)?, vec![&token]);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    /// New public key taking over from the signer.
+    pub key: PKey<Public>,
+}
+
+impl Rotation {
+    /// Creates a new rotation over to the `key`.
+    pub fn new(key: &PKeyRef<impl HasPublic>) -> Result<Self> {
+        let raw = key.raw_public_key().map_err(Error::KeyPublic)?;
+        Ok(Self {
+            key: PKey::public_key_from_raw_bytes(&raw, Id::ED25519).map_err(Error::KeyRawPublic)?,
+        })
+    }
+
+    /// Decodes a rotation from the payload of a rotation block.
+    pub(crate) fn from_payload(payload: &[u8]) -> Result<Self> {
+        if payload.len() != 32 {
+            return Err(Error::InvalidPayload);
+        }
+        let key = PKey::public_key_from_raw_bytes(payload, Id::ED25519)
+            .map_err(|_| Error::InvalidPayload)?;
+        Ok(Self { key })
+    }
+}
+
+impl Ledger {
+    /// Checks if the `key` has been rotated out, meaning it can't sign blocks or
+    /// receive tokens anymore.
+    pub fn is_retired(&self, key: &PKeyRef<impl HasPublic>) -> Result<bool> {
+        let raw = key.raw_public_key().map_err(Error::KeyPublic)?;
+        Ok(self.retired.contains(&raw))
+    }
+
+    /// Checks if the `old` and `new` raw keys both have a seat in a
+    /// multi-signature holding anything in this ledger, which rotating would
+    /// leave with the new key in two seats.
+    pub(crate) fn shares_multisig(&self, old: &[u8], new: &[u8]) -> bool {
+        self.identities()
+            .any(|raw| seated(raw, old).is_some() && seated(raw, new).is_some())
+    }
+
+    /// Gets every raw identity which holds something in this ledger.
+    fn identities(&self) -> impl Iterator<Item = &Vec<u8>> {
+        let tokens = self.tokens.values().flat_map(|record| {
+            let locked = record
+                .locked
+                .iter()
+                .flat_map(|pending| iter::once(&pending.recipient).chain(&pending.arbiter));
+            iter::once(&record.owner)
+                .chain(record.royalties.iter().map(|(raw, _)| raw))
+                .chain(locked)
+        });
+        let collections = self.collections.values().map(|record| &record.creator);
+        tokens.chain(collections)
+    }
+
+    /// Hands everything held by the `old` raw identity over to the `new` one,
+    /// retiring the old identity.
+    pub(crate) fn rotate(&mut self, old: Vec<u8>, new: Vec<u8>) {
+        let swap = |raw: &mut Vec<u8>| {
+            if *raw == old {
+                *raw = new.clone();
+            } else if let Some(rotated) = rotated(raw, &old, &new) {
+                *raw = rotated;
+            }
+        };
+        if let Some(authorities) = &mut self.authorities {
+            if authorities.remove(&old) {
+                authorities.insert(new.clone());
+            }
+        }
+        for record in self.tokens.values_mut() {
+            let owner = record.owner.clone();
+            swap(&mut record.owner);
+            if record.owner != owner || record.approved.as_ref() == Some(&old) {
+                record.approved = None;
+            }
+            record.royalties.iter_mut().for_each(|(raw, _)| swap(raw));
+            if let Some(pending) = &mut record.locked {
+                swap(&mut pending.recipient);
+                pending.arbiter.iter_mut().for_each(swap);
+            }
+        }
+        for record in self.collections.values_mut() {
+            swap(&mut record.creator);
+        }
+        self.operators.retain(|(owner, delegate)| {
+            [owner, delegate]
+                .iter()
+                .all(|raw| **raw != old && seated(raw, &old).is_none())
+        });
+        self.retired.insert(old);
+    }
+}
+
+/// Decodes the multi-signature encoded as `raw` and finds the seat of the raw
+/// `key` in it, if it's a multi-signature the key has a seat in.
+fn seated(raw: &[u8], key: &[u8]) -> Option<(MultiSig, usize)> {
+    if raw.len() == key.len() {
+        return None;
+    }
+    let multisig = MultiSig::from_bytes(raw).ok()?;
+    let seat = multisig
+        .raw_keys()
+        .ok()?
+        .iter()
+        .position(|seat| seat == key)?;
+    Some((multisig, seat))
+}
+
+/// Re-encodes the multi-signature encoded as `raw` with the `new` raw key in the
+/// `old` key's seat, if the old key has a seat in it.
+fn rotated(raw: &[u8], old: &[u8], new: &[u8]) -> Option<Vec<u8>> {
+    let (mut multisig, seat) = seated(raw, old)?;
+    multisig.keys[seat] = PKey::public_key_from_raw_bytes(new, Id::ED25519).ok()?;
+    // encoding checks the keys are still distinct
+    multisig.to_bytes().ok()
+}
+
+impl Chain {
+    /// Rotates the `old` keypair over to the `new` key, adding a new block
+    /// signed by the `old` keypair; chainable method.
+    pub fn rotate(
+        &mut self,
+        old: &PKey<Private>,
+        new: &PKeyRef<impl HasPublic>,
+    ) -> Result<&mut Self> {
+        if self.ledger.is_retired(old)? || self.ledger.is_retired(new)? || old.public_eq(new) {
+            return Err(Error::Retired);
+        }
+        let raw_old = old.raw_public_key().map_err(Error::KeyPublic)?;
+        let raw_new = new.raw_public_key().map_err(Error::KeyPublic)?;
+        if self.ledger.shares_multisig(&raw_old, &raw_new) {
+            return Err(Error::InvalidMultiSig);
+        }
+        self.push_kind(&BlockKind::Rotation(Rotation::new(new)?), old)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Block, Collection, MultiSig, Policy};
+
+    #[test]
+    fn rotated_ownership() {
+        let old = PKey::generate_ed25519().unwrap();
+        let new = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let collection = chain
+            .create_collection(&Collection::new(None, "Series"), &old)
+            .unwrap();
+        let token = chain.mint_into(&collection, "token", &old).unwrap();
+        let other = chain.mint("other", &old).unwrap();
+        chain.approve_all(&old, &bob).unwrap();
+        chain.rotate(&old, &new).unwrap();
+
+        let ledger = chain.ledger();
+        assert!(ledger.is_retired(&old).unwrap());
+        assert!(!ledger.is_operator(&new, &bob).unwrap());
+        assert!(ledger
+            .creator(&collection)
+            .unwrap()
+            .unwrap()
+            .public_eq(&new));
+        assert_eq!(ledger.tokens_of(&old).unwrap(), Vec::<&_>::new());
+        assert_eq!(ledger.tokens_of(&new).unwrap().len(), 2);
+        assert!(matches!(chain.rotate(&old, &bob), Err(Error::Retired)));
+        assert!(matches!(chain.rotate(&new, &old), Err(Error::Retired)));
+
+        chain.transfer(&token, &new, &bob).unwrap();
+        chain.mint_into(&collection, "more", &new).unwrap();
+        chain.burn(&other, &new).unwrap();
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn retired_keys_rejected() {
+        let old = PKey::generate_ed25519().unwrap();
+        let new = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("token", &new).unwrap();
+        chain.push_signed("before", &old).unwrap();
+        chain.rotate(&old, &new).unwrap();
+
+        // nothing can be sent to a retired key
+        assert!(chain.transfer(&token, &new, &old).is_err());
+        assert!(chain.push_signed("after", &old).is_err());
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn rotated_authorities() {
+        let old = PKey::generate_ed25519().unwrap();
+        let new = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::with_policy(&Policy::new(vec![&old]).unwrap()).unwrap();
+        chain.mint("before", &old).unwrap();
+        chain.rotate(&old, &new).unwrap();
+
+        assert!(matches!(
+            chain.mint("after", &old),
+            Err(Error::Unauthorized)
+        ));
+        chain.mint("after", &new).unwrap();
+        let policy = chain.policy().unwrap().unwrap();
+        assert_eq!(
+            policy.raw_authorities().unwrap(),
+            Policy::new(vec![&new]).unwrap().raw_authorities().unwrap()
+        );
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn rotated_guild_members() {
+        let keys: Vec<_> = (0..3).map(|_| PKey::generate_ed25519().unwrap()).collect();
+        let fresh = PKey::generate_ed25519().unwrap();
+        let buyer = PKey::generate_ed25519().unwrap();
+        let guild = MultiSig::new(2, keys.iter().map(|key| &**key).collect()).unwrap();
+        let mut chain = Chain::default();
+        let token = chain.mint("banner", &keys[0]).unwrap();
+        chain
+            .transfer_to_multisig(&token, &keys[0], &guild)
+            .unwrap();
+        chain.rotate(&keys[0], &fresh).unwrap();
+
+        // the guild now has the new key in the old key's seat
+        let rotated = MultiSig::new(2, vec![&*fresh, &*keys[1], &*keys[2]]).unwrap();
+        let owner = chain.ledger().ownership(&token).unwrap().unwrap();
+        assert_eq!(owner.to_raw_public().unwrap(), rotated.to_bytes().unwrap());
+        assert!(matches!(
+            chain.transfer_multisig(&token, &guild, &[&keys[0], &keys[1]], &buyer),
+            Err(Error::NotOwner)
+        ));
+
+        // members can't rotate over to a key which already has a seat
+        assert!(matches!(
+            chain.rotate(&keys[1], &keys[2]),
+            Err(Error::InvalidMultiSig)
+        ));
+        let rotation = BlockKind::Rotation(Rotation::new(&fresh).unwrap());
+        let block = Block::new_signed(chain.tip(), rotation.to_bytes().unwrap(), &keys[2]).unwrap();
+        assert!(!chain.ledger().allows(&block));
+
+        chain
+            .transfer_multisig(&token, &rotated, &[&fresh, &keys[1]], &buyer)
+            .unwrap();
+        assert_eq!(chain.ledger(), &Ledger::replay(&chain));
+        assert!(chain.verify().unwrap());
+    }
+}
@@ -0,0 +1,541 @@
+//! Contains [TokenMetadata], [Settlement], [RoyaltyRule] and implementations
+
+use crate::kind::{tagged, SETTLEMENT_TAG};
+use crate::{
+    error::Error, Block, BlockData, BlockKind, Chain, Condition, Hash, Ledger, Lock, Result,
+    TokenId, Transfer,
+};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{convert::TryInto, fmt, sync::Arc};
+
+/// Prefix of token metadata which carries royalties
+const ROYALTY_MAGIC: [u8; 4] = *b"\0roy";
+
+/// Basis points making up the entire price of a sale
+pub const MAX_BASIS_POINTS: u16 = 10_000;
+
+/// Share of every resale of a token owed to a recipient
+#[derive(Debug, Clone)]
+pub struct Royalty {
+    /// Public key of whoever is owed this royalty.
+    pub recipient: PKey<Public>,
+    /// Share of the sale price owed, in hundredths of a percent.
+    pub basis_points: u16,
+}
+
+impl Royalty {
+    /// Creates a new royalty of `basis_points` owed to the `recipient`.
+    pub fn new(recipient: &PKeyRef<impl HasPublic>, basis_points: u16) -> Result<Self> {
+        let raw = recipient.raw_public_key().map_err(Error::KeyPublic)?;
+        Ok(Self {
+            recipient: PKey::public_key_from_raw_bytes(&raw, Id::ED25519)
+                .map_err(Error::KeyRawPublic)?,
+            basis_points,
+        })
+    }
+}
+
+/// Metadata of a token alongside the royalties owed whenever it's resold
+///
+/// # Using
+///
+/// Royalties are fixed when a token is minted with this metadata, so that later
+/// owners can't remove them by updating the token's metadata. Transfers of
+/// tokens with royalties can reference a [Settlement] block showing how much was
+/// paid, which a [RoyaltyRule] set using [Chain::set_royalty_rule] checks for
+/// every transfer. You can, in high level terms, do the following:
+///
+/// - Mint a token with royalties: [Chain::mint] using [TokenMetadata::to_bytes]
+/// - Find the royalties owed on a token: [Ledger::royalties]
+/// - Record a royalty settlement: [Chain::settle]
+/// - Transfer a token referencing its settlement: [Chain::transfer_settled]
+/// - Reject transfers which don't settle royalties: [Chain::set_royalty_rule]
+/// - Check past transfers against a rule offline: [Chain::verify_royalties]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use onft::{RequireSettlement, Royalty, Settlement, TokenMetadata};
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let artist = PKey::generate_ed25519().unwrap();
+///     let collector = PKey::generate_ed25519().unwrap();
+///     let mut chain = Chain::default();
+///     chain.set_royalty_rule(RequireSettlement);
+///
+///     // 5% back to the artist on every sale
+///     let metadata = TokenMetadata::new("Starry Night", vec![Royalty::new(&artist, 500)?]);
+///     let token = chain.mint(metadata.to_bytes()?, &artist)?;
+///
+///     assert!(chain.transfer(&token, &artist, &collector).is_err());
+///     let settlement = chain.settle(&Settlement::new(token.clone(), 1_000, 50), &artist)?;
+///     chain.transfer_settled(&token, &artist, &collector, &settlement)?;
+///
+///     assert!(chain.verify()?);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TokenMetadata {
+    /// Royalties owed whenever the token is resold.
+    pub royalties: Vec<Royalty>,
+    /// Arbitrary data describing the token.
+    pub data: Vec<u8>,
+}
+
+impl TokenMetadata {
+    /// Creates new metadata from some `data` and the `royalties` owed on resale.
+    pub fn new(data: impl Into<Vec<u8>>, royalties: Vec<Royalty>) -> Self {
+        Self {
+            royalties,
+            data: data.into(),
+        }
+    }
+
+    /// Encodes this metadata into bytes to be used as a token's metadata,
+    /// returning [Error::InvalidPayload] if the royalties add up to more than
+    /// [MAX_BASIS_POINTS] or there are more than 255 of them.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let count: u8 = self
+            .royalties
+            .len()
+            .try_into()
+            .map_err(|_| Error::InvalidPayload)?;
+        if total_basis_points(self.royalties.iter().map(|royalty| royalty.basis_points))
+            > MAX_BASIS_POINTS.into()
+        {
+            return Err(Error::InvalidPayload);
+        }
+
+        let mut bytes = ROYALTY_MAGIC.to_vec();
+        bytes.push(count);
+        for royalty in self.royalties.iter() {
+            bytes.extend(
+                royalty
+                    .recipient
+                    .raw_public_key()
+                    .map_err(Error::KeyPublic)?,
+            );
+            bytes.extend_from_slice(&royalty.basis_points.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.data);
+        Ok(bytes)
+    }
+
+    /// Decodes metadata from a token's metadata bytes, which don't have any
+    /// royalties unless they were encoded using [TokenMetadata::to_bytes].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (raw, data) = split_royalties(bytes)?;
+        let royalties = raw
+            .into_iter()
+            .map(|(recipient, basis_points)| {
+                Ok(Royalty {
+                    recipient: PKey::public_key_from_raw_bytes(&recipient, Id::ED25519)
+                        .map_err(|_| Error::InvalidPayload)?,
+                    basis_points,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::new(data, royalties))
+    }
+}
+
+/// Raw public keys of royalty recipients alongside their basis points
+pub(crate) type RawRoyalties = Vec<(Vec<u8>, u16)>;
+
+/// Splits the raw royalties off the front of a token's metadata bytes.
+pub(crate) fn split_royalties(bytes: &[u8]) -> Result<(RawRoyalties, &[u8])> {
+    if bytes.len() <= ROYALTY_MAGIC.len() || bytes[..ROYALTY_MAGIC.len()] != ROYALTY_MAGIC {
+        return Ok((vec![], bytes));
+    }
+
+    let count = bytes[ROYALTY_MAGIC.len()] as usize;
+    let rest = &bytes[ROYALTY_MAGIC.len() + 1..];
+    if rest.len() < count * 34 {
+        return Err(Error::InvalidPayload);
+    }
+    let royalties: Vec<_> = rest[..count * 34]
+        .chunks(34)
+        .map(|chunk| {
+            let basis_points = u16::from_le_bytes(chunk[32..].try_into().unwrap());
+            (chunk[..32].to_vec(), basis_points)
+        })
+        .collect();
+    if total_basis_points(royalties.iter().map(|(_, basis_points)| *basis_points))
+        > MAX_BASIS_POINTS.into()
+    {
+        return Err(Error::InvalidPayload);
+    }
+    Ok((royalties, &rest[count * 34..]))
+}
+
+/// Adds up basis points without overflowing.
+fn total_basis_points(basis_points: impl Iterator<Item = u16>) -> u64 {
+    basis_points.map(u64::from).sum()
+}
+
+/// Record of royalties paid for the sale of a token, referenced by transfers
+///
+/// Settlements have to be signed by one of the token's royalty recipients,
+/// vouching that the royalties were paid. Each settlement can only be
+/// referenced by a single transfer or [Lock](crate::Lock), after which the next
+/// sale of the token needs a new settlement.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settlement {
+    /// Identifier of the sold token.
+    pub token: TokenId,
+    /// Price the token was sold for.
+    pub price: u64,
+    /// Total amount paid out to the token's royalty recipients.
+    pub paid: u64,
+}
+
+impl Settlement {
+    /// Length of an encoded settlement in bytes
+    const LEN: usize = 32 + 8 + 8;
+
+    /// Creates a new settlement of the `token` sold for `price`, of which
+    /// `paid` went to its royalty recipients.
+    pub fn new(token: TokenId, price: u64, paid: u64) -> Self {
+        Self { token, price, paid }
+    }
+
+    /// Gets the total amount owed to the `royalties` at this settlement's price,
+    /// rounded down.
+    pub fn owed(&self, royalties: &[Royalty]) -> u64 {
+        let basis_points = total_basis_points(royalties.iter().map(|royalty| royalty.basis_points));
+        (u128::from(self.price) * u128::from(basis_points) / u128::from(MAX_BASIS_POINTS))
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Encodes this settlement into bytes to be used as block data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = self.token.0.to_vec();
+        payload.extend_from_slice(&self.price.to_le_bytes());
+        payload.extend_from_slice(&self.paid.to_le_bytes());
+        tagged(SETTLEMENT_TAG, &payload)
+    }
+
+    /// Decodes a settlement from block data, returning [None] if the data
+    /// doesn't contain a settlement at all, see [BlockKind::from_data].
+    pub fn from_data(data: &BlockData) -> Result<Option<Self>> {
+        match BlockKind::from_data(data)? {
+            BlockKind::Settlement(settlement) => Ok(Some(settlement)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decodes the payload of a settlement, after its tag.
+    pub(crate) fn from_payload(payload: &[u8]) -> Result<Self> {
+        if payload.len() != Self::LEN {
+            return Err(Error::InvalidPayload);
+        }
+
+        Ok(Self {
+            token: TokenId(payload[..32].try_into().unwrap()),
+            price: u64::from_le_bytes(payload[32..40].try_into().unwrap()),
+            paid: u64::from_le_bytes(payload[40..].try_into().unwrap()),
+        })
+    }
+}
+
+/// Hook deciding whether transfers have settled the royalties of their token
+///
+/// Rules are consulted for every transfer whilst adding blocks to a chain and
+/// verifying it, after [Chain::set_royalty_rule] has been used. Each check only
+/// needs the chain itself, so rules can be run entirely offline.
+pub trait RoyaltyRule: fmt::Debug + Send + Sync {
+    /// Checks if a `transfer` of a token with the given `royalties` is allowed,
+    /// alongside the [Settlement] it references if one was found on the chain.
+    fn validate(
+        &self,
+        transfer: &Transfer,
+        royalties: &[Royalty],
+        settlement: Option<&Settlement>,
+    ) -> Result<bool>;
+}
+
+/// Royalty rule requiring transfers of tokens with royalties to reference a
+/// settlement which paid at least the amount owed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequireSettlement;
+
+impl RoyaltyRule for RequireSettlement {
+    fn validate(
+        &self,
+        _transfer: &Transfer,
+        royalties: &[Royalty],
+        settlement: Option<&Settlement>,
+    ) -> Result<bool> {
+        Ok(royalties.is_empty()
+            || settlement.is_some_and(|settlement| settlement.paid >= settlement.owed(royalties)))
+    }
+}
+
+impl Ledger {
+    /// Gets the royalties owed on resale of a token, as fixed when it was
+    /// minted, or [None] if the token isn't known to this ledger.
+    pub fn royalties(&self, token: &TokenId) -> Result<Option<Vec<Royalty>>> {
+        let record = match self.tokens.get(token) {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        record
+            .royalties
+            .iter()
+            .map(|(recipient, basis_points)| {
+                Ok(Royalty {
+                    recipient: PKey::public_key_from_raw_bytes(recipient, Id::ED25519)
+                        .map_err(Error::KeyRawPublic)?,
+                    basis_points: *basis_points,
+                })
+            })
+            .collect::<Result<_>>()
+            .map(Some)
+    }
+
+    /// Gets the settlement of a `token` recorded in the block with the given
+    /// `hash`, if it hasn't been used up by a transfer yet.
+    pub(crate) fn settlement(&self, token: &TokenId, hash: &Hash) -> Option<&Settlement> {
+        self.settlements
+            .get(hash)
+            .filter(|settlement| &settlement.token == token)
+    }
+
+    /// Checks if the next `block` follows a royalty `rule`, which always holds
+    /// for anything but transfers and locks of known tokens.
+    pub(crate) fn settles(&self, rule: &dyn RoyaltyRule, block: &Block) -> Result<bool> {
+        let transfer = match BlockKind::from_data(&block.data) {
+            Ok(BlockKind::Transfer(transfer)) => transfer,
+            // locks hand the token over once released, so they're sales too
+            Ok(BlockKind::Lock(lock)) => Transfer {
+                token: lock.token,
+                recipient: lock.recipient,
+                settlement: lock.settlement,
+            },
+            _ => return Ok(true),
+        };
+        let royalties = match self.royalties(&transfer.token)? {
+            Some(royalties) => royalties,
+            None => return Ok(true),
+        };
+        let settlement = transfer
+            .settlement
+            .as_ref()
+            .and_then(|hash| self.settlement(&transfer.token, hash));
+        rule.validate(&transfer, &royalties, settlement)
+    }
+}
+
+impl Chain {
+    /// Sets the rule which every transfer on this chain has to follow, see
+    /// [RoyaltyRule] for more information.
+    pub fn set_royalty_rule(&mut self, rule: impl RoyaltyRule + 'static) {
+        self.royalty_rule = Some(Arc::new(rule))
+    }
+
+    /// Records a `settlement` in a new block signed by one of the token's
+    /// royalty recipients, returning the hash of the new block for transfers to
+    /// reference.
+    pub fn settle(&mut self, settlement: &Settlement, keypair: &PKey<Private>) -> Result<Hash> {
+        self.push_signed(settlement.to_bytes(), keypair)?;
+        Ok(self.tip().hash.clone())
+    }
+
+    /// Transfers the `token` over to the `recipient` like [Chain::transfer],
+    /// referencing the block containing its royalty `settlement`; chainable
+    /// method.
+    pub fn transfer_settled(
+        &mut self,
+        token: &TokenId,
+        owner: &PKey<Private>,
+        recipient: &PKeyRef<impl HasPublic>,
+        settlement: &Hash,
+    ) -> Result<&mut Self> {
+        self.check_transferrer(token, owner)?;
+        let mut transfer = Transfer::new(token.clone(), recipient)?;
+        transfer.settlement = Some(settlement.clone());
+        self.push_signed(transfer.to_bytes()?, owner)
+    }
+
+    /// Locks the `token` for the `recipient` like [Chain::lock], referencing the
+    /// block containing its royalty `settlement`; chainable method.
+    pub fn lock_settled(
+        &mut self,
+        token: &TokenId,
+        owner: &PKey<Private>,
+        recipient: &PKeyRef<impl HasPublic>,
+        condition: Condition,
+        settlement: &Hash,
+    ) -> Result<&mut Self> {
+        let mut lock = Lock::new(token.clone(), recipient, condition)?;
+        lock.settlement = Some(settlement.clone());
+        self.push_locked(&lock, owner)
+    }
+
+    /// Verifies every transfer on this chain against a royalty `rule`, which
+    /// can be used to check a chain offline without setting the rule on it.
+    ///
+    /// Transfers made before the chain was pruned aren't checked, whilst
+    /// settlements recorded before then are still known to its snapshot.
+    pub fn verify_royalties(&self, rule: &dyn RoyaltyRule) -> Result<bool> {
+        let (mut ledger, start) = match &self.base {
+            Some(base) => (base.ledger.clone(), base.height + 1),
+            None => (Ledger::new(&self.blocks[0]), 1),
+        };
+        for block in self.blocks.iter().skip(start) {
+            if !ledger.settles(rule, block)? {
+                return Ok(false);
+            }
+            ledger.apply(block);
+        }
+        Ok(true)
+    }
+
+    /// Checks if the next block follows this chain's royalty rule, if it has one.
+    pub(crate) fn royalties_settled(&self, block: &Block) -> Result<bool> {
+        match &self.royalty_rule {
+            Some(rule) => self.ledger.settles(&**rule, block),
+            None => Ok(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChainTree, Delta};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn settled_transfers() {
+        let artist = PKey::generate_ed25519().unwrap();
+        let alice = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.set_royalty_rule(RequireSettlement);
+
+        let metadata = TokenMetadata::new("art", vec![Royalty::new(&artist, 1_000).unwrap()]);
+        let token = chain.mint(metadata.to_bytes().unwrap(), &artist).unwrap();
+        let plain = chain.mint("plain", &artist).unwrap();
+        chain.transfer(&plain, &artist, &alice).unwrap();
+
+        assert!(matches!(
+            chain.transfer(&token, &artist, &alice),
+            Err(Error::Unsettled)
+        ));
+        // buyers can't vouch for their own payments
+        assert!(matches!(
+            chain.settle(&Settlement::new(token.clone(), 1_000, 100), &alice),
+            Err(Error::Unauthorized)
+        ));
+        assert!(matches!(
+            chain.settle(&Settlement::new(plain.clone(), 1_000, 100), &artist),
+            Err(Error::Unauthorized)
+        ));
+        let underpaid = chain
+            .settle(&Settlement::new(token.clone(), 1_000, 99), &artist)
+            .unwrap();
+        assert!(matches!(
+            chain.transfer_settled(&token, &artist, &alice, &underpaid),
+            Err(Error::Unsettled)
+        ));
+        let paid = chain
+            .settle(&Settlement::new(token.clone(), 1_000, 100), &artist)
+            .unwrap();
+        chain
+            .transfer_settled(&token, &artist, &alice, &paid)
+            .unwrap();
+
+        // metadata updates can't remove royalties
+        chain.update_metadata(&token, "art", &alice).unwrap();
+        assert_eq!(chain.ledger().royalties(&token).unwrap().unwrap().len(), 1);
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn reused_settlements() {
+        let artist = PKey::generate_ed25519().unwrap();
+        let alice = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.set_royalty_rule(RequireSettlement);
+        let metadata = TokenMetadata::new("art", vec![Royalty::new(&artist, 1_000).unwrap()]);
+        let token = chain.mint(metadata.to_bytes().unwrap(), &artist).unwrap();
+        let paid = chain
+            .settle(&Settlement::new(token.clone(), 1_000, 100), &artist)
+            .unwrap();
+        chain
+            .transfer_settled(&token, &artist, &alice, &paid)
+            .unwrap();
+        let fork = chain.clone();
+
+        // a settlement only pays for a single sale
+        assert!(matches!(
+            chain.transfer_settled(&token, &alice, &artist, &paid),
+            Err(Error::Unsettled)
+        ));
+        let mut transfer = Transfer::new(token.clone(), &artist).unwrap();
+        transfer.settlement = Some(paid.clone());
+        let reused = Block::new_signed(chain.tip(), transfer.to_bytes().unwrap(), &alice).unwrap();
+
+        let mut blocks: Vec<Block> = chain.clone().into();
+        blocks.push(reused.clone());
+        let mut tampered = Chain::try_from(blocks).unwrap();
+        tampered.set_royalty_rule(RequireSettlement);
+        assert!(!tampered.verify().unwrap());
+        assert!(!tampered.verify_royalties(&RequireSettlement).unwrap());
+
+        // nor through any other way of appending blocks
+        let mut tree = ChainTree::new(&chain);
+        assert!(!tree.insert(&chain.tip().hash, reused.clone()).unwrap());
+        let delta = Delta {
+            from: fork.tip().hash.clone(),
+            blocks: vec![reused],
+        };
+        assert!(!chain.clone().apply_delta(&delta).unwrap());
+
+        // settlements below a pruned base can still be used once
+        let mut pruned = fork;
+        let fresh = pruned
+            .settle(&Settlement::new(token.clone(), 1_000, 100), &artist)
+            .unwrap();
+        pruned.extend_data(vec!["more", "data"]).unwrap();
+        pruned.prune(pruned.blocks().len() - 1).unwrap();
+        pruned
+            .transfer_settled(&token, &alice, &artist, &fresh)
+            .unwrap();
+        assert!(matches!(
+            pruned.transfer_settled(&token, &artist, &alice, &fresh),
+            Err(Error::Unsettled)
+        ));
+        assert!(pruned.verify().unwrap());
+    }
+
+    #[test]
+    fn metadata_payloads() {
+        let artist = PKey::generate_ed25519().unwrap();
+        let metadata = TokenMetadata::new("data", vec![Royalty::new(&artist, 250).unwrap()]);
+        let decoded = TokenMetadata::from_bytes(&metadata.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.data, b"data");
+        assert!(decoded.royalties[0].recipient.public_eq(&artist));
+        assert_eq!(decoded.royalties[0].basis_points, 250);
+
+        let plain = TokenMetadata::from_bytes(b"plain").unwrap();
+        assert!(plain.royalties.is_empty());
+        let greedy = TokenMetadata::new("greedy", vec![Royalty::new(&artist, 10_001).unwrap()]);
+        assert!(matches!(greedy.to_bytes(), Err(Error::InvalidPayload)));
+
+        let settlement = Settlement::new(TokenId([1; 32]), 12_345, 617);
+        let data = BlockData::new(settlement.to_bytes()).unwrap();
+        assert_eq!(
+            Settlement::from_data(&data).unwrap(),
+            Some(settlement.clone())
+        );
+        assert_eq!(settlement.owed(&metadata.royalties), 308);
+    }
+}
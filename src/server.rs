@@ -0,0 +1,285 @@
+//! HTTP/JSON REST API serving a stored chain, enabled with the `server` feature
+//!
+//! # Endpoints
+//!
+//! | Method | Path             | Description                                        |
+//! | ------ | ---------------- | -------------------------------------------------- |
+//! | `GET`  | `/blocks`        | Lists every block in the chain                     |
+//! | `GET`  | `/blocks/{hash}` | Fetches a single block by its hexadecimal hash     |
+//! | `POST` | `/blocks`        | Appends the raw request body as a new block        |
+//! | `GET`  | `/tokens`        | Lists every unburned token with its mint and owner |
+//! | `GET`  | `/tokens/{id}`   | Fetches a single token by its hexadecimal id       |
+//! | `POST` | `/transfers`     | Transfers a token owned by the server to someone   |
+//! | `GET`  | `/verify`        | Verifies the entire chain                          |
+//!
+//! New blocks are signed by the server's own keypair, so the server owns every
+//! token it creates and can transfer them to others. Raw blocks can't contain
+//! payloads onft understands, so only `/transfers` can act on tokens. Transfers take a JSON body
+//! containing the hexadecimal `token` id and `recipient` raw public key:
+//!
+//! ```json
+//! { "token": "426c6f6f...", "recipient": "d75a9801..." }
+//! ```
+//!
+//! Errors are returned with a fitting status code and a JSON body containing a
+//! single `error` message. Blocks the chain's current state doesn't allow, such
+//! as transfers of locked tokens, are rejected with `409 Conflict`, whilst
+//! blocks breaking its rules, such as unsettled royalties, are rejected with
+//! `422 Unprocessable Entity`.
+
+use crate::error::{Error, Result};
+use crate::kind::PAYLOAD_MAGIC;
+use crate::{from_hex, to_hex};
+use crate::{Block, Chain, Hash, Ledger, Ownership, Store, TokenId};
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use openssl::pkey::{Id, PKey, Private};
+use serde::Deserialize;
+use serde_json::json;
+use std::io;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+/// REST API server for a chain persisted using a [Store]
+///
+/// # Example
+///
+/// ```no_run
+/// use onft::server::Server;
+/// use onft::Store;
+/// use openssl::pkey::PKey;
+/// use tokio::net::TcpListener;
+///
+/// #[tokio::main]
+/// async fn main() -> onft::Result<()> {
+///     let (store, chain) = Store::open("chain.jsonl")?;
+///     let keypair = PKey::generate_ed25519().unwrap();
+///
+///     let listener = TcpListener::bind("127.0.0.1:8080").await?;
+///     Server::new(chain, store, keypair).serve(listener).await
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Server {
+    state: Arc<ServerState>,
+}
+
+/// State shared between every request to a [Server]
+#[derive(Debug)]
+struct ServerState {
+    stored: Arc<Mutex<(Chain, Store)>>,
+    keypair: PKey<Private>,
+}
+
+impl ServerState {
+    /// Runs an operation on the locked chain and its store.
+    ///
+    /// Operations verify, seal and store blocks, so they're run on a blocking
+    /// thread to keep them off of the runtime's threads.
+    async fn update<T: Send + 'static>(
+        &self,
+        op: impl FnOnce(&mut Chain, &mut Store) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let mut stored = self.stored.clone().lock_owned().await;
+        blocking(move || {
+            let (chain, store) = &mut *stored;
+            op(chain, store)
+        })
+        .await
+    }
+}
+
+impl Server {
+    /// Creates a new server for a `chain` which has been opened from a `store`,
+    /// signing new blocks using the given `keypair`.
+    pub fn new(chain: Chain, store: Store, keypair: PKey<Private>) -> Self {
+        Self {
+            state: Arc::new(ServerState {
+                stored: Arc::new(Mutex::new((chain, store))),
+                keypair,
+            }),
+        }
+    }
+
+    /// Creates the router containing every endpoint, which can be nested within
+    /// a larger application.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/blocks", get(list_blocks).post(append_block))
+            .route("/blocks/{hash}", get(get_block))
+            .route("/tokens", get(list_tokens))
+            .route("/tokens/{id}", get(get_token))
+            .route("/transfers", post(transfer))
+            .route("/verify", get(verify))
+            .with_state(self.state.clone())
+    }
+
+    /// Serves the API on the given listener forever.
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        axum::serve(listener, self.router())
+            .await
+            .map_err(Error::Serve)
+    }
+}
+
+/// Body of a transfer request, see the module-level documentation
+#[derive(Deserialize)]
+struct TransferRequest {
+    token: String,
+    recipient: String,
+}
+
+/// Error returned from an endpoint, converted into a fitting response
+struct ApiError(Error);
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        Self(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0 {
+            Error::UnknownBlock | Error::NotToken | Error::UnknownCollection => {
+                StatusCode::NOT_FOUND
+            }
+            Error::NotOwner => StatusCode::FORBIDDEN,
+            Error::Burned => StatusCode::GONE,
+            Error::InvalidHash | Error::KeyRawPublic(_) | Error::TypedPayload => {
+                StatusCode::BAD_REQUEST
+            }
+            // the chain's current state doesn't allow the block
+            Error::Unauthorized
+            | Error::ConsensusRejected
+            | Error::Locked
+            | Error::Retired
+            | Error::SupplyExhausted
+            | Error::NotReleasable
+            | Error::InvalidTimestamp
+            | Error::NotApproved => StatusCode::CONFLICT,
+            // the block itself breaks the chain's rules
+            Error::Unsettled
+            | Error::InvalidPayload
+            | Error::InvalidEditions
+            | Error::InvalidMultiSig
+            | Error::ThresholdNotMet => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(json!({ "error": self.0.to_string() }))).into_response()
+    }
+}
+
+type Shared = State<Arc<ServerState>>;
+
+async fn list_blocks(State(state): Shared) -> Json<Vec<Block>> {
+    Json(state.stored.lock().await.0.blocks.clone())
+}
+
+async fn get_block(
+    State(state): Shared,
+    Path(hash): Path<String>,
+) -> std::result::Result<Json<Block>, ApiError> {
+    let hash: Hash = hash.parse()?;
+    let stored = state.stored.lock().await;
+    let block = stored.0.blocks.iter().find(|block| block.hash == hash);
+    Ok(Json(block.cloned().ok_or(Error::UnknownBlock)?))
+}
+
+async fn list_tokens(
+    State(state): Shared,
+) -> std::result::Result<Json<Vec<serde_json::Value>>, ApiError> {
+    let stored = state.stored.lock().await;
+    let ledger = stored.0.ledger();
+    let mut tokens = vec![];
+    for (id, record) in &ledger.tokens {
+        if let Some(owner) = ledger.ownership(id)? {
+            tokens.push(token_json(id, &record.mint, &owner)?);
+        }
+    }
+    Ok(Json(tokens))
+}
+
+async fn get_token(
+    State(state): Shared,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<serde_json::Value>, ApiError> {
+    let id: TokenId = id.parse()?;
+    let stored = state.stored.lock().await;
+    let ledger = stored.0.ledger();
+    let owner = ledger.ownership(&id)?.ok_or(Error::NotToken)?;
+    let mint = ledger.mint(&id).ok_or(Error::NotToken)?;
+    Ok(Json(token_json(&id, mint, &owner)?))
+}
+
+async fn append_block(
+    State(state): Shared,
+    data: Bytes,
+) -> std::result::Result<(StatusCode, Json<Block>), ApiError> {
+    // typed payloads would be signed by the server's own key
+    if data.starts_with(&PAYLOAD_MAGIC) {
+        return Err(Error::TypedPayload.into());
+    }
+    let keypair = state.keypair.clone();
+    let block = state
+        .update(move |chain, store| {
+            chain.push_signed(data.to_vec(), &keypair)?;
+            persist_tip(chain, store)?;
+            Ok(chain.tip().clone())
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(block)))
+}
+
+async fn transfer(
+    State(state): Shared,
+    Json(request): Json<TransferRequest>,
+) -> std::result::Result<(StatusCode, Json<Block>), ApiError> {
+    let token: TokenId = request.token.parse()?;
+    let recipient = from_hex(&request.recipient).ok_or(Error::InvalidHash)?;
+    let recipient =
+        PKey::public_key_from_raw_bytes(&recipient, Id::ED25519).map_err(Error::KeyRawPublic)?;
+
+    let keypair = state.keypair.clone();
+    let block = state
+        .update(move |chain, store| {
+            chain.transfer(&token, &keypair, &recipient)?;
+            persist_tip(chain, store)?;
+            Ok(chain.tip().clone())
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(block)))
+}
+
+async fn verify(State(state): Shared) -> std::result::Result<Json<serde_json::Value>, ApiError> {
+    // verified from a copy so other requests aren't held up meanwhile
+    let chain = state.stored.lock().await.0.clone();
+    let verified = blocking(move || chain.verify()).await?;
+    Ok(Json(json!({ "verified": verified })))
+}
+
+/// Runs a computationally heavy operation on a blocking thread.
+async fn blocking<T: Send + 'static>(op: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(op)
+        .await
+        .map_err(|err| Error::Serve(io::Error::from(err)))?
+}
+
+/// Describes a single unburned token for responses.
+fn token_json(id: &TokenId, mint: &Hash, owner: &Ownership) -> Result<serde_json::Value> {
+    Ok(json!({ "id": id, "mint": mint, "owner": to_hex(&owner.to_raw_public()?) }))
+}
+
+/// Stores the latest block of the chain, removing it from the chain if it
+/// couldn't be stored so both stay the same.
+fn persist_tip(chain: &mut Chain, store: &mut Store) -> Result<()> {
+    store.append(chain.tip()).inspect_err(|_| {
+        chain.blocks.pop();
+        chain.ledger = Ledger::replay(chain);
+    })
+}
@@ -0,0 +1,230 @@
+//! Contains [Snapshot] and implementations
+
+use crate::{error::Error, Block, Chain, Checkpoint, Hash, Ledger, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// Ownership state of a chain at a single block, used to prune and bootstrap
+///
+/// # Using
+///
+/// Old blocks don't need their payloads once the ownership state they lead to
+/// is known, as the data hash kept inside each block is enough to verify the
+/// chain's signatures. You can, in high level terms, do the following:
+///
+/// - Take a snapshot of the current state of a chain: [Chain::snapshot]
+/// - Drop the payloads of old blocks, keeping their hashes: [Chain::prune]
+/// - Bootstrap a chain from a snapshot and its blocks: [Chain::from_snapshot]
+/// - Look up who owned what when a snapshot was taken: [Snapshot::ledger]
+///
+/// A snapshot can't be checked without the payloads it replaces, so it should
+/// only be bootstrapped from if it was made locally or if it matches a
+/// [Checkpoint] signed by a trusted key, whose signature covers the ledger as
+/// well as the tip; see [Snapshot::checkpoint] and [Snapshot::matches].
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let owner = PKey::generate_ed25519().unwrap();
+///     let mut chain = Chain::default();
+///     chain.push_signed("Hello, world!", &owner)?;
+///     let token = chain.token_id(&chain.tip().hash)?;
+///     chain.extend_data(vec!["more", "data"])?;
+///
+///     // drop old payloads but keep who owns what
+///     chain.prune(3)?;
+///     assert!(chain.blocks()[1].data.inner.is_empty());
+///     assert!(chain.verify()?);
+///
+///     // bootstrap a new node from the snapshot
+///     let snapshot = chain.snapshot();
+///     assert!(snapshot.ledger().owner(&token)?.is_some());
+///     let bootstrapped = Chain::from_snapshot(snapshot, chain.into())?;
+///     assert!(bootstrapped.verify()?);
+///     Ok(())
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Height of the block this snapshot was taken at.
+    pub height: usize,
+    /// Hash of the block this snapshot was taken at.
+    pub tip: Hash,
+    /// Ledger after applying every block up to the tip.
+    pub(crate) ledger: Ledger,
+}
+
+impl Snapshot {
+    /// Gets the ledger of who owned each token at the time of this snapshot.
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Creates an unsigned checkpoint of the block this snapshot was taken at
+    /// along with its ledger, which can then be signed so others can trust
+    /// this snapshot.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let mut checkpoint = Checkpoint::new(self.height, self.tip.clone());
+        checkpoint.ledger = Some(self.ledger.clone());
+        checkpoint
+    }
+
+    /// Checks if this snapshot is of the same block and ledger as the
+    /// `checkpoint`, so it can be trusted if the checkpoint's signature is.
+    pub fn matches(&self, checkpoint: &Checkpoint) -> bool {
+        self.height == checkpoint.height
+            && self.tip == checkpoint.hash
+            && checkpoint.ledger.as_ref() == Some(&self.ledger)
+    }
+}
+
+impl Chain {
+    /// Takes a snapshot of the ownership state at the tip of this chain, see
+    /// [Snapshot] for more information.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            height: self.blocks.len() - 1,
+            tip: self.tip().hash.clone(),
+            ledger: self.ledger.clone(),
+        }
+    }
+
+    /// Gets the snapshot this chain's payloads were pruned up to, or [None] if
+    /// it hasn't been pruned.
+    pub fn pruned(&self) -> Option<&Snapshot> {
+        self.base.as_ref()
+    }
+
+    /// Drops the payloads of every block below `height` whilst keeping their
+    /// data hashes, so the chain can still be verified; chainable method.
+    ///
+    /// The ownership state up to the pruned blocks is kept as a [Snapshot],
+    /// meaning transfers of tokens minted in pruned blocks still work. Blocks
+    /// which have already been pruned are left as-is.
+    pub fn prune(&mut self, height: usize) -> Result<&mut Self> {
+        if height > self.blocks.len() {
+            return Err(Error::UnknownBlock);
+        } else if height <= self.base.as_ref().map_or(0, |base| base.height + 1) {
+            return Ok(self);
+        }
+
+        let pruned = Chain::from_parts(
+            self.blocks[..height].to_vec(),
+            self.consensus.clone(),
+            self.base.take(),
+        );
+        self.base = Some(pruned.snapshot());
+        for block in self.blocks[..height].iter_mut() {
+            block.data.inner.clear();
+        }
+        Ok(self)
+    }
+
+    /// Creates a chain from existing `blocks` which start from the genesis
+    /// block, using the trusted `snapshot` in place of the payloads up to it.
+    ///
+    /// Payloads of the blocks up to the snapshot are dropped, as they may
+    /// have been pruned by whoever sent them, and the block at the snapshot's
+    /// height has to match its tip.
+    pub fn from_snapshot(snapshot: Snapshot, blocks: Vec<Block>) -> Result<Self> {
+        let mut chain = Chain::try_from(blocks)?;
+        match chain.blocks.get(snapshot.height) {
+            Some(block) if block.hash == snapshot.tip => (),
+            _ => return Err(Error::SnapshotMismatch),
+        }
+        for block in chain.blocks[..=snapshot.height].iter_mut() {
+            block.data.inner.clear();
+        }
+        chain.base = Some(snapshot);
+        chain.ledger = Ledger::replay(&chain);
+        Ok(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Policy, TokenId};
+    use openssl::pkey::PKey;
+
+    #[test]
+    fn prune_and_transfer() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::with_policy(&Policy::new(vec![&alice]).unwrap()).unwrap();
+        chain.push_signed("token", &alice).unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+        chain.push_signed("other", &alice).unwrap();
+
+        assert!(chain.pruned().is_none());
+        chain.prune(3).unwrap();
+        assert_eq!(chain.pruned().map(|base| base.height), Some(2));
+        assert!(chain.blocks.iter().all(|block| block.data.inner.is_empty()));
+        assert!(chain.blocks[1].verify(&chain.blocks[0].hash).unwrap());
+        assert!(chain.verify().unwrap());
+        assert!(chain.prune(4).is_err());
+
+        // state and policy survive the payloads being dropped
+        chain.transfer(&token, &alice, &bob).unwrap();
+        assert!(chain.push_signed("token", &bob).is_err());
+        assert!(chain.policy().unwrap().is_some());
+        assert_eq!(
+            chain.owner_of(&token).unwrap(),
+            bob.raw_public_key().unwrap()
+        );
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn bootstrap_from_snapshot() {
+        let owner = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.push_signed("token", &owner).unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+        let snapshot = chain.snapshot();
+        chain.push_data("after").unwrap();
+
+        assert_eq!(snapshot.ledger().tokens().collect::<Vec<_>>(), vec![&token]);
+        assert!(snapshot
+            .ledger()
+            .owner(&TokenId([0; 32]))
+            .unwrap()
+            .is_none());
+        assert!(chain.validate_checkpoint(&snapshot.checkpoint()));
+
+        // snapshots with a forged ledger don't match a signed checkpoint
+        let trusted = PKey::generate_ed25519().unwrap();
+        let signed = snapshot.checkpoint().sign(&trusted).unwrap();
+        assert!(signed.verify_signature(&trusted).unwrap());
+        assert!(snapshot.matches(&signed));
+        let mut forged = snapshot.clone();
+        forged.ledger.tokens.clear();
+        assert!(!forged.matches(&signed));
+        let mut resigned = signed.clone();
+        resigned.ledger = Some(forged.ledger.clone());
+        assert!(forged.matches(&resigned));
+        assert!(!resigned.verify_signature(&trusted).unwrap());
+
+        let blocks: Vec<Block> = chain.clone().into();
+        let bootstrapped = Chain::from_snapshot(snapshot.clone(), blocks.clone()).unwrap();
+        assert!(bootstrapped.blocks[1].data.inner.is_empty());
+        assert!(bootstrapped.verify().unwrap());
+        assert_eq!(
+            bootstrapped.owner_of(&token).unwrap(),
+            owner.raw_public_key().unwrap()
+        );
+
+        let mut moved = snapshot;
+        moved.height = 2;
+        assert!(matches!(
+            Chain::from_snapshot(moved, blocks),
+            Err(Error::SnapshotMismatch)
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+//! Contains [Store] and implementations, enabled with the `store` feature
+
+use crate::error::{Result, StoreError};
+use crate::{Block, Chain, Checkpoint, Snapshot};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Line at the start of a stored pruned chain containing its [Snapshot]
+#[derive(Serialize, Deserialize)]
+struct SnapshotLine {
+    snapshot: Snapshot,
+}
+
+/// Persistent storage engine keeping a [Chain] inside of a single file
+///
+/// # Using
+///
+/// Chains are stored as one JSON-encoded block per line, with new blocks simply
+/// being appended to the end of the file. You can, in high level terms, do the
+/// following:
+///
+/// - Open or create a stored chain: [Store::open]
+/// - Append a new block to the stored chain: [Store::append]
+/// - Replace the stored chain entirely, such as after a reorg: [Store::save]
+/// - Keep a checkpoint next to the stored chain: [Store::save_checkpoint] and
+///   [Store::load_checkpoint]
+///
+/// Keys for blocks owned by us are never stored, so every block is loaded as
+/// being owned by someone else. Pruned chains have their [Snapshot] stored on
+/// the first line, before the blocks, which is used in place of their dropped
+/// payloads when they're opened again.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::Store;
+///
+/// fn main() -> onft::Result<()> {
+///     let path = std::env::temp_dir().join("onft-store-example.jsonl");
+///     let (mut store, mut chain) = Store::open(&path)?;
+///
+///     chain.push_data("Hello, world!")?;
+///     store.append(chain.tip())?;
+///
+///     let (_, stored) = Store::open(&path)?;
+///     assert_eq!(stored.tip().hash, chain.tip().hash);
+///     # std::fs::remove_file(path).unwrap();
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Store {
+    path: PathBuf,
+    file: File,
+}
+
+impl Store {
+    /// Opens the chain stored at `path`, creating a new file containing just
+    /// the genesis block if it doesn't exist yet.
+    ///
+    /// The stored chain isn't verified beforehand, so you'll likely want to use
+    /// [Chain::verify] on it afterwards. If the chain was pruned when it was
+    /// saved, it's bootstrapped from its stored snapshot using
+    /// [Chain::from_snapshot].
+    pub fn open(path: impl AsRef<Path>) -> Result<(Self, Chain)> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            let mut store = Self::create(path)?;
+            let chain = Chain::default();
+            store.append(chain.tip())?;
+            return Ok((store, chain));
+        }
+
+        let mut blocks = vec![];
+        let mut snapshot = None;
+        let reader = BufReader::new(File::open(&path).map_err(StoreError::Io)?);
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(StoreError::Io)?;
+            if line.trim().is_empty() {
+                continue;
+            } else if index == 0 && line.starts_with("{\"snapshot\"") {
+                let decoded: SnapshotLine =
+                    serde_json::from_str(&line).map_err(StoreError::Snapshot)?;
+                snapshot = Some(decoded.snapshot);
+                continue;
+            }
+            blocks.push(serde_json::from_str(&line).map_err(|err| StoreError::Decode(index, err))?);
+        }
+
+        let chain = match snapshot {
+            Some(snapshot) => Chain::from_snapshot(snapshot, blocks)?,
+            None => Chain::try_from(blocks)?,
+        };
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(StoreError::Io)?;
+        Ok((Self { path, file }, chain))
+    }
+
+    /// Appends a single new block to the stored chain, which should directly
+    /// follow the currently stored latest block.
+    pub fn append(&mut self, block: &Block) -> Result<()> {
+        let mut line = serde_json::to_vec(block).map_err(StoreError::Encode)?;
+        line.push(b'\n');
+
+        self.file.write_all(&line).map_err(StoreError::Io)?;
+        self.file.sync_data().map_err(StoreError::Io)?;
+        Ok(())
+    }
+
+    /// Replaces the stored chain with an entirely new one, atomically swapping
+    /// the files so a crash midway leaves the previous chain intact.
+    ///
+    /// The snapshot of a pruned chain is written into the same file as its
+    /// blocks, so the two are always swapped together.
+    pub fn save(&mut self, chain: &Chain) -> Result<()> {
+        let temp_path = self.path.with_extension("tmp");
+        let mut temp = Self::create(temp_path.clone())?;
+        if let Some(base) = &chain.base {
+            let line = SnapshotLine {
+                snapshot: base.clone(),
+            };
+            let mut encoded = serde_json::to_vec(&line).map_err(StoreError::Encode)?;
+            encoded.push(b'\n');
+            temp.file.write_all(&encoded).map_err(StoreError::Io)?;
+        }
+        for block in chain.blocks.iter() {
+            temp.append(block)?;
+        }
+
+        fs::rename(&temp_path, &self.path).map_err(StoreError::Io)?;
+        self.file = temp.file;
+        Ok(())
+    }
+
+    /// Saves a checkpoint next to the stored chain, replacing any existing one,
+    /// so verification can resume from it using [Chain::verify_from].
+    pub fn save_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        let encoded = serde_json::to_vec(checkpoint).map_err(StoreError::Encode)?;
+        let temp_path = self.checkpoint_path().with_extension("checkpoint.tmp");
+        fs::write(&temp_path, encoded).map_err(StoreError::Io)?;
+        fs::rename(&temp_path, self.checkpoint_path()).map_err(StoreError::Io)?;
+        Ok(())
+    }
+
+    /// Loads the checkpoint saved next to the stored chain, if one has been
+    /// saved. The checkpoint should be validated against the chain before
+    /// being trusted, as the two files could have diverged.
+    pub fn load_checkpoint(&self) -> Result<Option<Checkpoint>> {
+        let path = self.checkpoint_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let encoded = fs::read(path).map_err(StoreError::Io)?;
+        serde_json::from_slice(&encoded)
+            .map(Some)
+            .map_err(|err| StoreError::Checkpoint(err).into())
+    }
+
+    /// Gets the path of the file checkpoints are stored in.
+    fn checkpoint_path(&self) -> PathBuf {
+        self.path.with_extension("checkpoint")
+    }
+
+    /// Gets the path of the file the chain is stored in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Creates a new empty store at `path`, overwriting any existing file.
+    fn create(path: PathBuf) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(StoreError::Io)?;
+        Ok(Self { path, file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use openssl::pkey::PKey;
+
+    /// Gets a unique path inside of the temporary directory for a test
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("onft-{}-{}.jsonl", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn append_and_reopen() {
+        let path = temp_path("append");
+        let (mut store, mut chain) = Store::open(&path).unwrap();
+        chain.extend_data(vec!["first", "second"]).unwrap();
+        for block in chain.blocks[1..].iter() {
+            store.append(block).unwrap();
+        }
+
+        let (_, stored) = Store::open(&path).unwrap();
+        assert_eq!(stored.tip().hash, chain.tip().hash);
+        assert!(stored.verify().unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn save_and_corrupt() {
+        let path = temp_path("save");
+        let (mut store, mut chain) = Store::open(&path).unwrap();
+        chain.push_data("Hello, world!").unwrap();
+        store.save(&chain).unwrap();
+        assert_eq!(Store::open(&path).unwrap().1.tip().hash, chain.tip().hash);
+
+        fs::write(&path, "not json\n").unwrap();
+        assert!(matches!(
+            Store::open(&path),
+            Err(Error::Store(StoreError::Decode(0, _)))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn checkpoints() {
+        let path = temp_path("checkpoint");
+        let (store, mut chain) = Store::open(&path).unwrap();
+        assert!(store.load_checkpoint().unwrap().is_none());
+
+        chain.push_data("Hello, world!").unwrap();
+        let checkpoint = chain.checkpoint(1).unwrap();
+        store.save_checkpoint(&checkpoint).unwrap();
+        assert_eq!(store.load_checkpoint().unwrap(), Some(checkpoint));
+
+        fs::remove_file(store.checkpoint_path()).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn pruned_chains() {
+        let path = temp_path("pruned");
+        let (mut store, mut chain) = Store::open(&path).unwrap();
+        let owner = PKey::generate_ed25519().unwrap();
+        let token = chain.mint("token", &owner).unwrap();
+        chain.extend_data(vec!["first", "second"]).unwrap();
+        chain.prune(3).unwrap();
+        store.save(&chain).unwrap();
+
+        let (mut store, stored) = Store::open(&path).unwrap();
+        assert_eq!(stored.snapshot(), chain.snapshot());
+        assert!(stored.ledger().owner(&token).unwrap().is_some());
+        assert!(stored.verify().unwrap());
+
+        // the snapshot is kept in the same file, so new blocks append after it
+        let mut stored = stored;
+        stored.push_data("third").unwrap();
+        store.append(stored.tip()).unwrap();
+        let (mut store, reopened) = Store::open(&path).unwrap();
+        assert_eq!(reopened.tip().hash, stored.tip().hash);
+        assert!(reopened.verify().unwrap());
+
+        // saving an unpruned chain drops the old snapshot
+        store.save(&Chain::default()).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("snapshot"));
+        assert!(Store::open(&path).unwrap().1.verify().unwrap());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+//! Contains [Delta] and implementations
+
+use crate::{error::Error, Block, Chain, Hash, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Blocks a peer is missing, used to incrementally synchronize two replicas
+///
+/// # Using
+///
+/// Instead of shipping an entire [Chain] to a peer, you can ask it for the hash
+/// of its latest block, create a delta of the blocks it's missing using
+/// [Chain::delta] and send it over whatever transport you're using. The peer
+/// then applies it using [Chain::apply_delta], verifying each block as it goes.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+///
+/// fn main() -> onft::Result<()> {
+///     let mut ours = Chain::default();
+///     let mut theirs = ours.clone();
+///     ours.extend_data(vec!["Hello", "world"])?;
+///
+///     let delta = ours.delta(&theirs.tip().hash)?;
+///     assert_eq!(delta.blocks.len(), 2);
+///
+///     if theirs.apply_delta(&delta)? {
+///         println!("Synchronized")
+///     } else {
+///         eprintln!("Not synchronized")
+///     }
+///     Ok(())
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Delta {
+    /// Hash of the latest block the peer already has.
+    pub from: Hash,
+    /// Blocks following on from the [Delta::from] block in order.
+    pub blocks: Vec<Block>,
+}
+
+impl Chain {
+    /// Gets the blocks which come after the known `tip` block, being what a peer
+    /// whose latest block is `tip` is missing.
+    pub fn missing(&self, tip: &Hash) -> Result<&[Block]> {
+        let index = self
+            .blocks
+            .iter()
+            .rposition(|block| &block.hash == tip)
+            .ok_or(Error::UnknownBlock)?;
+        Ok(&self.blocks[index + 1..])
+    }
+
+    /// Creates a delta containing all blocks a peer whose latest block is `tip`
+    /// is missing, see [Delta] for more information.
+    pub fn delta(&self, tip: &Hash) -> Result<Delta> {
+        Ok(Delta {
+            from: tip.clone(),
+            blocks: self.missing(tip)?.to_vec(),
+        })
+    }
+
+    /// Applies a delta created by a peer, verifying each new block beforehand
+    /// using [Block::verify] and the chain's consensus, leaving this chain
+    /// untouched if any of them don't verify.
+    ///
+    /// Blocks contained in the delta which this chain already has are skipped,
+    /// whilst `false` is returned if the delta conflicts with blocks this chain
+    /// has, meaning the two have forked and a [ChainTree](crate::ChainTree)
+    /// should be used instead.
+    pub fn apply_delta(&mut self, delta: &Delta) -> Result<bool> {
+        let ours = self.missing(&delta.from)?;
+        let known = ours.len().min(delta.blocks.len());
+        if ours[..known]
+            .iter()
+            .zip(delta.blocks.iter())
+            .any(|(ours, theirs)| ours.hash != theirs.hash)
+        {
+            return Ok(false);
+        }
+
+        let new_blocks = &delta.blocks[known..];
+        let mut previous = self.tip();
+        for block in new_blocks.iter() {
+            if !self.verify_link(previous, block)? {
+                return Ok(false);
+            }
+            previous = block
+        }
+        // only the new blocks need applying on top of the current ledger
+        let rule = self.royalty_rule.as_deref();
+        self.ledger = match self.ledger.clone().folded(new_blocks, rule)? {
+            Some(ledger) => ledger,
+            None => return Ok(false),
+        };
+        self.blocks.extend(new_blocks.iter().cloned());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Ledger, Transfer};
+    use openssl::pkey::PKey;
+
+    #[test]
+    fn incremental_sync() {
+        let mut ours = Chain::default();
+        let mut theirs = ours.clone();
+        ours.push_data("first").unwrap();
+
+        assert!(theirs
+            .apply_delta(&ours.delta(&theirs.tip().hash).unwrap())
+            .unwrap());
+        let partial = ours.delta(&theirs.tip().hash).unwrap();
+        assert!(partial.blocks.is_empty());
+
+        ours.extend_data(vec!["second", "third"]).unwrap();
+        let overlapping = ours.delta(&Hash::default()).unwrap();
+        assert!(theirs.apply_delta(&overlapping).unwrap());
+
+        assert_eq!(theirs.tip().hash, ours.tip().hash);
+        assert!(theirs.verify().unwrap());
+    }
+
+    #[test]
+    fn rejected_deltas() {
+        let mut ours = Chain::default();
+        let mut theirs = ours.clone();
+        ours.push_data("ours").unwrap();
+        theirs.push_data("theirs").unwrap();
+
+        let forked = ours.delta(&Hash::default()).unwrap();
+        assert!(!theirs.apply_delta(&forked).unwrap());
+
+        let mut tampered = ours.delta(&Hash::default()).unwrap();
+        tampered.from = theirs.tip().hash.clone();
+        assert!(!theirs.apply_delta(&tampered).unwrap());
+
+        // genesis blocks from untrusted peers are rejected rather than errors
+        let genesis = Delta {
+            from: theirs.tip().hash.clone(),
+            blocks: vec![Block::new_genesis("another").unwrap()],
+        };
+        assert!(!theirs.apply_delta(&genesis).unwrap());
+
+        let unknown = theirs.delta(&theirs.tip().hash).unwrap();
+        assert!(matches!(
+            ours.apply_delta(&unknown),
+            Err(Error::UnknownBlock)
+        ));
+        assert_eq!(theirs.blocks.len(), 2);
+    }
+
+    #[test]
+    fn ledger_follows_deltas() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut ours = Chain::default();
+        let mut theirs = ours.clone();
+        let token = ours.mint("token", &alice).unwrap();
+        ours.transfer(&token, &alice, &bob).unwrap();
+
+        // announced one block at a time, like gossip does
+        for block in ours.blocks()[1..].iter() {
+            let delta = Delta {
+                from: theirs.tip().hash.clone(),
+                blocks: vec![block.clone()],
+            };
+            assert!(theirs.apply_delta(&delta).unwrap());
+        }
+        assert_eq!(theirs.ledger(), ours.ledger());
+        assert_eq!(theirs.ledger(), &Ledger::replay(&theirs));
+
+        // transfers by someone who doesn't own the token leave it untouched
+        let stolen = Transfer::new(token, &alice).unwrap().to_bytes().unwrap();
+        let delta = Delta {
+            from: theirs.tip().hash.clone(),
+            blocks: vec![Block::new_signed(theirs.tip(), stolen, &alice).unwrap()],
+        };
+        let before = theirs.ledger().clone();
+        assert!(!theirs.apply_delta(&delta).unwrap());
+        assert_eq!(theirs.ledger(), &before);
+    }
+}
@@ -0,0 +1,187 @@
+//! Contains [TokenId] and implementations
+
+use crate::error::Error;
+use crate::hash::{from_hex, to_hex};
+use crate::{Block, Chain, Hash, Result};
+use openssl::sha::Sha256;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{convert::TryInto, fmt, str::FromStr};
+
+/// Prefix of hashed token identifiers, keeping them distinct from block hashes
+const TOKEN_DOMAIN: &[u8] = b"onft token";
+
+/// Stable identifier of a token, assigned when it's minted
+///
+/// # Using
+///
+/// Each token is identified by the hash of the chain it was minted on, being
+/// the hash of the chain's genesis block, alongside the hash of the block which
+/// minted it. Unlike block hashes, a token's identifier keeps referring to the
+/// token itself as it's transferred and can't be confused with the hash of any
+/// other block. You can, in high level terms, do the following:
+///
+/// - Get the identifier of a token minted by a block: [Chain::token_id]
+/// - Find the block which minted a token: [Chain::token]
+/// - Transfer a token using its identifier: [Chain::transfer]
+/// - Format and parse identifiers as hexadecimal: [TokenId]'s display and [FromStr]
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let owner = PKey::generate_ed25519().unwrap();
+///     let mut chain = Chain::default();
+///     chain.push_signed("Hello, world!", &owner)?;
+///
+///     let mint = chain.tip().hash.clone();
+///     let token = chain.token_id(&mint)?;
+///     assert_eq!(chain.token(&token).unwrap().hash, mint);
+///
+///     let parsed: TokenId = token.to_string().parse()?;
+///     assert_eq!(parsed, token);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TokenId(pub(crate) [u8; 32]);
+
+impl TokenId {
+    /// Creates the identifier of a token minted by the block with the `mint`
+    /// hash on the chain with the `chain` genesis hash.
+    pub fn new(chain: &Hash, mint: &Hash) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(TOKEN_DOMAIN);
+        hasher.update(&chain.0[..]);
+        hasher.update(&mint.0[..]);
+        Self(hasher.finish())
+    }
+}
+
+impl Chain {
+    /// Gets the identifier of this chain, which is the hash of its genesis block.
+    pub fn id(&self) -> &Hash {
+        &self.blocks[0].hash
+    }
+
+    /// Gets the identifier of the token minted by the block with the `mint`
+    /// hash, see [TokenId] for more information.
+    pub fn token_id(&self, mint: &Hash) -> Result<TokenId> {
+        let token = TokenId::new(self.id(), mint);
+        if self.ledger.contains(&token) {
+            Ok(token)
+        } else if self.blocks.iter().any(|block| &block.hash == mint) {
+            Err(Error::NotToken)
+        } else {
+            Err(Error::UnknownBlock)
+        }
+    }
+
+    /// Finds the block which minted the given token, returning [None] if the
+    /// token isn't known or its block has since been pruned.
+    pub fn token(&self, token: &TokenId) -> Option<&Block> {
+        let mint = self.ledger.mint(token)?;
+        self.blocks.iter().rev().find(|block| &block.hash == mint)
+    }
+}
+
+impl fmt::Display for TokenId {
+    /// Formats this identifier as lowercase hexadecimal.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", to_hex(&self.0[..]))
+    }
+}
+
+impl FromStr for TokenId {
+    type Err = Error;
+
+    /// Parses an identifier from hexadecimal, as formatted by its display.
+    fn from_str(s: &str) -> Result<Self> {
+        from_hex(s)
+            .and_then(|bytes| bytes[..].try_into().ok())
+            .map(Self)
+            .ok_or(Error::InvalidHash)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for TokenId {
+    /// Serializes as hexadecimal for human-readable formats and as raw bytes
+    /// otherwise.
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for TokenId {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(|err| de::Error::custom(format!("{}", err)))
+        } else {
+            <[u8; 32]>::deserialize(deserializer).map(Self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::pkey::PKey;
+
+    #[test]
+    fn ids_per_chain() {
+        let owner = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.push_signed("token", &owner).unwrap();
+        let mint = chain.tip().hash.clone();
+        let token = chain.token_id(&mint).unwrap();
+
+        assert_ne!(token.0, mint.0);
+        assert_ne!(token, TokenId::new(&Hash([1; 32]), &mint));
+        assert!(matches!(chain.token_id(chain.id()), Err(Error::NotToken)));
+        assert!(matches!(
+            chain.token_id(&Hash([1; 32])),
+            Err(Error::UnknownBlock)
+        ));
+        assert!(chain.token(&TokenId([0; 32])).is_none());
+    }
+
+    #[test]
+    fn stable_through_transfers() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.push_signed("token", &alice).unwrap();
+        let mint = chain.tip().hash.clone();
+        let token = chain.token_id(&mint).unwrap();
+
+        chain.transfer(&token, &alice, &bob).unwrap();
+        chain.transfer(&token, &bob, &alice).unwrap();
+        assert_eq!(chain.token(&token).unwrap().hash, mint);
+        assert!(chain
+            .ledger()
+            .owner(&token)
+            .unwrap()
+            .unwrap()
+            .public_eq(&alice));
+
+        assert_eq!(token.to_string().parse::<TokenId>().unwrap(), token);
+        assert!("nope".parse::<TokenId>().is_err());
+    }
+}
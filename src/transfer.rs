@@ -0,0 +1,286 @@
+//! Contains [Transfer] and implementations
+
+use crate::kind::{tagged, MULTISIG_TRANSFER_TAG, TRANSFER_TAG};
+use crate::{
+    error::Error, BlockData, BlockKind, Chain, Hash, MultiSig, Ownership, Result, TokenId,
+};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private};
+use std::convert::TryInto;
+
+/// Transfer of a token's ownership over to a new owner
+///
+/// # Using
+///
+/// Blocks which mint a token, see [BlockKind](crate::BlockKind), are initially
+/// owned by the keypair which signed them and identified by their [TokenId].
+/// A token can then be transferred by its current owner, which signs a new
+/// block containing this transfer as its data. You'll typically want to use
+/// [Chain::transfer] instead of creating transfers directly.
+///
+/// # Example
+///
+/// ```rust
+/// use onft::prelude::*;
+/// use openssl::pkey::PKey;
+///
+/// fn main() -> onft::Result<()> {
+///     let owner = PKey::generate_ed25519().unwrap();
+///     let recipient = PKey::generate_ed25519().unwrap();
+///
+///     let mut chain = Chain::default();
+///     chain.push_signed("Hello, world!", &owner)?;
+///     let token = chain.token_id(&chain.tip().hash)?;
+///
+///     chain.transfer(&token, &owner, &recipient)?;
+///     let transfer = Transfer::from_data(&chain.tip().data)?.unwrap();
+///
+///     assert_eq!(transfer.token, token);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    /// Identifier of the transferred token.
+    pub token: TokenId,
+    /// New owner, being either a single public key or a [MultiSig].
+    pub recipient: Ownership,
+    /// Hash of the block containing the royalty [Settlement](crate::Settlement)
+    /// for this transfer, if there is one.
+    pub settlement: Option<Hash>,
+}
+
+impl Transfer {
+    /// Length of the payload of a transfer to a single key in bytes, without a
+    /// settlement
+    const LEN: usize = 32 + 32;
+
+    /// Creates a new transfer of the `token` over to the `recipient`.
+    pub fn new(token: TokenId, recipient: &PKeyRef<impl HasPublic>) -> Result<Self> {
+        let raw = recipient.raw_public_key().map_err(Error::KeyPublic)?;
+        Ok(Self {
+            token,
+            recipient: Ownership::Them(
+                PKey::public_key_from_raw_bytes(&raw, Id::ED25519).map_err(Error::KeyRawPublic)?,
+            ),
+            settlement: None,
+        })
+    }
+
+    /// Creates a new transfer of the `token` over to a `recipient` which is
+    /// jointly owned by several keys.
+    pub fn to_multisig(token: TokenId, recipient: MultiSig) -> Self {
+        Self {
+            token,
+            recipient: Ownership::MultiSig(recipient),
+            settlement: None,
+        }
+    }
+
+    /// Encodes this transfer into bytes to be used as block data.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut payload = self.token.0.to_vec();
+        if let Ownership::MultiSig(multisig) = &self.recipient {
+            match &self.settlement {
+                Some(settlement) => {
+                    payload.push(1);
+                    payload.extend_from_slice(&settlement.0[..]);
+                }
+                None => payload.push(0),
+            }
+            payload.extend(multisig.to_bytes()?);
+            return Ok(tagged(MULTISIG_TRANSFER_TAG, &payload));
+        }
+
+        payload.extend(self.recipient.to_raw_public()?);
+        if let Some(settlement) = &self.settlement {
+            payload.extend_from_slice(&settlement.0[..]);
+        }
+        Ok(tagged(TRANSFER_TAG, &payload))
+    }
+
+    /// Decodes a transfer from block data, returning [None] if the data doesn't
+    /// contain a transfer at all, see [BlockKind::from_data].
+    pub fn from_data(data: &BlockData) -> Result<Option<Self>> {
+        match BlockKind::from_data(data)? {
+            BlockKind::Transfer(transfer) => Ok(Some(transfer)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decodes the payload of a transfer to a single key, after its tag.
+    pub(crate) fn from_payload(payload: &[u8]) -> Result<Self> {
+        if payload.len() != Self::LEN && payload.len() != Self::LEN + 32 {
+            return Err(Error::InvalidPayload);
+        }
+
+        let token = TokenId(payload[..32].try_into().unwrap());
+        let recipient = PKey::public_key_from_raw_bytes(&payload[32..Self::LEN], Id::ED25519)
+            .map_err(|_| Error::InvalidPayload)?;
+        let settlement = payload
+            .get(Self::LEN..)
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| Hash(rest.try_into().unwrap()));
+        Ok(Self {
+            token,
+            recipient: Ownership::Them(recipient),
+            settlement,
+        })
+    }
+
+    /// Decodes the payload of a transfer to a [MultiSig], after its tag.
+    pub(crate) fn from_multisig_payload(payload: &[u8]) -> Result<Self> {
+        if payload.len() < 33 {
+            return Err(Error::InvalidPayload);
+        }
+        let token = TokenId(payload[..32].try_into().unwrap());
+        let (settlement, recipient) = match payload[32] {
+            0 => (None, &payload[33..]),
+            1 if payload.len() >= 65 => (
+                Some(Hash(payload[33..65].try_into().unwrap())),
+                &payload[65..],
+            ),
+            _ => return Err(Error::InvalidPayload),
+        };
+        let recipient = MultiSig::from_bytes(recipient).map_err(|_| Error::InvalidPayload)?;
+        Ok(Self {
+            token,
+            recipient: Ownership::MultiSig(recipient),
+            settlement,
+        })
+    }
+}
+
+impl Chain {
+    /// Transfers the `token` over to the `recipient`, adding a new block signed
+    /// by its current `owner`; chainable method. The block may instead be signed
+    /// by a delegate which the owner has approved, see
+    /// [Approval](crate::Approval).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use onft::prelude::*;
+    /// use openssl::pkey::PKey;
+    ///
+    /// fn main() -> onft::Result<()> {
+    ///     let owner = PKey::generate_ed25519().unwrap();
+    ///     let recipient = PKey::generate_ed25519().unwrap();
+    ///
+    ///     let mut chain = Chain::default();
+    ///     chain.push_signed("Hello, world!", &owner)?;
+    ///     let token = chain.token_id(&chain.tip().hash)?;
+    ///
+    ///     chain.transfer(&token, &owner, &recipient)?;
+    ///
+    ///     // we don't own it anymore
+    ///     assert!(chain.transfer(&token, &owner, &recipient).is_err());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn transfer(
+        &mut self,
+        token: &TokenId,
+        owner: &PKey<Private>,
+        recipient: &PKeyRef<impl HasPublic>,
+    ) -> Result<&mut Self> {
+        self.check_transferrer(token, owner)?;
+        self.push_signed(Transfer::new(token.clone(), recipient)?.to_bytes()?, owner)
+    }
+
+    /// Checks if the `keypair` may transfer a token, being either its current
+    /// owner or a delegate they've approved.
+    pub(crate) fn check_transferrer(&self, token: &TokenId, keypair: &PKey<Private>) -> Result<()> {
+        self.owner_of(token)?;
+        let signer = keypair.raw_public_key().map_err(Error::KeyPublic)?;
+        if self
+            .ledger
+            .may_transfer(&self.ledger.tokens[token], &signer)
+        {
+            Ok(())
+        } else {
+            Err(Error::NotOwner)
+        }
+    }
+
+    /// Checks if the `keypair` is the current owner of a token, which delegates
+    /// aren't.
+    pub(crate) fn check_owner(&self, token: &TokenId, keypair: &PKey<Private>) -> Result<()> {
+        if self.owner_of(token)? == keypair.raw_public_key().map_err(Error::KeyPublic)? {
+            Ok(())
+        } else {
+            Err(Error::NotOwner)
+        }
+    }
+
+    /// Finds the raw public key of the current owner of a token using the
+    /// chain's ledger.
+    pub(crate) fn owner_of(&self, token: &TokenId) -> Result<Vec<u8>> {
+        match self.ledger.tokens.get(token) {
+            Some(record) if record.burned => Err(Error::Burned),
+            Some(record) if record.locked.is_some() => Err(Error::Locked),
+            Some(record) => Ok(record.owner.clone()),
+            None => Err(Error::NotToken),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kind::PAYLOAD_MAGIC;
+    use crate::Hash;
+
+    #[test]
+    fn transfer_ownership() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let bob = PKey::generate_ed25519().unwrap();
+        let carol = PKey::generate_ed25519().unwrap();
+
+        let mut chain = Chain::default();
+        chain.push_signed("token", &alice).unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+
+        chain.transfer(&token, &alice, &bob).unwrap();
+        assert!(matches!(
+            chain.transfer(&token, &alice, &carol),
+            Err(Error::NotOwner)
+        ));
+        chain.transfer(&token, &bob, &carol).unwrap();
+
+        assert_eq!(
+            chain.owner_of(&token).unwrap(),
+            carol.raw_public_key().unwrap()
+        );
+        assert!(chain.verify().unwrap());
+    }
+
+    #[test]
+    fn not_tokens() {
+        let alice = PKey::generate_ed25519().unwrap();
+        let mut chain = Chain::default();
+        chain.push_signed("token", &alice).unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+        chain.transfer(&token, &alice, &alice).unwrap();
+
+        assert!(matches!(
+            chain.token_id(&Hash::default()),
+            Err(Error::NotToken)
+        ));
+        assert!(matches!(
+            chain.token_id(&chain.tip().hash),
+            Err(Error::NotToken)
+        ));
+        assert!(matches!(
+            chain.owner_of(&TokenId([0; 32])),
+            Err(Error::NotToken)
+        ));
+        assert!(matches!(
+            Transfer::from_data(&BlockData::new(&PAYLOAD_MAGIC[..]).unwrap()),
+            Ok(None)
+        ));
+        assert!(matches!(
+            Transfer::from_data(&BlockData::new(&b"\0nft\x01short"[..]).unwrap()),
+            Err(Error::InvalidPayload)
+        ));
+    }
+}
@@ -0,0 +1,76 @@
+use onft::error::{Error, NetError};
+use onft::net::{self, Message, Node};
+use onft::prelude::*;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+/// Spawns a new listening node on localhost, returning it and its address
+fn spawn_node(chain: Chain) -> (Node, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let node = Node::new(chain);
+    node.spawn(listener);
+    (node, addr)
+}
+
+#[test]
+fn nodes_converge() {
+    let mut chain = Chain::default();
+    chain.extend_data(vec!["first", "second"]).unwrap();
+    let (server, addr) = spawn_node(chain);
+
+    let client = Node::default();
+    assert!(client.sync(addr).unwrap());
+    assert_eq!(client.chain().tip().hash, server.chain().tip().hash);
+
+    client.chain().extend_data(vec!["third", "fourth"]).unwrap();
+    assert!(client.sync(addr).unwrap());
+    assert_eq!(client.chain().tip().hash, server.chain().tip().hash);
+
+    let other = Node::default();
+    assert!(other.sync(addr).unwrap());
+    assert_eq!(other.chain().tip().hash, client.chain().tip().hash);
+    assert!(other.chain().verify().unwrap());
+}
+
+#[test]
+fn forked_nodes() {
+    let (server, addr) = spawn_node(Chain::default());
+    server.chain().push_data("server").unwrap();
+
+    let client = Node::default();
+    client.chain().push_data("client").unwrap();
+
+    assert!(!client.sync(addr).unwrap());
+    assert_ne!(client.chain().tip().hash, server.chain().tip().hash);
+}
+
+#[test]
+fn version_mismatch() {
+    let (_, addr) = spawn_node(Chain::default());
+    let mut stream = TcpStream::connect(addr).unwrap();
+
+    net::write_message(&mut stream, &Message::Handshake(u8::MAX)).unwrap();
+    assert!(matches!(
+        net::read_message(&mut stream).unwrap(),
+        Message::Handshake(onft::PROTO_VERSION)
+    ));
+    assert!(matches!(
+        net::read_message(&mut stream),
+        Err(Error::Net(NetError::Io(_)))
+    ));
+}
+
+#[test]
+fn batched_sync() {
+    // far more than fits inside of a single frame
+    let mut chain = Chain::default();
+    chain
+        .extend_data((0..12).map(|index| vec![index; 1024 * 1024]))
+        .unwrap();
+    let (server, addr) = spawn_node(chain);
+
+    let client = Node::default();
+    assert!(client.sync(addr).unwrap());
+    assert_eq!(client.chain().tip().hash, server.chain().tip().hash);
+    assert!(client.chain().verify().unwrap());
+}
@@ -0,0 +1,211 @@
+use onft::server::Server;
+use onft::{
+    to_hex, BlockKind, Chain, Condition, Lock, RequireSettlement, Rotation, Royalty, Store,
+    TokenMetadata,
+};
+use openssl::pkey::{PKey, Private};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Spawns a new server on localhost backed by a fresh store, returning its
+/// address and the path of the store
+async fn spawn_server(name: &str) -> (SocketAddr, PathBuf) {
+    spawn_server_with(name, |_, _| ()).await
+}
+
+/// Spawns a new server like [spawn_server], letting `setup` configure its chain
+/// with the server's keypair beforehand
+async fn spawn_server_with(
+    name: &str,
+    setup: impl FnOnce(&mut Chain, &PKey<Private>),
+) -> (SocketAddr, PathBuf) {
+    let path =
+        std::env::temp_dir().join(format!("onft-server-{}-{}.jsonl", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (store, mut chain) = Store::open(&path).unwrap();
+    let keypair = PKey::generate_ed25519().unwrap();
+    setup(&mut chain, &keypair);
+    let server = Server::new(chain, store, keypair);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { server.serve(listener).await });
+    (addr, path)
+}
+
+/// Sends a single HTTP request, returning the status code and JSON body
+async fn request(addr: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        path,
+        addr,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await.unwrap();
+    stream.write_all(body).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[tokio::test]
+async fn append_and_fetch() {
+    let (addr, path) = spawn_server("append").await;
+
+    let (status, block) = request(addr, "POST", "/blocks", b"Hello, world!").await;
+    assert_eq!(status, 201);
+    let hash = block["hash"].as_str().unwrap();
+
+    let (status, blocks) = request(addr, "GET", "/blocks", b"").await;
+    assert_eq!(status, 200);
+    assert_eq!(blocks.as_array().unwrap().len(), 2);
+
+    let (status, fetched) = request(addr, "GET", &format!("/blocks/{}", hash), b"").await;
+    assert_eq!((status, &fetched), (200, &block));
+    let (status, _) = request(addr, "GET", &format!("/blocks/{:0>64}", 0), b"").await;
+    assert_eq!(status, 404);
+    let (status, _) = request(addr, "GET", "/blocks/nothex", b"").await;
+    assert_eq!(status, 400);
+
+    let (_, stored) = Store::open(&path).unwrap();
+    assert_eq!(stored.tip().hash.to_string(), hash);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn transfer_and_verify() {
+    let (addr, path) = spawn_server("transfer").await;
+    let recipient = PKey::generate_ed25519().unwrap();
+    let recipient = to_hex(&recipient.raw_public_key().unwrap());
+
+    let (_, block) = request(addr, "POST", "/blocks", b"token").await;
+    let (_, tokens) = request(addr, "GET", "/tokens", b"").await;
+    let token = &tokens[0];
+    assert_eq!(token["mint"], block["hash"]);
+    let (status, fetched) = request(
+        addr,
+        "GET",
+        &format!("/tokens/{}", token["id"].as_str().unwrap()),
+        b"",
+    )
+    .await;
+    assert_eq!((status, &fetched), (200, token));
+
+    let transfer = json!({ "token": token["id"], "recipient": recipient });
+    let body = serde_json::to_vec(&transfer).unwrap();
+
+    let (status, _) = request(addr, "POST", "/transfers", &body).await;
+    assert_eq!(status, 201);
+    let (status, error) = request(addr, "POST", "/transfers", &body).await;
+    assert_eq!(status, 403);
+    assert!(error["error"].is_string());
+
+    let (_, fetched) = request(
+        addr,
+        "GET",
+        &format!("/tokens/{}", token["id"].as_str().unwrap()),
+        b"",
+    )
+    .await;
+    assert_eq!(fetched["owner"], json!(recipient));
+
+    let (status, verified) = request(addr, "GET", "/verify", b"").await;
+    assert_eq!((status, verified), (200, json!({ "verified": true })));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn ledger_rejections() {
+    let recipient = PKey::generate_ed25519().unwrap();
+    let hex = to_hex(&recipient.raw_public_key().unwrap());
+
+    // locked tokens can't be transferred until released
+    let (addr, path) = spawn_server_with("locked", |chain, keypair| {
+        chain.push_signed("token", keypair).unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+        let lock = Lock::new(token, &recipient, Condition::Height(1_000)).unwrap();
+        chain
+            .push_signed(lock.to_bytes().unwrap(), keypair)
+            .unwrap();
+    })
+    .await;
+    let (_, tokens) = request(addr, "GET", "/tokens", b"").await;
+    let id = tokens[0]["id"].as_str().unwrap();
+
+    let transfer = serde_json::to_vec(&json!({ "token": id, "recipient": hex })).unwrap();
+    let (status, error) = request(addr, "POST", "/transfers", &transfer).await;
+    assert_eq!(status, 409);
+    assert!(error["error"].is_string());
+    std::fs::remove_file(path).unwrap();
+
+    // as are unsettled royalties on a chain requiring them
+    let (addr, path) = spawn_server_with("unsettled", |chain, keypair| {
+        chain.set_royalty_rule(RequireSettlement);
+        let metadata = TokenMetadata::new("art", vec![Royalty::new(&recipient, 500).unwrap()]);
+        let mint = BlockKind::Mint {
+            collection: None,
+            metadata: metadata.to_bytes().unwrap(),
+        };
+        chain
+            .push_signed(mint.to_bytes().unwrap(), keypair)
+            .unwrap();
+    })
+    .await;
+    let (_, tokens) = request(addr, "GET", "/tokens", b"").await;
+    let id = tokens[0]["id"].as_str().unwrap();
+
+    let transfer = serde_json::to_vec(&json!({ "token": id, "recipient": hex })).unwrap();
+    let (status, _) = request(addr, "POST", "/transfers", &transfer).await;
+    assert_eq!(status, 422);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn typed_payloads() {
+    let (addr, path) = spawn_server("typed").await;
+    let attacker = PKey::generate_ed25519().unwrap();
+
+    // the server would otherwise sign away its own key
+    let rotation = BlockKind::Rotation(Rotation::new(&attacker).unwrap());
+    let (status, error) = request(addr, "POST", "/blocks", &rotation.to_bytes().unwrap()).await;
+    assert_eq!(status, 400);
+    assert!(error["error"].is_string());
+
+    let (_, blocks) = request(addr, "GET", "/blocks", b"").await;
+    assert_eq!(blocks.as_array().unwrap().len(), 1);
+    let (status, _) = request(addr, "POST", "/blocks", b"token").await;
+    assert_eq!(status, 201);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn burned_tokens() {
+    let mut burned = None;
+    let (addr, path) = spawn_server_with("burned", |chain, keypair| {
+        chain.push_signed("burned", keypair).unwrap();
+        let token = chain.token_id(&chain.tip().hash).unwrap();
+        chain.burn(&token, keypair).unwrap();
+        burned = Some(token);
+        chain.push_signed("kept", keypair).unwrap();
+    })
+    .await;
+
+    let (_, tokens) = request(addr, "GET", "/tokens", b"").await;
+    let tokens = tokens.as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    let (_, blocks) = request(addr, "GET", "/blocks", b"").await;
+    assert_eq!(tokens[0]["mint"], blocks[3]["hash"]);
+
+    let burned = burned.unwrap();
+    let (status, _) = request(addr, "GET", &format!("/tokens/{}", burned), b"").await;
+    assert_eq!(status, 404);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,68 @@
+use onft::net::{AsyncNode, Node};
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Spawns a new listening node on localhost, returning it and its address
+async fn spawn_node() -> (AsyncNode, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let node = AsyncNode::default();
+    tokio::spawn({
+        let node = node.clone();
+        async move { node.serve(listener).await }
+    });
+    (node, addr)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_clients() {
+    let (server, addr) = spawn_node().await;
+    let mut events = server.subscribe();
+
+    let clients: Vec<_> = (0..8)
+        .map(|_| {
+            tokio::spawn(async move {
+                let client = AsyncNode::default();
+                client.sync(addr).await.unwrap()
+            })
+        })
+        .collect();
+    for client in clients {
+        assert!(client.await.unwrap());
+    }
+
+    let block = server.push_data("Hello, world!").await.unwrap();
+    assert_eq!(events.recv().await.unwrap().hash, block.hash);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn appended_events() {
+    let (server, addr) = spawn_node().await;
+    let mut events = server.subscribe();
+
+    let client = AsyncNode::default();
+    let first = client.push_data("first").await.unwrap();
+    let second = client.push_data("second").await.unwrap();
+    assert!(client.sync(addr).await.unwrap());
+
+    assert_eq!(events.recv().await.unwrap().hash, first.hash);
+    assert_eq!(events.recv().await.unwrap().hash, second.hash);
+    assert!(server.chain().await.verify().unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn blocking_interop() {
+    let (server, addr) = spawn_node().await;
+    server.push_data("Hello, world!").await.unwrap();
+
+    let client = tokio::task::spawn_blocking(move || {
+        let client = Node::default();
+        assert!(client.sync(addr).unwrap());
+        client
+    })
+    .await
+    .unwrap();
+
+    let tip = server.chain().await.tip().hash.clone();
+    assert_eq!(client.chain().tip().hash, tip);
+}